@@ -0,0 +1,330 @@
+//! Generates the storage getters, call builders and event decoders of every module from the
+//! metadata of `node_template_runtime`.
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use serde_json::Value;
+
+/// Type names the generated code may refer to, defined in `src/types.rs` or built in.
+const KNOWN_TYPES: &[&str] = &[
+	"AccountId", "AccountIndex", "Address", "AuthorityId", "Balance", "BalanceLock", "BalanceOf",
+	"BlockNumber", "Call", "Compact", "Digest", "Event", "EventRecord", "Hash", "Index", "Key",
+	"KeyValue", "Moment", "Option", "Proposal", "SessionKey", "Vec", "VestingSchedule", "Weight",
+	"bool", "u8", "u16", "u32", "u64", "u128",
+];
+
+const KEYWORDS: &[&str] = &[
+	"as", "box", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "fn",
+	"for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+	"return", "self", "static", "struct", "super", "trait", "type", "unsafe", "use", "where", "while",
+];
+
+fn main() {
+	println!("cargo:rerun-if-env-changed=NODE_TEMPLATE_SPEC_VERSION");
+	let spec_version = node_template_runtime::VERSION.spec_version;
+	if let Ok(expected) = env::var("NODE_TEMPLATE_SPEC_VERSION") {
+		let expected = expected.parse::<u32>().expect("NODE_TEMPLATE_SPEC_VERSION must be a number");
+		if expected != spec_version {
+			panic!(
+				"The client is generated from a runtime with spec_version {}, \
+				but NODE_TEMPLATE_SPEC_VERSION requires {}",
+				spec_version, expected,
+			);
+		}
+	}
+
+	let metadata = serde_json::to_value(&node_template_runtime::Runtime::metadata())
+		.expect("Runtime metadata serializes to JSON");
+	// `RuntimeMetadataPrefixed` serializes as `[magic, { "V<version>": { "modules": [..] } }]`.
+	let modules = metadata.get(1)
+		.and_then(Value::as_object)
+		.and_then(|versioned| versioned.values().next())
+		.and_then(|metadata| metadata.get("modules"))
+		.and_then(Value::as_array)
+		.expect("Unexpected runtime metadata layout");
+
+	let mut out = String::new();
+	writeln!(out, "/// `spec_version` of the runtime the client was generated from.").unwrap();
+	writeln!(out, "pub const SPEC_VERSION: u32 = {};\n", spec_version).unwrap();
+
+	// Calls and events are indexed by the position of their module among those having any.
+	let mut call_index = 0u8;
+	let mut events = Vec::new();
+	for module in modules {
+		let name = string(module, "name");
+		let ident = identifier(&name.to_lowercase());
+		writeln!(out, "/// Storage, calls and events of the `{}` module.", name).unwrap();
+		writeln!(out, "pub mod {} {{", ident).unwrap();
+
+		if let Some(entries) = module.get("storage").and_then(Value::as_array) {
+			storage(&mut out, &name, &string(module, "prefix"), entries);
+		}
+		if let Some(calls) = module.get("calls").and_then(Value::as_array) {
+			self::calls(&mut out, &name, call_index, calls);
+			call_index += 1;
+		}
+		if let Some(items) = module.get("event").and_then(Value::as_array) {
+			let decodable = self::events(&mut out, &name, items);
+			events.push((ident.clone(), decodable));
+		}
+		writeln!(out, "}}\n").unwrap();
+	}
+	outer_event(&mut out, &events);
+
+	let path = Path::new(&env::var("OUT_DIR").expect("OUT_DIR is set by cargo")).join("runtime.rs");
+	fs::write(path, out).expect("Unable to write the generated client");
+}
+
+fn storage(out: &mut String, module: &str, prefix: &str, entries: &[Value]) {
+	writeln!(out, "\t/// Storage of the `{}` module, read at the best block unless `at` is given.", module).unwrap();
+	writeln!(out, "\tpub mod storage {{").unwrap();
+	writeln!(out, "\t\t#[allow(unused_imports)]\n\t\tuse crate::types::*;").unwrap();
+	writeln!(out, "\t\t#[allow(unused_imports)]\n\t\tuse crate::{{Client, Hasher, Result, storage_key}};").unwrap();
+
+	for entry in entries {
+		let name = string(entry, "name");
+		let function = identifier(&snake_case(&name));
+		let ty = &entry["ty"];
+		let (key, value, hasher) = if let Some(value) = ty.get("Plain").and_then(Value::as_str) {
+			(None, value.to_string(), "Twox128".to_string())
+		} else if let Some(map) = ty.get("Map") {
+			let hasher = map.get("hasher").and_then(Value::as_str).unwrap_or("Blake2_256");
+			(Some(string(map, "key")), string(map, "value"), hasher.to_string())
+		} else {
+			writeln!(out, "\n\t\t// `{}` is not a plain value or a map, and is not supported.", name).unwrap();
+			continue;
+		};
+		let key = match key.map(|key| normalize(&key)) {
+			Some(None) => {
+				writeln!(out, "\n\t\t// The key of `{}` has an unsupported type.", name).unwrap();
+				continue;
+			},
+			key => key.and_then(|key| key),
+		};
+
+		// The key of an entry.
+		writeln!(out).unwrap();
+		writeln!(out, "\t\t/// Storage key of `{}`.", name).unwrap();
+		match &key {
+			Some(key) => {
+				writeln!(out, "\t\tpub fn {}_key(key: &{}) -> Vec<u8> {{", function, key).unwrap();
+				writeln!(out, "\t\t\tlet mut data = b\"{} {}\".to_vec();", prefix, name).unwrap();
+				writeln!(out, "\t\t\tkey.encode_to(&mut data);").unwrap();
+				writeln!(out, "\t\t\tstorage_key(Hasher::{}, &data)", hasher).unwrap();
+			},
+			None => {
+				writeln!(out, "\t\tpub fn {}_key() -> Vec<u8> {{", function).unwrap();
+				writeln!(out, "\t\t\tstorage_key(Hasher::{}, b\"{} {}\")", hasher, prefix, name).unwrap();
+			},
+		}
+		writeln!(out, "\t\t}}\n").unwrap();
+
+		// The getter of an entry.
+		docs(out, "\t\t", entry);
+		let (parameter, argument) = match &key {
+			Some(key) => (format!("key: &{}, ", key), "key"),
+			None => (String::new(), ""),
+		};
+		let optional = entry.get("modifier").and_then(Value::as_str) == Some("Optional");
+		match normalize(&value) {
+			Some(value) if optional => {
+				writeln!(out, "\t\tpub fn {}(client: &Client, {}at: Option<Hash>) -> Result<Option<{}>> {{", function, parameter, value).unwrap();
+				writeln!(out, "\t\t\tclient.fetch(&{}_key({}), at)", function, argument).unwrap();
+			},
+			Some(value) => {
+				let default = entry.get("default").cloned().unwrap_or_default();
+				let default = serde_json::from_value::<Vec<u8>>(default).unwrap_or_default();
+				writeln!(out, "\t\tpub fn {}(client: &Client, {}at: Option<Hash>) -> Result<{}> {{", function, parameter, value).unwrap();
+				writeln!(out, "\t\t\tclient.fetch_or_default(&{}_key({}), at, &{:?})", function, argument, default).unwrap();
+			},
+			None => {
+				writeln!(out, "\t\t///\n\t\t/// The value has an unsupported type, `{}`, and is returned encoded.", value).unwrap();
+				writeln!(out, "\t\tpub fn {}(client: &Client, {}at: Option<Hash>) -> Result<Option<Vec<u8>>> {{", function, parameter).unwrap();
+				writeln!(out, "\t\t\tclient.storage(&{}_key({}), at)", function, argument).unwrap();
+			},
+		}
+		writeln!(out, "\t\t}}").unwrap();
+	}
+	writeln!(out, "\t}}\n").unwrap();
+}
+
+fn calls(out: &mut String, module: &str, module_index: u8, calls: &[Value]) {
+	writeln!(out, "\t/// Builders of the calls of the `{}` module.", module).unwrap();
+	writeln!(out, "\tpub mod calls {{").unwrap();
+	writeln!(out, "\t\t#[allow(unused_imports)]\n\t\tuse crate::types::*;").unwrap();
+
+	'calls: for (call_index, call) in calls.iter().enumerate() {
+		let name = string(call, "name");
+		let mut parameters = Vec::new();
+		let mut encode = Vec::new();
+		for argument in call.get("arguments").and_then(Value::as_array).map(|a| &a[..]).unwrap_or_default() {
+			let argument_name = identifier(&string(argument, "name"));
+			let ty = match normalize(&string(argument, "ty")) {
+				Some(ty) => ty,
+				None => {
+					writeln!(out, "\n\t\t// `{}` has arguments of unsupported types.", name).unwrap();
+					continue 'calls;
+				},
+			};
+			// Compact arguments are taken as their inner type.
+			match strip_wrapper(&ty, "Compact") {
+				Some(inner) => {
+					parameters.push(format!("{}: {}", argument_name, inner));
+					encode.push(format!("Compact({}).encode_to(&mut encoded);", argument_name));
+				},
+				None => {
+					parameters.push(format!("{}: {}", argument_name, ty));
+					encode.push(format!("{}.encode_to(&mut encoded);", argument_name));
+				},
+			}
+		}
+
+		writeln!(out).unwrap();
+		docs(out, "\t\t", call);
+		writeln!(out, "\t\tpub fn {}({}) -> Call {{", identifier(&name), parameters.join(", ")).unwrap();
+		let mutable = if encode.is_empty() { "" } else { "mut " };
+		writeln!(out, "\t\t\tlet {}encoded = vec![{}, {}];", mutable, module_index, call_index).unwrap();
+		for line in encode {
+			writeln!(out, "\t\t\t{}", line).unwrap();
+		}
+		writeln!(out, "\t\t\tcrate::decode_call(&encoded)").unwrap();
+		writeln!(out, "\t\t}}").unwrap();
+	}
+	writeln!(out, "\t}}\n").unwrap();
+}
+
+/// Generate the `Event` enum of a module, returning whether it could be.
+fn events(out: &mut String, module: &str, events: &[Value]) -> bool {
+	let mut variants = String::new();
+	for event in events {
+		let name = string(event, "name");
+		let mut fields = Vec::new();
+		for ty in event.get("arguments").and_then(Value::as_array).map(|a| &a[..]).unwrap_or_default() {
+			match normalize(ty.as_str().unwrap_or_default()) {
+				Some(ty) => fields.push(ty),
+				None => {
+					writeln!(out, "\t// The events of the `{}` module have arguments of unsupported types.", module).unwrap();
+					return false;
+				},
+			}
+		}
+		docs(&mut variants, "\t\t", event);
+		match fields.is_empty() {
+			true => writeln!(variants, "\t\t{},", name).unwrap(),
+			false => writeln!(variants, "\t\t{}({}),", name, fields.join(", ")).unwrap(),
+		}
+	}
+
+	writeln!(out, "\t#[allow(unused_imports)]\n\tuse crate::types::*;\n").unwrap();
+	writeln!(out, "\t/// Events of the `{}` module.", module).unwrap();
+	writeln!(out, "\t#[derive(Debug, Clone, PartialEq, Decode)]").unwrap();
+	writeln!(out, "\tpub enum Event {{\n{}\t}}", variants).unwrap();
+	true
+}
+
+fn outer_event(out: &mut String, modules: &[(String, bool)]) {
+	writeln!(out, "/// An event of any module, as found in `system::storage::events`.").unwrap();
+	writeln!(out, "///").unwrap();
+	writeln!(out, "/// Decoding fails for the events of modules whose `Event` could not be generated.").unwrap();
+	writeln!(out, "#[derive(Debug, Clone, PartialEq)]").unwrap();
+	writeln!(out, "pub enum Event {{").unwrap();
+	for (module, _) in modules.iter().filter(|(_, decodable)| *decodable) {
+		writeln!(out, "\t/// An event of the `{}` module.", module).unwrap();
+		writeln!(out, "\t{}({}::Event),", camel_case(module), module).unwrap();
+	}
+	writeln!(out, "}}\n").unwrap();
+
+	writeln!(out, "impl parity_codec::Decode for Event {{").unwrap();
+	writeln!(out, "\tfn decode<I: parity_codec::Input>(input: &mut I) -> Option<Self> {{").unwrap();
+	writeln!(out, "\t\tmatch input.read_byte()? {{").unwrap();
+	for (index, (module, decodable)) in modules.iter().enumerate() {
+		if *decodable {
+			writeln!(out, "\t\t\t{} => Some(Event::{}(parity_codec::Decode::decode(input)?)),", index, camel_case(module)).unwrap();
+		}
+	}
+	writeln!(out, "\t\t\t_ => None,\n\t\t}}\n\t}}\n}}").unwrap();
+}
+
+/// Map a type name of the metadata to the names of `src/types.rs`, or `None` if it is not
+/// supported.
+fn normalize(ty: &str) -> Option<String> {
+	let mut ty = ty.replace("<T::Lookup as StaticLookup>::Source", "Address");
+	// `<T as Trait>::Call` and the like.
+	while let Some(start) = ty.find("<T as ") {
+		let end = ty[start..].find(">::")? + start;
+		ty.replace_range(start..end + 3, "");
+	}
+	let mut ty = ty.replace("T::", "").replace("<T, I>", "").replace("<T>", "");
+	// Boxed values are encoded as the value itself.
+	if let Some(inner) = strip_wrapper(&ty, "Box") {
+		ty = inner.to_string();
+	}
+
+	// Check every name is known, referring to the outer event rather than that of the module.
+	let mut normalized = String::new();
+	let mut name = String::new();
+	for c in ty.chars().chain(Some(' ')) {
+		if c.is_alphanumeric() || c == '_' {
+			name.push(c);
+			continue;
+		}
+		if !name.is_empty() {
+			if !KNOWN_TYPES.contains(&name.as_str()) {
+				return None;
+			}
+			normalized.push_str(if name == "Event" { "crate::Event" } else { &name });
+			name.clear();
+		}
+		normalized.push(c);
+	}
+	Some(normalized.trim_end().to_string())
+}
+
+/// The type wrapped in `wrapper<..>`, if `ty` is one.
+fn strip_wrapper<'a>(ty: &'a str, wrapper: &str) -> Option<&'a str> {
+	let inner = ty.trim();
+	if inner.starts_with(wrapper) && inner[wrapper.len()..].starts_with('<') && inner.ends_with('>') {
+		Some(inner[wrapper.len() + 1..inner.len() - 1].trim())
+	} else {
+		None
+	}
+}
+
+fn docs(out: &mut String, indent: &str, item: &Value) {
+	for line in item.get("documentation").and_then(Value::as_array).map(|d| &d[..]).unwrap_or_default() {
+		writeln!(out, "{}///{}", indent, line.as_str().unwrap_or_default()).unwrap();
+	}
+}
+
+fn string(value: &Value, field: &str) -> String {
+	value.get(field).and_then(Value::as_str).unwrap_or_default().to_string()
+}
+
+fn identifier(name: &str) -> String {
+	match KEYWORDS.contains(&name) {
+		true => format!("{}_", name),
+		false => name.to_string(),
+	}
+}
+
+fn snake_case(name: &str) -> String {
+	let mut snake = String::new();
+	for (i, c) in name.chars().enumerate() {
+		if c.is_uppercase() && i > 0 {
+			snake.push('_');
+		}
+		snake.extend(c.to_lowercase());
+	}
+	snake
+}
+
+fn camel_case(name: &str) -> String {
+	name.split('_')
+		.map(|part| {
+			let mut chars = part.chars();
+			chars.next().map(|first| first.to_uppercase().chain(chars).collect::<String>()).unwrap_or_default()
+		})
+		.collect()
+}
@@ -0,0 +1,21 @@
+//! Errors of the client.
+
+use node_template_signer as signer;
+
+error_chain! {
+	links {
+		Rpc(signer::Error, signer::ErrorKind) #[doc="RPC error"];
+	}
+	errors {
+		/// The node runs another runtime than the one the client was generated from.
+		SpecVersionMismatch(expected: u32, found: u32) {
+			description("Runtime spec_version mismatch"),
+			display("The client was generated for spec_version {}, but the node runs {}", expected, found),
+		}
+		/// A value returned by the node could not be decoded.
+		Decode(what: String) {
+			description("Decoding error"),
+			display("Unable to decode {}", what),
+		}
+	}
+}
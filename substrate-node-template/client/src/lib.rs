@@ -0,0 +1,161 @@
+//! Typed access to a node running the node template runtime.
+//!
+//! The modules of this crate are generated at build time from the metadata of
+//! `node_template_runtime`, one per module of `construct_runtime!`:
+//!
+//! - `<module>::storage` reads every storage entry, decoded to its runtime type.
+//! - `<module>::calls` builds every call as a `Call` of the runtime, ready to be signed with
+//!   `node_template_signer::sign`.
+//! - `<module>::Event` decodes the events of the module, and `Event` those of any module.
+//!
+//! Items whose types are not listed in `types` are left out, or read as raw bytes for storage.
+//! `Client::connect` refuses nodes whose runtime has another `spec_version` than the one the
+//! crate was built from. Setting `NODE_TEMPLATE_SPEC_VERSION` when building makes the build
+//! fail unless the runtime has that `spec_version`.
+//!
+//! ```no_run
+//! use node_template_client::{Client, balances};
+//! use primitives::{Pair, sr25519};
+//!
+//! let client = Client::connect("ws://127.0.0.1:9944").unwrap();
+//! let alice = sr25519::Pair::from_string("//Alice", None).unwrap().public();
+//! let free = balances::storage::free_balance(&client, &alice, None).unwrap();
+//! let transfer = balances::calls::transfer(alice.into(), free / 2);
+//! ```
+
+#[macro_use]
+extern crate error_chain;
+
+pub mod error;
+pub mod types;
+
+pub use error::{Error, ErrorKind, Result};
+
+use parity_codec::Decode;
+use primitives::{blake2_128, blake2_256, twox_128, twox_256, twox_64};
+use primitives::hexdisplay::HexDisplay;
+use serde_json::{json, Value};
+use node_template_runtime::{Call, Hash};
+
+include!(concat!(env!("OUT_DIR"), "/runtime.rs"));
+
+/// A connection to a node, checked to run the runtime the crate was generated from.
+pub struct Client {
+	rpc: node_template_signer::Client,
+}
+
+impl Client {
+	/// Connect to the WebSocket RPC server of the node at `url`, e.g. `ws://127.0.0.1:9944`.
+	pub fn connect(url: &str) -> Result<Self> {
+		let client = Client { rpc: node_template_signer::Client::connect(url)? };
+		let found = client.spec_version()?;
+		if found != SPEC_VERSION {
+			bail!(ErrorKind::SpecVersionMismatch(SPEC_VERSION, found));
+		}
+		Ok(client)
+	}
+
+	/// The underlying RPC client, e.g. to submit transactions built with `calls`.
+	pub fn rpc(&self) -> &node_template_signer::Client {
+		&self.rpc
+	}
+
+	/// `spec_version` of the runtime of the best block of the node.
+	pub fn spec_version(&self) -> Result<u32> {
+		let version = self.rpc.request("state_getRuntimeVersion", json!([]))?;
+		version.get("specVersion")
+			.and_then(Value::as_u64)
+			.map(|version| version as u32)
+			.ok_or_else(|| ErrorKind::Decode("runtime version".into()).into())
+	}
+
+	/// Raw value of a storage key.
+	pub fn storage(&self, key: &[u8], at: Option<Hash>) -> Result<Option<Vec<u8>>> {
+		let key = format!("0x{}", HexDisplay::from(&key));
+		let value = self.rpc.request("state_getStorage", json!([key, at]))?;
+		let value = serde_json::from_value::<Option<primitives::Bytes>>(value)
+			.map_err(|_| ErrorKind::Decode("storage value".into()))?;
+		Ok(value.map(|bytes| bytes.0))
+	}
+
+	/// Value of a storage key, decoded as `V`.
+	pub fn fetch<V: Decode>(&self, key: &[u8], at: Option<Hash>) -> Result<Option<V>> {
+		match self.storage(key, at)? {
+			Some(value) => V::decode(&mut &value[..])
+				.map(Some)
+				.ok_or_else(|| ErrorKind::Decode("storage value".into()).into()),
+			None => Ok(None),
+		}
+	}
+
+	/// Value of a storage key decoded as `V`, or the encoded `default` if it is not set.
+	pub fn fetch_or_default<V: Decode>(&self, key: &[u8], at: Option<Hash>, default: &[u8]) -> Result<V> {
+		match self.fetch(key, at)? {
+			Some(value) => Ok(value),
+			None => V::decode(&mut &default[..]).ok_or_else(|| ErrorKind::Decode("storage default".into()).into()),
+		}
+	}
+}
+
+/// Hashers of storage map keys.
+// Not every hasher is used by the runtime.
+#[allow(dead_code)]
+enum Hasher {
+	Blake2_128,
+	Blake2_256,
+	Twox128,
+	Twox256,
+	Twox64Concat,
+}
+
+/// Storage key of an entry, given its prefix followed by the encoded map key, if any.
+fn storage_key(hasher: Hasher, data: &[u8]) -> Vec<u8> {
+	match hasher {
+		Hasher::Blake2_128 => blake2_128(data).to_vec(),
+		Hasher::Blake2_256 => blake2_256(data).to_vec(),
+		Hasher::Twox128 => twox_128(data).to_vec(),
+		Hasher::Twox256 => twox_256(data).to_vec(),
+		Hasher::Twox64Concat => twox_64(data).iter().chain(data).cloned().collect(),
+	}
+}
+
+/// Decode a call built from the metadata of the runtime.
+fn decode_call(encoded: &[u8]) -> Call {
+	Call::decode(&mut &encoded[..]).expect("calls are built from the metadata of the runtime; qed")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use parity_codec::Encode;
+	use primitives::{Pair, sr25519};
+	use node_template_runtime::BalancesCall;
+
+	#[test]
+	fn calls_match_the_runtime() {
+		let bob = sr25519::Pair::from_string("//Bob", None).unwrap().public();
+		assert_eq!(
+			balances::calls::transfer(bob.clone().into(), 1_000),
+			Call::Balances(BalancesCall::transfer(bob.into(), 1_000)),
+		);
+	}
+
+	#[test]
+	fn events_match_the_runtime() {
+		let bob = sr25519::Pair::from_string("//Bob", None).unwrap().public();
+		let event = node_template_runtime::Event::balances(srml_balances::RawEvent::NewAccount(bob.clone(), 500));
+		assert_eq!(
+			Event::decode(&mut &event.encode()[..]),
+			Some(Event::Balances(balances::Event::NewAccount(bob, 500))),
+		);
+	}
+
+	#[test]
+	fn storage_keys_match_the_runtime() {
+		let alice = sr25519::Pair::from_string("//Alice", None).unwrap().public();
+		assert_eq!(system::storage::events_key(), twox_128(b"System Events").to_vec());
+		let mut data = b"System AccountNonce".to_vec();
+		alice.encode_to(&mut data);
+		assert_eq!(system::storage::account_nonce_key(&alice), blake2_256(&data).to_vec());
+	}
+}
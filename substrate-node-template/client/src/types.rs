@@ -0,0 +1,30 @@
+//! Runtime types the generated code refers to, under the names used by the metadata.
+
+pub use parity_codec::{Compact, Decode, Encode};
+pub use node_template_runtime::{AccountId, Address, AuthorityId, Balance, BlockNumber, Call, Hash, Weight};
+use node_template_runtime::Runtime;
+
+/// Nonce of an account.
+pub type Index = node_template_runtime::Nonce;
+/// Short index of an account.
+pub type AccountIndex = <Runtime as srml_indices::Trait>::AccountIndex;
+/// Balance type of the `balances` module, as used by other modules.
+pub type BalanceOf = Balance;
+/// Timestamp of a block.
+pub type Moment = <Runtime as srml_timestamp::Trait>::Moment;
+/// Key of an authority.
+pub type SessionKey = AuthorityId;
+/// A call dispatched on behalf of another origin.
+pub type Proposal = Call;
+/// Digest of a block header.
+pub type Digest = <Runtime as srml_system::Trait>::Digest;
+/// An event along with the phase of the block it was emitted in.
+pub type EventRecord<E> = srml_system::EventRecord<E>;
+/// A lock on the free balance of an account.
+pub type BalanceLock<B, N> = srml_balances::BalanceLock<B, N>;
+/// Vesting of the balance of an account.
+pub type VestingSchedule<B> = srml_balances::VestingSchedule<B>;
+/// A raw storage key.
+pub type Key = Vec<u8>;
+/// A raw storage key and value.
+pub type KeyValue = (Vec<u8>, Vec<u8>);
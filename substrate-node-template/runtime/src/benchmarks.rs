@@ -0,0 +1,64 @@
+//! Benchmarks of the runtime's calls, driven by the `benchmark` node subcommand.
+//!
+//! Each benchmark declares its components, the inputs its cost may depend on, together with
+//! the range they are measured over. `setup` prepares storage for a given value of every
+//! component and returns the signed call to time, which `dispatch` then executes.
+//!
+//! Only compiled with the `runtime-benchmarks` feature, since `setup` mints funds out of thin air.
+
+use rstd::prelude::*;
+use parity_codec::{Encode, Decode};
+use support::dispatch::Dispatchable;
+use support::traits::Currency;
+use runtime_primitives::traits::StaticLookup;
+use crate::{AccountId, Balances, Call, Indices, Origin, template};
+
+/// Name, lowest and highest value of each component of a benchmark.
+pub type Components = Vec<(Vec<u8>, u32, u32)>;
+
+/// The components of the benchmark of `call` in `module`.
+pub fn components(module: &[u8], call: &[u8]) -> Result<Components, &'static str> {
+	match (module, call) {
+		// The stored value.
+		(b"template", b"do_something") => Ok(vec![(b"v".to_vec(), 0, 1_000)]),
+		// Whether the recipient already exists.
+		(b"balances", b"transfer") => Ok(vec![(b"e".to_vec(), 0, 1)]),
+		_ => Err("Unknown benchmark"),
+	}
+}
+
+/// Prepare storage for the benchmark of `call` in `module`, returning the encoded caller and call.
+pub fn setup(module: &[u8], call: &[u8], components: &[(Vec<u8>, u32)]) -> Result<Vec<u8>, &'static str> {
+	let component = |name: &[u8]| components.iter()
+		.find(|(n, _)| &n[..] == name)
+		.map(|(_, value)| *value)
+		.unwrap_or(0);
+	let caller = account(0);
+
+	let call = match (module, call) {
+		(b"template", b"do_something") => Call::TemplateModule(template::Call::do_something(component(b"v"))),
+		(b"balances", b"transfer") => {
+			let _ = Balances::deposit_creating(&caller, 1 << 60);
+			let recipient = account(1);
+			if component(b"e") == 1 {
+				let _ = Balances::deposit_creating(&recipient, 1 << 20);
+			}
+			Call::Balances(balances::Call::transfer(Indices::unlookup(recipient), 1 << 30))
+		},
+		_ => return Err("Unknown benchmark"),
+	};
+
+	Ok((caller, call).encode())
+}
+
+/// Dispatch a call returned by `setup`.
+pub fn dispatch(encoded: &[u8]) -> Result<(), &'static str> {
+	let (caller, call) = <(AccountId, Call)>::decode(&mut &encoded[..]).ok_or("Invalid benchmark call")?;
+	call.dispatch(Origin::signed(caller))
+}
+
+fn account(seed: u32) -> AccountId {
+	let mut raw = [0u8; 32];
+	raw[..4].copy_from_slice(&seed.encode());
+	AccountId::from_raw(raw)
+}
@@ -0,0 +1,196 @@
+/// A runtime module changing the target block time at epoch boundaries.
+///
+/// Aura derives the slot duration from the minimum period of the timestamp module, as twice
+/// that period. Root schedules a new slot duration with `set_block_time`, which is applied at
+/// the end of the last block of the current epoch of `EpochLength` blocks. That block carries
+/// a `SlotDurationChange` log in its header, so nodes, light clients included, switch to the
+/// new slot duration from the next block on.
+
+#[cfg(feature = "std")]
+use serde_derive::Serialize;
+use parity_codec::{Encode, Decode};
+use runtime_primitives::traits::{As, One, Zero};
+use support::{decl_module, decl_storage, decl_event, StorageValue, dispatch::Result};
+use system::ensure_root;
+
+/// The module's configuration trait.
+pub trait Trait: timestamp::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+
+	/// The overarching log type.
+	type Log: From<Log<Self>> + Into<system::DigestItemOf<Self>>;
+}
+
+/// The log of this module.
+pub type Log<T> = RawLog<<T as timestamp::Trait>::Moment>;
+
+/// A header digest item of this module.
+#[cfg_attr(feature = "std", derive(Serialize, Debug))]
+#[derive(Encode, Decode, PartialEq, Eq, Clone)]
+pub enum RawLog<Moment> {
+	/// Blocks after the one with this log use the given slot duration.
+	SlotDurationChange(Moment),
+}
+
+impl<Moment: Clone> RawLog<Moment> {
+	/// The new slot duration, if this log changes it.
+	pub fn as_slot_duration_change(&self) -> Option<Moment> {
+		match self {
+			RawLog::SlotDurationChange(duration) => Some(duration.clone()),
+		}
+	}
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as BlockTime {
+		/// Number of blocks in an epoch. Block time changes apply from the first block of an epoch.
+		pub EpochLength get(epoch_length) config(): T::BlockNumber;
+		/// A scheduled slot duration, with the block at the end of which it is applied.
+		pub PendingChange get(pending_change): Option<(T::BlockNumber, T::Moment)>;
+	}
+}
+
+decl_module! {
+	/// The module declaration.
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event<T>() = default;
+
+		/// Use `slot_duration` as the target block time from the next epoch on. The slot
+		/// duration is twice the minimum period between timestamps, so it must be even.
+		fn set_block_time(origin, slot_duration: T::Moment) -> Result {
+			ensure_root(origin)?;
+			let two = T::Moment::sa(2);
+			if slot_duration.is_zero() || !(slot_duration.clone() % two).is_zero() {
+				return Err("slot duration must be even and positive");
+			}
+			let epoch_length = Self::epoch_length().max(One::one());
+			let number = <system::Module<T>>::block_number();
+			let end_of_epoch = (number / epoch_length + One::one()) * epoch_length - One::one();
+			<PendingChange<T>>::put((end_of_epoch, slot_duration.clone()));
+			Self::deposit_event(RawEvent::BlockTimeScheduled(slot_duration, end_of_epoch + One::one()));
+			Ok(())
+		}
+
+		fn on_finalize(n: T::BlockNumber) {
+			if let Some((at, slot_duration)) = Self::pending_change() {
+				if at <= n {
+					<PendingChange<T>>::kill();
+					<timestamp::MinimumPeriod<T>>::put(slot_duration.clone() / T::Moment::sa(2));
+					Self::deposit_log(RawLog::SlotDurationChange(slot_duration.clone()));
+					Self::deposit_event(RawEvent::BlockTimeChanged(slot_duration));
+				}
+			}
+		}
+	}
+}
+
+decl_event!(
+	pub enum Event<T> where BlockNumber = <T as system::Trait>::BlockNumber, Moment = <T as timestamp::Trait>::Moment {
+		/// A slot duration was scheduled, to be used from the given block on.
+		BlockTimeScheduled(Moment, BlockNumber),
+		/// The slot duration changed from the next block on.
+		BlockTimeChanged(Moment),
+	}
+);
+
+impl<T: Trait> Module<T> {
+	fn deposit_log(log: Log<T>) {
+		let log: <T as Trait>::Log = log.into();
+		<system::Module<T>>::deposit_log(log.into());
+	}
+}
+
+/// tests for this module
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use runtime_io::with_externalities;
+	use primitives::{H256, Blake2Hasher};
+	use support::{impl_outer_origin, assert_ok, assert_noop};
+	use runtime_primitives::{
+		BuildStorage,
+		traits::{BlakeTwo256, IdentityLookup, OnFinalize},
+		testing::{Digest, DigestItem, Header}
+	};
+
+	impl_outer_origin! {
+		pub enum Origin for Test {}
+	}
+
+	impl From<RawLog<u64>> for DigestItem {
+		fn from(log: RawLog<u64>) -> Self {
+			DigestItem::Other(log.encode())
+		}
+	}
+
+	#[derive(Clone, Eq, PartialEq)]
+	pub struct Test;
+	impl system::Trait for Test {
+		type Origin = Origin;
+		type Index = u64;
+		type BlockNumber = u64;
+		type Hash = H256;
+		type Hashing = BlakeTwo256;
+		type Digest = Digest;
+		type AccountId = u64;
+		type Lookup = IdentityLookup<Self::AccountId>;
+		type Header = Header;
+		type Event = ();
+		type Log = DigestItem;
+	}
+	impl timestamp::Trait for Test {
+		type Moment = u64;
+		type OnTimestampSet = ();
+	}
+	impl Trait for Test {
+		type Event = ();
+		type Log = DigestItem;
+	}
+	type BlockTime = Module<Test>;
+	type System = system::Module<Test>;
+	type Timestamp = timestamp::Module<Test>;
+
+	fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+		let mut t = system::GenesisConfig::<Test>::default().build_storage().unwrap().0;
+		t.extend(timestamp::GenesisConfig::<Test> {
+			minimum_period: 5,
+		}.build_storage().unwrap().0);
+		t.extend(GenesisConfig::<Test> {
+			epoch_length: 10,
+		}.build_storage().unwrap().0);
+		t.into()
+	}
+
+	#[test]
+	fn block_time_changes_at_the_end_of_the_epoch() {
+		with_externalities(&mut new_test_ext(), || {
+			System::set_block_number(13);
+			assert_ok!(BlockTime::set_block_time(Origin::ROOT, 6));
+			assert_eq!(BlockTime::pending_change(), Some((19, 6)));
+
+			BlockTime::on_finalize(18);
+			assert_eq!(Timestamp::minimum_period(), 5);
+
+			BlockTime::on_finalize(19);
+			assert_eq!(Timestamp::minimum_period(), 3);
+			assert_eq!(BlockTime::pending_change(), None);
+			assert!(System::digest().logs.contains(&DigestItem::Other(RawLog::SlotDurationChange(6u64).encode())));
+		});
+	}
+
+	#[test]
+	fn only_root_sets_an_even_block_time() {
+		with_externalities(&mut new_test_ext(), || {
+			assert!(BlockTime::set_block_time(Origin::signed(1), 6).is_err());
+			assert_noop!(BlockTime::set_block_time(Origin::ROOT, 7), "slot duration must be even and positive");
+			assert_noop!(BlockTime::set_block_time(Origin::ROOT, 0), "slot duration must be even and positive");
+		});
+	}
+
+	#[test]
+	fn logs_expose_the_new_slot_duration() {
+		assert_eq!(RawLog::SlotDurationChange(8u64).as_slot_duration_change(), Some(8));
+	}
+}
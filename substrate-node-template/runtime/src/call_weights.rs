@@ -0,0 +1,108 @@
+//! Weights of the runtime's dispatchable calls.
+//!
+//! One unit of weight is roughly one nanosecond of execution on reference hardware. The
+//! weights of a call can be measured with the `benchmark` node subcommand, whose output
+//! replaces the call's entry below.
+
+use crate::weights::Weight;
+
+/// Weight of calls without a dedicated entry below.
+pub const DEFAULT: Weight = 10_000;
+
+/// Calls of `Timestamp`.
+pub mod timestamp {
+	use super::Weight;
+
+	/// `Timestamp::set`.
+	pub const SET: Weight = 10_000;
+}
+
+/// Calls of `Consensus`.
+pub mod consensus {
+	use super::Weight;
+
+	/// `Consensus::set_code`, dominated by writing the new runtime.
+	pub const SET_CODE: Weight = 500_000_000;
+}
+
+/// Calls of `Balances`.
+pub mod balances {
+	use super::Weight;
+
+	/// `Balances::transfer`.
+	pub const TRANSFER: Weight = 200_000;
+	/// `Balances::set_balance`.
+	pub const SET_BALANCE: Weight = 100_000;
+}
+
+/// Calls of `Sudo`.
+pub mod sudo {
+	use super::Weight;
+
+	/// `Sudo::sudo`, on top of the weight of the proposal it dispatches.
+	pub const SUDO: Weight = 20_000;
+	/// `Sudo::set_key`.
+	pub const SET_KEY: Weight = 20_000;
+}
+
+/// Calls of `BlockTime`.
+pub mod block_time {
+	use super::Weight;
+
+	/// `BlockTime::set_block_time`.
+	pub const SET_BLOCK_TIME: Weight = 20_000;
+}
+
+/// Calls of `Equivocation`.
+pub mod equivocation {
+	use super::Weight;
+
+	/// `Equivocation::report`, dominated by checking two signatures.
+	pub const REPORT: Weight = 200_000;
+}
+
+/// Calls of `Online`.
+pub mod online {
+	use super::Weight;
+
+	/// `Online::rejoin`, dominated by checking the signature.
+	pub const REJOIN: Weight = 150_000;
+	/// `Online::set_window`.
+	pub const SET_WINDOW: Weight = 20_000;
+}
+
+/// Calls of `Randomness`.
+pub mod randomness {
+	use super::Weight;
+
+	/// `Randomness::register_vrf_key`, dominated by checking the signature.
+	pub const REGISTER_VRF_KEY: Weight = 100_000;
+	/// `Randomness::set_vrf_output`.
+	pub const SET_VRF_OUTPUT: Weight = 10_000;
+}
+
+/// Calls of `TemplateModule`.
+pub mod template {
+	use super::Weight;
+
+	/// `TemplateModule::do_something`.
+	pub const DO_SOMETHING: Weight = 50_000;
+}
+
+/// Calls of `Validation`.
+pub mod validation {
+	use super::Weight;
+
+	/// `Validation::with_tip`, on top of the weight of the call it dispatches.
+	pub const WITH_TIP: Weight = 20_000;
+	/// `Validation::blacklist` and `Validation::unblacklist`.
+	pub const BLACKLIST: Weight = 20_000;
+}
+
+/// Calls of `Permissions`.
+pub mod permissions {
+	use super::Weight;
+
+	/// Every call of `Permissions`, which write one storage entry each.
+	pub const MANAGE: Weight = 20_000;
+}
@@ -0,0 +1,205 @@
+/// A runtime module punishing Aura authorities that seal two different blocks for one slot.
+///
+/// Nodes detect equivocations while importing blocks and report them with an unsigned
+/// `report` transaction, carrying both headers without their seal along with the seal
+/// signatures. The proof is checked against the current authority set: both signatures must be
+/// made by the author of the slot over `(slot, header hash)`, as Aura seals blocks. The offender
+/// is then removed from the authority set, unless it is the last authority.
+
+use parity_codec::{Encode, Decode};
+use rstd::prelude::*;
+use runtime_primitives::traits::{Header as HeaderT, Verify};
+use runtime_primitives::transaction_validity::{TransactionValidity, TransactionPriority, TransactionLongevity};
+use support::{decl_module, decl_storage, decl_event, StorageMap, Parameter, dispatch::Result};
+use system::ensure_inherent;
+
+/// `TransactionValidity::Invalid` code of reports with an invalid proof.
+pub const INVALID_PROOF: i8 = -3;
+
+/// Number of blocks a report stays in the pool.
+const REPORT_LONGEVITY: TransactionLongevity = 64;
+
+/// Two headers sealed by the same authority for the same slot.
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct EquivocationProof<Header, Signature> {
+	/// The slot both headers were sealed for.
+	pub slot: u64,
+	/// The first header without its seal, and the seal signature.
+	pub first: (Header, Signature),
+	/// The second header without its seal, and the seal signature.
+	pub second: (Header, Signature),
+}
+
+/// The module's configuration trait.
+pub trait Trait: consensus::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+
+	/// The signature of block seals.
+	type Signature: Parameter + Verify<Signer = Self::SessionKey>;
+}
+
+/// The proof type of the runtime.
+pub type ProofOf<T> = EquivocationProof<<T as system::Trait>::Header, <T as Trait>::Signature>;
+
+decl_storage! {
+	trait Store for Module<T: Trait> as Equivocation {
+		/// Authorities punished for an equivocation, with the slot they equivocated at.
+		pub Offenders get(offence): map T::SessionKey => Option<u64>;
+		/// Slots an equivocation was reported for.
+		pub Reported get(is_reported): map u64 => bool;
+	}
+}
+
+decl_module! {
+	/// The module declaration.
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event<T>() = default;
+
+		/// Report an equivocation, removing the offender from the authority set.
+		fn report(origin, proof: ProofOf<T>) -> Result {
+			ensure_inherent(origin)?;
+			let offender = Self::check_proof(&proof)?;
+
+			<Reported<T>>::insert(proof.slot, true);
+			<Offenders<T>>::insert(&offender, proof.slot);
+			let authorities = <consensus::Module<T>>::authorities();
+			let remaining = authorities.iter().filter(|a| **a != offender).cloned().collect::<Vec<_>>();
+			let removed = !remaining.is_empty();
+			if removed {
+				<consensus::Module<T>>::set_authorities(&remaining);
+			}
+			Self::deposit_event(RawEvent::Equivocation(offender, proof.slot, removed));
+			Ok(())
+		}
+	}
+}
+
+decl_event!(
+	pub enum Event<T> where SessionKey = <T as consensus::Trait>::SessionKey {
+		/// An authority sealed two blocks for a slot. The flag tells whether it was removed from
+		/// the authority set, which is not done for the last authority.
+		Equivocation(SessionKey, u64, bool),
+	}
+);
+
+impl<T: Trait> Module<T> {
+	/// Check `proof` against the current authority set, returning the offender.
+	pub fn check_proof(proof: &ProofOf<T>) -> rstd::result::Result<T::SessionKey, &'static str> {
+		if Self::is_reported(proof.slot) {
+			return Err("equivocation already reported");
+		}
+		let authorities = <consensus::Module<T>>::authorities();
+		if authorities.is_empty() {
+			return Err("no authorities");
+		}
+		let author = &authorities[(proof.slot % authorities.len() as u64) as usize];
+
+		let (first, first_signature) = &proof.first;
+		let (second, second_signature) = &proof.second;
+		let (first_hash, second_hash) = (first.hash(), second.hash());
+		if first_hash == second_hash {
+			return Err("headers are identical");
+		}
+		if !first_signature.verify(&(proof.slot, first_hash).encode()[..], author)
+			|| !second_signature.verify(&(proof.slot, second_hash).encode()[..], author)
+		{
+			return Err("invalid seal");
+		}
+		Ok(author.clone())
+	}
+
+	/// Validity of a report in the transaction pool. Valid reports are included before any
+	/// other transaction, and only one per slot.
+	pub fn validate_report(proof: &ProofOf<T>) -> TransactionValidity {
+		if Self::check_proof(proof).is_err() {
+			return TransactionValidity::Invalid(INVALID_PROOF);
+		}
+		TransactionValidity::Valid {
+			priority: TransactionPriority::max_value(),
+			requires: Vec::new(),
+			provides: vec![(b"equivocation", proof.slot).encode()],
+			longevity: REPORT_LONGEVITY,
+		}
+	}
+}
+
+/// tests for this module
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use runtime_io::with_externalities;
+	use primitives::Blake2Hasher;
+	use support::{assert_ok, assert_noop};
+	use runtime_primitives::{
+		BuildStorage,
+		testing::{Header, UintAuthorityId}
+	};
+	use crate::mock::{Origin, Test, TestSignature};
+
+	impl Trait for Test {
+		type Event = ();
+		type Signature = TestSignature;
+	}
+	type Equivocation = Module<Test>;
+	type Consensus = consensus::Module<Test>;
+
+	fn new_test_ext(authorities: Vec<u64>) -> runtime_io::TestExternalities<Blake2Hasher> {
+		let mut t = system::GenesisConfig::<Test>::default().build_storage().unwrap().0;
+		t.extend(consensus::GenesisConfig::<Test> {
+			code: vec![],
+			authorities: authorities.into_iter().map(UintAuthorityId).collect(),
+		}.build_storage().unwrap().0);
+		t.into()
+	}
+
+	fn header(number: u64) -> Header {
+		Header::new(number, Default::default(), Default::default(), Default::default(), Default::default())
+	}
+
+	fn sealed(slot: u64, author: u64, header: Header) -> (Header, TestSignature) {
+		let signature = TestSignature(author, (slot, header.hash()).encode());
+		(header, signature)
+	}
+
+	#[test]
+	fn equivocating_author_is_removed() {
+		with_externalities(&mut new_test_ext(vec![1, 2, 3]), || {
+			// Slot 4 belongs to authority 4 % 3 = 1, so the second one.
+			let proof = EquivocationProof { slot: 4, first: sealed(4, 2, header(1)), second: sealed(4, 2, header(2)) };
+			assert!(Equivocation::report(Origin::signed(1), proof.clone()).is_err());
+			assert_ok!(Equivocation::report(Origin::INHERENT, proof.clone()));
+
+			assert_eq!(Consensus::authorities(), vec![UintAuthorityId(1), UintAuthorityId(3)]);
+			assert_eq!(Equivocation::offence(UintAuthorityId(2)), Some(4));
+			assert_noop!(Equivocation::report(Origin::INHERENT, proof), "equivocation already reported");
+		});
+	}
+
+	#[test]
+	fn invalid_proofs_are_rejected() {
+		with_externalities(&mut new_test_ext(vec![1, 2, 3]), || {
+			let same = EquivocationProof { slot: 4, first: sealed(4, 2, header(1)), second: sealed(4, 2, header(1)) };
+			assert_noop!(Equivocation::report(Origin::INHERENT, same), "headers are identical");
+
+			let wrong_author = EquivocationProof { slot: 4, first: sealed(4, 3, header(1)), second: sealed(4, 3, header(2)) };
+			assert_noop!(Equivocation::report(Origin::INHERENT, wrong_author), "invalid seal");
+
+			let wrong_slot = EquivocationProof { slot: 4, first: sealed(7, 2, header(1)), second: sealed(4, 2, header(2)) };
+			assert_noop!(Equivocation::report(Origin::INHERENT, wrong_slot.clone()), "invalid seal");
+			assert_eq!(Equivocation::validate_report(&wrong_slot), TransactionValidity::Invalid(INVALID_PROOF));
+		});
+	}
+
+	#[test]
+	fn last_authority_is_kept() {
+		with_externalities(&mut new_test_ext(vec![1]), || {
+			let proof = EquivocationProof { slot: 9, first: sealed(9, 1, header(1)), second: sealed(9, 1, header(2)) };
+			assert_ok!(Equivocation::report(Origin::INHERENT, proof));
+			assert_eq!(Consensus::authorities(), vec![UintAuthorityId(1)]);
+			assert_eq!(Equivocation::offence(UintAuthorityId(1)), Some(9));
+		});
+	}
+}
@@ -13,7 +13,7 @@ use rstd::prelude::*;
 use primitives::bytes;
 use primitives::{ed25519, sr25519, OpaqueMetadata};
 use runtime_primitives::{
-	ApplyResult, transaction_validity::TransactionValidity, generic, create_runtime_str,
+	ApplyResult, ApplyError, ApplyOutcome, transaction_validity::TransactionValidity, generic, create_runtime_str,
 	traits::{self, NumberFor, BlakeTwo256, Block as BlockT, StaticLookup, Verify}
 };
 use client::{
@@ -32,6 +32,8 @@ pub use timestamp::Call as TimestampCall;
 pub use balances::Call as BalancesCall;
 pub use runtime_primitives::{Permill, Perbill};
 pub use timestamp::BlockPeriod;
+pub use equivocation::{Call as EquivocationCall, EquivocationProof};
+pub use randomness::{Call as RandomnessCall, InherentType as VrfInherentType, INHERENT_IDENTIFIER as VRF_INHERENT_IDENTIFIER};
 pub use support::{StorageValue, construct_runtime};
 
 /// The type that is used for identifying authorities.
@@ -46,6 +48,9 @@ pub type AccountId = <AccountSignature as Verify>::Signer;
 /// The type used by authorities to prove their ID.
 pub type AccountSignature = sr25519::Signature;
 
+/// The type of the VRF keys authorities contribute randomness with.
+pub type VrfKey = sr25519::Public;
+
 /// A hash of some data used by the chain.
 pub type Hash = primitives::H256;
 
@@ -58,6 +63,65 @@ pub type Nonce = u64;
 /// Used for the module template in `./template.rs`
 mod template;
 
+/// Storage migrations run after runtime upgrades, in `./migrations.rs`
+mod migrations;
+
+/// Block weight and length limits and weight based fees, in `./weights.rs`
+mod weights;
+
+/// Transaction filters, tips and priorities, in `./validation.rs`
+mod validation;
+
+/// The weight of every call of the runtime, in `./call_weights.rs`
+mod call_weights;
+
+/// Equivocation reports for Aura authors, in `./equivocation.rs`
+mod equivocation;
+
+/// Offline authority tracking, in `./online.rs`
+mod online;
+
+/// Target block time changes, in `./block_time.rs`
+mod block_time;
+
+/// Randomness from the VRF outputs of block authors, in `./randomness.rs`
+mod randomness;
+
+/// Account and node allow-lists of permissioned chains, in `./permissions.rs`
+mod permissions;
+
+/// Test runtime shared by the module tests, in `./mock.rs`
+#[cfg(test)]
+mod mock;
+
+/// Benchmarks used to derive call weights, in `./benchmarks.rs`
+#[cfg(feature = "runtime-benchmarks")]
+mod benchmarks;
+
+/// Stand-in for `./benchmarks.rs` when built without the `runtime-benchmarks` feature.
+#[cfg(not(feature = "runtime-benchmarks"))]
+mod benchmarks {
+	use rstd::prelude::*;
+
+	const DISABLED: &str = "Runtime built without the runtime-benchmarks feature";
+
+	pub fn components(_: &[u8], _: &[u8]) -> Result<Vec<(Vec<u8>, u32, u32)>, &'static str> {
+		Err(DISABLED)
+	}
+
+	pub fn setup(_: &[u8], _: &[u8], _: &[(Vec<u8>, u32)]) -> Result<Vec<u8>, &'static str> {
+		Err(DISABLED)
+	}
+
+	pub fn dispatch(_: &[u8]) -> Result<(), &'static str> {
+		Err(DISABLED)
+	}
+}
+
+use weights::{DispatchInfo, GetDispatchInfo};
+pub use weights::{DispatchClass, Weight};
+use validation::TransactionFilter;
+
 /// Opaque types. These are used by the CLI to instantiate machinery that don't need to know
 /// the specifics of the runtime. They can then be made to be agnostic over specific formats
 /// of data like extrinsics, allowing for them to continue syncing the network through upgrades
@@ -95,7 +159,7 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	spec_name: create_runtime_str!("node-template"),
 	impl_name: create_runtime_str!("node-template"),
 	authoring_version: 3,
-	spec_version: 3,
+	spec_version: 16,
 	impl_version: 0,
 	apis: RUNTIME_API_VERSIONS,
 };
@@ -187,6 +251,72 @@ impl sudo::Trait for Runtime {
 	type Proposal = Call;
 }
 
+/// Provides the `spec_version` of this runtime to the migrations module.
+pub struct SpecVersion;
+impl migrations::SpecVersion for SpecVersion {
+	fn get() -> u32 {
+		VERSION.spec_version
+	}
+}
+
+impl migrations::Trait for Runtime {
+	type SpecVersion = SpecVersion;
+	/// Every module with a versioned storage layout.
+	type Migrations = (TemplateModule,);
+}
+
+impl weights::Trait for Runtime {
+	/// Fees are paid in the native currency.
+	type Currency = Balances;
+}
+
+impl validation::Trait for Runtime {
+	/// The uniquitous event type.
+	type Event = Event;
+	/// Tips are paid in the native currency.
+	type Currency = Balances;
+	type Call = Call;
+	/// Checks run on every signed transaction.
+	type Filter = (Validation, Permissions);
+}
+
+impl block_time::Trait for Runtime {
+	/// The uniquitous event type.
+	type Event = Event;
+	/// The ubiquitous log type.
+	type Log = Log;
+}
+
+impl equivocation::Trait for Runtime {
+	/// The uniquitous event type.
+	type Event = Event;
+	/// Blocks are sealed with the signature of authorities.
+	type Signature = AuthoritySignature;
+}
+
+impl online::Trait for Runtime {
+	/// The uniquitous event type.
+	type Event = Event;
+	/// Authorities rejoin with a signature of their key.
+	type Signature = AuthoritySignature;
+}
+
+impl randomness::Trait for Runtime {
+	/// The uniquitous event type.
+	type Event = Event;
+	/// Authors evaluate an sr25519 VRF.
+	type VrfKey = VrfKey;
+	/// VRF keys are registered with a signature of the authority key.
+	type Signature = AuthoritySignature;
+	/// The ubiquitous log type.
+	type Log = Log;
+}
+
+impl permissions::Trait for Runtime {
+	/// The uniquitous event type.
+	type Event = Event;
+}
+
 /// Used for the module template in `./template.rs`
 impl template::Trait for Runtime {
 	type Event = Event;
@@ -199,6 +329,9 @@ construct_runtime!(
 		UncheckedExtrinsic = UncheckedExtrinsic
 	{
 		System: system::{default, Log(ChangesTrieRoot)},
+		// Runs before every other module, so they only ever see migrated storage. It has no
+		// event, so the event indices of the modules after it are unchanged.
+		Migrations: migrations::{Module, Storage, Config<T>},
 		Timestamp: timestamp::{Module, Call, Storage, Config<T>, Inherent},
 		Consensus: consensus::{Module, Call, Storage, Config<T>, Log(AuthoritiesChange), Inherent},
 		Aura: aura::{Module},
@@ -207,17 +340,68 @@ construct_runtime!(
 		Sudo: sudo,
 		// Used for the module template in `./template.rs`
 		TemplateModule: template::{Module, Call, Storage, Event<T>},
+		Weights: weights::{Module, Storage, Config<T>},
+		Validation: validation::{Module, Call, Storage, Event<T>},
+		Equivocation: equivocation::{Module, Call, Storage, Event<T>},
+		Online: online::{Module, Call, Storage, Config<T>, Event<T>},
+		BlockTime: block_time::{Module, Call, Storage, Config<T>, Event<T>, Log()},
+		Randomness: randomness::{Module, Call, Storage, Config<T>, Event<T>, Log(), Inherent},
+		Permissions: permissions::{Module, Call, Storage, Config<T>, Event<T>},
 	}
 );
 
+impl GetDispatchInfo for Call {
+	fn dispatch_info(&self) -> DispatchInfo {
+		match self {
+			// Inherents must always fit in a block.
+			Call::Timestamp(_) => DispatchInfo::operational(call_weights::timestamp::SET),
+			Call::Consensus(consensus::Call::set_code(..)) =>
+				DispatchInfo::operational(call_weights::consensus::SET_CODE),
+			Call::Consensus(consensus::Call::set_heap_pages(..)) |
+			Call::Consensus(consensus::Call::set_storage(..)) |
+			Call::Consensus(consensus::Call::kill_storage(..)) |
+			Call::Consensus(consensus::Call::note_offline(..)) => DispatchInfo::operational(call_weights::DEFAULT),
+			// `remark` and `report_misbehavior` can be sent by any account.
+			Call::Consensus(_) => DispatchInfo::normal(call_weights::DEFAULT),
+			Call::Balances(balances::Call::transfer(..)) =>
+				DispatchInfo::normal(call_weights::balances::TRANSFER),
+			Call::Balances(balances::Call::set_balance(..)) =>
+				DispatchInfo::operational(call_weights::balances::SET_BALANCE),
+			Call::Sudo(sudo::Call::sudo(proposal)) => DispatchInfo::operational(
+				call_weights::sudo::SUDO.saturating_add(proposal.dispatch_info().weight)
+			),
+			Call::Sudo(_) => DispatchInfo::operational(call_weights::sudo::SET_KEY),
+			Call::BlockTime(_) => DispatchInfo::operational(call_weights::block_time::SET_BLOCK_TIME),
+			Call::Equivocation(_) => DispatchInfo::operational(call_weights::equivocation::REPORT),
+			Call::Online(online::Call::rejoin(..)) => DispatchInfo::normal(call_weights::online::REJOIN),
+			Call::Online(_) => DispatchInfo::operational(call_weights::online::SET_WINDOW),
+			Call::Randomness(randomness::Call::register_vrf_key(..)) =>
+				DispatchInfo::operational(call_weights::randomness::REGISTER_VRF_KEY),
+			Call::Randomness(_) => DispatchInfo::operational(call_weights::randomness::SET_VRF_OUTPUT),
+			Call::TemplateModule(call) => call.dispatch_info(),
+			Call::Validation(validation::Call::with_tip(_, call)) => {
+				let info = call.dispatch_info();
+				DispatchInfo { weight: info.weight.saturating_add(call_weights::validation::WITH_TIP), ..info }
+			},
+			// Root only, so it is only operational as a proposal of `sudo`.
+			Call::Validation(_) => DispatchInfo::normal(call_weights::validation::BLACKLIST),
+			// Also made by the admin account, which must not reach the operational space.
+			Call::Permissions(_) => DispatchInfo::normal(call_weights::permissions::MANAGE),
+			_ => DispatchInfo::normal(call_weights::DEFAULT),
+		}
+	}
+}
+
 /// The type used as a helper for interpreting the sender of transactions.
 type Context = system::ChainContext<Runtime>;
 /// The address format for describing accounts.
-type Address = <Indices as StaticLookup>::Source;
+pub type Address = <Indices as StaticLookup>::Source;
 /// Block header type as expected by this runtime.
 pub type Header = generic::Header<BlockNumber, BlakeTwo256, Log>;
 /// Block type as expected by this runtime.
 pub type Block = generic::Block<Header, UncheckedExtrinsic>;
+/// Balance of an account.
+pub type Balance = <Runtime as balances::Trait>::Balance;
 /// BlockId type as expected by this runtime.
 pub type BlockId = generic::BlockId<Block>;
 /// Unchecked extrinsic type as expected by this runtime.
@@ -225,7 +409,250 @@ pub type UncheckedExtrinsic = generic::UncheckedMortalCompactExtrinsic<Address,
 /// Extrinsic type that has already been checked.
 pub type CheckedExtrinsic = generic::CheckedExtrinsic<AccountId, Nonce, Call>;
 /// Executive: handles dispatch to the various modules.
-pub type Executive = executive::Executive<Runtime, Block, Context, Balances, AllModules>;
+pub type Executive = executive::Executive<Runtime, Block, Context, Weights, AllModules>;
+
+/// The slot duration the blocks after `header` use, if `header` changes it.
+pub fn slot_duration_change(header: &Header) -> Option<u64> {
+	header.digest.logs.iter()
+		.filter_map(|log| match log {
+			Log(InternalLog::block_time(log)) => log.as_slot_duration_change(),
+			_ => None,
+		})
+		.next()
+}
+
+/// The seed, VRF output and proof of the author of `header`, if it set them.
+pub fn vrf_output(header: &Header) -> Option<(Hash, [u8; 32], Vec<u8>)> {
+	header.digest.logs.iter()
+		.filter_map(|log| match log {
+			Log(InternalLog::randomness(log)) => log.as_vrf_output(),
+			_ => None,
+		})
+		.next()
+}
+
+/// The weight and class of `call` sent by `signer`, or unsigned.
+///
+/// Signed calls are only operational when the sudo key sends them through `sudo`: any account
+/// can sign root calls, which then fail on dispatch, and must not be able to fill the share of
+/// blocks reserved to operational calls with them.
+fn dispatch_info_of(call: &Call, signer: Option<&AccountId>) -> DispatchInfo {
+	let info = call.dispatch_info();
+	match signer {
+		Some(who) if !(is_sudo(call) && *who == Sudo::key()) =>
+			DispatchInfo { class: DispatchClass::Normal, ..info },
+		_ => info,
+	}
+}
+
+/// Whether `call` goes through `sudo`, possibly tipped.
+fn is_sudo(call: &Call) -> bool {
+	match call {
+		Call::Sudo(_) => true,
+		Call::Validation(validation::Call::with_tip(_, call)) => is_sudo(call),
+		_ => false,
+	}
+}
+
+/// The tip paid by a call wrapped in `Validation::with_tip`.
+fn tip_of(call: &Call) -> Balance {
+	match call {
+		Call::Validation(validation::Call::with_tip(tip, _)) => *tip,
+		_ => 0,
+	}
+}
+
+/// `TransactionValidity::Invalid` code of transactions whose signer has no account index yet.
+pub const UNKNOWN_ACCOUNT: i8 = -5;
+
+/// Why `apply_extrinsic_with_limits` rejected an extrinsic.
+enum Rejection {
+	/// Rejected by a transaction filter, like `validation::FILTERED` in the pool.
+	Filtered(&'static str),
+	/// Rejected by the block limits or the `Executive`.
+	Apply(ApplyError),
+}
+
+impl From<ApplyError> for Rejection {
+	fn from(e: ApplyError) -> Self {
+		Rejection::Apply(e)
+	}
+}
+
+/// Apply an extrinsic if it passes the transaction filters and fits in the weight and length
+/// left in the block.
+fn apply_extrinsic_with_limits(extrinsic: UncheckedExtrinsic) -> Result<ApplyOutcome, Rejection> {
+	let signer = match &extrinsic.signature {
+		Some((address, ..)) => Some(Indices::lookup(address.clone()).map_err(|_| ApplyError::BadSignature)?),
+		None => None,
+	};
+	if let Some(who) = &signer {
+		<Runtime as validation::Trait>::Filter::filter(who, &extrinsic.function).map_err(Rejection::Filtered)?;
+	}
+
+	let info = dispatch_info_of(&extrinsic.function, signer.as_ref());
+	let len = extrinsic.encode().len() as u32;
+	Weights::note_extrinsic(info, len).map_err(|_| ApplyError::FullBlock)?;
+	Ok(Executive::apply_extrinsic(extrinsic)?)
+}
+
+/// Validate a transaction, rejecting it if it could never fit in a block or fails a filter.
+///
+/// Signed transactions are prioritised by fee per byte plus tip, and live in the pool only
+/// as long as their era.
+fn validate_transaction_with_limits(tx: UncheckedExtrinsic) -> TransactionValidity {
+	let signer = match &tx.signature {
+		Some((address, _, _, era)) => match Indices::lookup(address.clone()) {
+			Ok(who) => Some((who, era.clone())),
+			Err(_) => return TransactionValidity::Invalid(UNKNOWN_ACCOUNT),
+		},
+		None => None,
+	};
+	let info = dispatch_info_of(&tx.function, signer.as_ref().map(|(who, _)| who));
+	let len = tx.encode().len() as u32;
+	if !Weights::fits_empty_block(info, len) {
+		return TransactionValidity::Invalid(ApplyError::FullBlock as i8);
+	}
+
+	let (who, era) = match signer {
+		Some(signer) => signer,
+		None => return match &tx.function {
+			// Reports are unsigned, so their proof is what keeps them out of the pool if invalid.
+			Call::Equivocation(equivocation::Call::report(proof)) => Equivocation::validate_report(proof),
+			Call::Randomness(randomness::Call::register_vrf_key(key, vrf_key, signature)) =>
+				Randomness::validate_registration(key, vrf_key, signature),
+			_ => Executive::validate_transaction(tx),
+		},
+	};
+	// `Executive::validate_transaction` only charges the base and byte fees, as the weight is
+	// only known to `make_payment` once the extrinsic is applied.
+	if Weights::ensure_can_pay(&who, info.weight, len).is_err() {
+		return TransactionValidity::Invalid(ApplyError::CantPay as i8);
+	}
+	let tip = tip_of(&tx.function);
+	if Validation::check(&who, &tx.function, tip).is_err() {
+		return TransactionValidity::Invalid(validation::FILTERED);
+	}
+
+	match Executive::validate_transaction(tx) {
+		TransactionValidity::Valid { requires, provides, .. } => {
+			let current = System::block_number();
+			TransactionValidity::Valid {
+				priority: Validation::priority(Weights::compute_fee(info.weight, len), tip, len),
+				requires,
+				provides,
+				longevity: era.death(current).saturating_sub(current),
+			}
+		},
+		invalid => invalid,
+	}
+}
+
+/// Weight and fees of a transaction.
+#[derive(Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct FeeInfo {
+	/// Weight of the call.
+	pub weight: Weight,
+	/// Class of the call.
+	pub class: DispatchClass,
+	/// Fee charged by `Weights` when the transaction is applied, zero for unsigned ones.
+	pub fee: Balance,
+	/// Tip paid on top of the fee through `Validation::with_tip`.
+	pub tip: Balance,
+}
+
+/// The fee and tip a transaction is charged, computed as when it is applied.
+fn query_fee_info(tx: UncheckedExtrinsic) -> FeeInfo {
+	let signer = tx.signature.as_ref().and_then(|(address, ..)| Indices::lookup(address.clone()).ok());
+	let info = dispatch_info_of(&tx.function, signer.as_ref());
+	let len = tx.encode().len() as u32;
+	let fee = match tx.signature {
+		Some(_) => Weights::compute_fee(info.weight, len),
+		None => 0,
+	};
+	FeeInfo { weight: info.weight, class: info.class, fee, tip: tip_of(&tx.function) }
+}
+
+/// Nonce, balances and locks of an account.
+#[derive(Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct AccountInfo {
+	/// Nonce of the next transaction of the account.
+	pub nonce: Nonce,
+	/// Free balance.
+	pub free: Balance,
+	/// Reserved balance.
+	pub reserved: Balance,
+	/// Locks on the free balance.
+	pub locks: Vec<balances::BalanceLock<Balance, BlockNumber>>,
+}
+
+/// Runtime APIs specific to this runtime.
+pub mod api_ext {
+	use rstd::prelude::*;
+	use runtime_primitives::traits::Block as BlockT;
+	use super::{AccountId, AccountInfo, AuthorityId, BlockNumber, FeeInfo, Hash, Nonce, VrfKey};
+
+	client::decl_runtime_apis! {
+		/// Setup and dispatch of the call benchmarks run by the `benchmark` node subcommand.
+		///
+		/// Every method fails unless the runtime is built with the `runtime-benchmarks` feature.
+		pub trait Benchmark {
+			/// Name, lowest and highest value of each component of a benchmark.
+			fn components(module: Vec<u8>, call: Vec<u8>) -> Result<Vec<(Vec<u8>, u32, u32)>, Vec<u8>>;
+			/// Prepare storage for a run of a benchmark, returning the call to dispatch.
+			fn setup(module: Vec<u8>, call: Vec<u8>, components: Vec<(Vec<u8>, u32)>) -> Result<Vec<u8>, Vec<u8>>;
+			/// Dispatch a call returned by `setup`.
+			fn dispatch(call: Vec<u8>) -> Result<(), Vec<u8>>;
+		}
+
+		/// Details of transactions used by the transaction pool of the node.
+		pub trait TransactionInfo {
+			/// Sender and nonce of a signed transaction, along with the current nonce of the sender.
+			fn sender(tx: <Block as BlockT>::Extrinsic) -> Option<(AccountId, Nonce, Nonce)>;
+		}
+
+		/// State of accounts, served by the `account_*` RPC methods of the node.
+		pub trait AccountApi {
+			/// The nonce the next transaction of `account` must use, ignoring pending transactions.
+			fn account_nonce(account: AccountId) -> Nonce;
+			/// Nonce, balances and locks of `account`.
+			fn account_info(account: AccountId) -> AccountInfo;
+		}
+
+		/// Fees of transactions, served by the `payment_queryInfo` RPC method of the node.
+		pub trait TransactionPayment {
+			/// Weight, fee and tip of a transaction, computed as when it is applied.
+			fn query_info(tx: <Block as BlockT>::Extrinsic) -> FeeInfo;
+		}
+
+		/// Randomness of the `Randomness` module, and what nodes check VRF outputs with.
+		pub trait RandomnessApi {
+			/// Randomness for `subject`, along with the block from which on it is safe to use:
+			/// it is unpredictable for anything committed to before that block.
+			fn random(subject: Vec<u8>) -> (Hash, BlockNumber);
+			/// The seed the VRF of the next block author is evaluated over.
+			fn vrf_seed() -> Hash;
+			/// The VRF key registered by `authority`.
+			fn vrf_key(authority: AuthorityId) -> Option<VrfKey>;
+		}
+
+		/// Allow-lists of the `Permissions` module.
+		pub trait PermissionsApi {
+			/// Whether `account` may sign transactions.
+			fn may_transact(account: AccountId) -> bool;
+			/// Base58 IDs of the nodes allowed to join the network.
+			fn allowed_nodes() -> Vec<Vec<u8>>;
+		}
+
+		/// State of the template module, served by the `template_*` RPC methods of the node.
+		pub trait TemplateApi {
+			/// The stored value, along with the block it was stored at.
+			fn something() -> Option<(u32, Option<BlockNumber>)>;
+		}
+	}
+}
 
 // Implement our runtime API endpoints. This is just a bunch of proxying.
 impl_runtime_apis! {
@@ -255,7 +682,12 @@ impl_runtime_apis! {
 
 	impl block_builder_api::BlockBuilder<Block> for Runtime {
 		fn apply_extrinsic(extrinsic: <Block as BlockT>::Extrinsic) -> ApplyResult {
-			Executive::apply_extrinsic(extrinsic)
+			apply_extrinsic_with_limits(extrinsic).map_err(|rejection| match rejection {
+				// `ApplyError` has no variant for filtered transactions. Authors only try them
+				// if a filter changed after the pool accepted them, and drop them on any error.
+				Rejection::Filtered(_) => ApplyError::BadSignature,
+				Rejection::Apply(e) => e,
+			})
 		}
 
 		fn finalize_block() -> <Block as BlockT>::Header {
@@ -277,7 +709,7 @@ impl_runtime_apis! {
 
 	impl runtime_api::TaggedTransactionQueue<Block> for Runtime {
 		fn validate_transaction(tx: <Block as BlockT>::Extrinsic) -> TransactionValidity {
-			Executive::validate_transaction(tx)
+			validate_transaction_with_limits(tx)
 		}
 	}
 
@@ -298,4 +730,78 @@ impl_runtime_apis! {
 			Consensus::authorities()
 		}
 	}
+
+	impl api_ext::Benchmark<Block> for Runtime {
+		fn components(module: Vec<u8>, call: Vec<u8>) -> Result<Vec<(Vec<u8>, u32, u32)>, Vec<u8>> {
+			benchmarks::components(&module, &call).map_err(|e| e.as_bytes().to_vec())
+		}
+
+		fn setup(module: Vec<u8>, call: Vec<u8>, components: Vec<(Vec<u8>, u32)>) -> Result<Vec<u8>, Vec<u8>> {
+			benchmarks::setup(&module, &call, &components).map_err(|e| e.as_bytes().to_vec())
+		}
+
+		fn dispatch(call: Vec<u8>) -> Result<(), Vec<u8>> {
+			benchmarks::dispatch(&call).map_err(|e| e.as_bytes().to_vec())
+		}
+	}
+
+	impl api_ext::AccountApi<Block> for Runtime {
+		fn account_nonce(account: AccountId) -> Nonce {
+			System::account_nonce(&account)
+		}
+
+		fn account_info(account: AccountId) -> AccountInfo {
+			AccountInfo {
+				nonce: System::account_nonce(&account),
+				free: Balances::free_balance(&account),
+				reserved: Balances::reserved_balance(&account),
+				locks: Balances::locks(&account),
+			}
+		}
+	}
+
+	impl api_ext::TemplateApi<Block> for Runtime {
+		fn something() -> Option<(u32, Option<BlockNumber>)> {
+			TemplateModule::something().map(|value| (value, TemplateModule::last_update()))
+		}
+	}
+
+	impl api_ext::RandomnessApi<Block> for Runtime {
+		fn random(subject: Vec<u8>) -> (Hash, BlockNumber) {
+			(Randomness::random(&subject), Randomness::seed().1)
+		}
+
+		fn vrf_seed() -> Hash {
+			Randomness::seed().0
+		}
+
+		fn vrf_key(authority: AuthorityId) -> Option<VrfKey> {
+			Randomness::vrf_key(authority)
+		}
+	}
+
+	impl api_ext::PermissionsApi<Block> for Runtime {
+		fn may_transact(account: AccountId) -> bool {
+			Permissions::may_transact(&account)
+		}
+
+		fn allowed_nodes() -> Vec<Vec<u8>> {
+			Permissions::nodes()
+		}
+	}
+
+	impl api_ext::TransactionPayment<Block> for Runtime {
+		fn query_info(tx: <Block as BlockT>::Extrinsic) -> FeeInfo {
+			query_fee_info(tx)
+		}
+	}
+
+	impl api_ext::TransactionInfo<Block> for Runtime {
+		fn sender(tx: <Block as BlockT>::Extrinsic) -> Option<(AccountId, Nonce, Nonce)> {
+			let (address, _, nonce, _) = tx.signature?;
+			let who = Indices::lookup(address).ok()?;
+			let current = System::account_nonce(&who);
+			Some((who, nonce.0, current))
+		}
+	}
 }
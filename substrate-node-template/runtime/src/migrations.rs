@@ -0,0 +1,245 @@
+/// A runtime module that runs versioned storage migrations after a runtime upgrade.
+///
+/// Every module whose storage layout changes implements `VersionedStorage`, listing one
+/// `Migration` per layout version. The last applied version of each module is stored here,
+/// and `on_initialize` of the first block with a new `spec_version` runs every pending
+/// migration in order.
+///
+/// The genesis block records the latest version of every module and the `spec_version` it
+/// was built with, so migrations only ever run on a real upgrade.
+///
+/// The module runs before every other one, which `construct_runtime!` only allows without
+/// shifting the event indices of the modules after it because it has no event. Applied
+/// versions are read from `StorageVersion` instead.
+
+use rstd::prelude::*;
+use support::{decl_module, decl_storage, StorageValue, StorageMap};
+
+/// A single step of a module's storage layout.
+pub struct Migration {
+	/// Storage version of the module once this migration has run.
+	pub version: u32,
+	/// Rewrites the storage of the previous version into the new layout.
+	pub migrate: fn(),
+}
+
+/// A module with a versioned storage layout.
+pub trait VersionedStorage {
+	/// Name under which the storage version of the module is recorded.
+	const MODULE: &'static [u8];
+
+	/// All migrations of the module, in increasing `version` order.
+	fn migrations() -> Vec<Migration>;
+}
+
+/// The set of modules whose migrations are run, as a tuple of `VersionedStorage`.
+pub trait Migrations<T: Trait> {
+	/// Run all pending migrations of every module in the set.
+	fn migrate();
+
+	/// The latest storage version of every module in the set, keyed by `MODULE`.
+	fn latest_versions() -> Vec<(Vec<u8>, u32)>;
+}
+
+impl<T: Trait> Migrations<T> for () {
+	fn migrate() {}
+
+	fn latest_versions() -> Vec<(Vec<u8>, u32)> {
+		Vec::new()
+	}
+}
+
+macro_rules! impl_migrations_for_tuple {
+	($($module:ident),+) => {
+		impl<T: Trait, $($module: VersionedStorage),+> Migrations<T> for ($($module,)+) {
+			fn migrate() {
+				$( <Module<T>>::run_pending::<$module>(); )+
+			}
+
+			fn latest_versions() -> Vec<(Vec<u8>, u32)> {
+				vec![$( ($module::MODULE.to_vec(), latest_version::<$module>()) ),+]
+			}
+		}
+	}
+}
+
+impl_migrations_for_tuple!(A);
+impl_migrations_for_tuple!(A, B);
+impl_migrations_for_tuple!(A, B, C);
+impl_migrations_for_tuple!(A, B, C, D);
+impl_migrations_for_tuple!(A, B, C, D, E);
+impl_migrations_for_tuple!(A, B, C, D, E, F);
+impl_migrations_for_tuple!(A, B, C, D, E, F, G);
+impl_migrations_for_tuple!(A, B, C, D, E, F, G, H);
+
+/// The version the last migration of `M` leaves its storage at.
+fn latest_version<M: VersionedStorage>() -> u32 {
+	M::migrations().iter().map(|m| m.version).max().unwrap_or(0)
+}
+
+/// Provides the `spec_version` of the runtime.
+pub trait SpecVersion {
+	/// The `spec_version` of the runtime that is executing.
+	fn get() -> u32;
+}
+
+/// The module's configuration trait.
+pub trait Trait: system::Trait {
+	/// The `spec_version` of the runtime.
+	type SpecVersion: SpecVersion;
+
+	/// Modules whose migrations are run after an upgrade.
+	type Migrations: Migrations<Self>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as Migrations {
+		/// The `spec_version` migrations last ran for.
+		LastSpecVersion get(last_spec_version) build(|_| T::SpecVersion::get()): u32;
+
+		/// The storage version of each module, keyed by `VersionedStorage::MODULE`.
+		StorageVersion get(storage_version) build(|_| T::Migrations::latest_versions()): map Vec<u8> => u32;
+	}
+}
+
+decl_module! {
+	/// The module declaration.
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn on_initialize(_n: T::BlockNumber) {
+			let spec_version = T::SpecVersion::get();
+			if Self::last_spec_version() != spec_version {
+				T::Migrations::migrate();
+				<LastSpecVersion<T>>::put(spec_version);
+			}
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// Run the migrations of `M` newer than its recorded storage version.
+	fn run_pending<M: VersionedStorage>() {
+		let module = M::MODULE.to_vec();
+		let current = Self::storage_version(&module);
+		for migration in M::migrations().into_iter().filter(|m| m.version > current) {
+			(migration.migrate)();
+			<StorageVersion<T>>::insert(&module, migration.version);
+		}
+	}
+}
+
+/// tests for this module
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use runtime_io::with_externalities;
+	use primitives::{H256, Blake2Hasher};
+	use support::{impl_outer_origin, storage::unhashed};
+	use runtime_primitives::{
+		BuildStorage,
+		traits::{BlakeTwo256, IdentityLookup, OnInitialize},
+		testing::{Digest, DigestItem, Header}
+	};
+
+	impl_outer_origin! {
+		pub enum Origin for Test {}
+	}
+
+	#[derive(Clone, Eq, PartialEq)]
+	pub struct Test;
+	impl system::Trait for Test {
+		type Origin = Origin;
+		type Index = u64;
+		type BlockNumber = u64;
+		type Hash = H256;
+		type Hashing = BlakeTwo256;
+		type Digest = Digest;
+		type AccountId = u64;
+		type Lookup = IdentityLookup<Self::AccountId>;
+		type Header = Header;
+		type Event = ();
+		type Log = DigestItem;
+	}
+
+	pub struct TestSpecVersion;
+	impl SpecVersion for TestSpecVersion {
+		fn get() -> u32 { 7 }
+	}
+
+	const APPLIED: &[u8] = b":test:applied";
+
+	// Records which migrations ran, so tests can check their order.
+	fn applied(version: u32) {
+		let mut versions: Vec<u32> = unhashed::get_or_default(APPLIED);
+		versions.push(version);
+		unhashed::put(APPLIED, &versions);
+	}
+
+	pub struct Dummy;
+	impl VersionedStorage for Dummy {
+		const MODULE: &'static [u8] = b"Dummy";
+
+		fn migrations() -> Vec<Migration> {
+			vec![
+				Migration { version: 1, migrate: || applied(1) },
+				Migration { version: 2, migrate: || applied(2) },
+			]
+		}
+	}
+
+	impl Trait for Test {
+		type SpecVersion = TestSpecVersion;
+		type Migrations = (Dummy,);
+	}
+	type Migrations = Module<Test>;
+
+	fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+		system::GenesisConfig::<Test>::default().build_storage().unwrap().0.into()
+	}
+
+	#[test]
+	fn runs_pending_migrations_in_order() {
+		with_externalities(&mut new_test_ext(), || {
+			Migrations::on_initialize(1);
+			assert_eq!(unhashed::get::<Vec<u32>>(APPLIED), Some(vec![1, 2]));
+			assert_eq!(Migrations::storage_version(b"Dummy".to_vec()), 2);
+			assert_eq!(Migrations::last_spec_version(), 7);
+		});
+	}
+
+	#[test]
+	fn skips_applied_migrations() {
+		with_externalities(&mut new_test_ext(), || {
+			<StorageVersion<Test>>::insert(b"Dummy".to_vec(), 1);
+			Migrations::on_initialize(1);
+			assert_eq!(unhashed::get::<Vec<u32>>(APPLIED), Some(vec![2]));
+		});
+	}
+
+	#[test]
+	fn genesis_starts_at_the_latest_versions() {
+		let mut t = system::GenesisConfig::<Test>::default().build_storage().unwrap().0;
+		t.extend(GenesisConfig::<Test>::default().build_storage().unwrap().0);
+		let mut ext: runtime_io::TestExternalities<Blake2Hasher> = t.into();
+		with_externalities(&mut ext, || {
+			assert_eq!(Migrations::storage_version(b"Dummy".to_vec()), 2);
+			assert_eq!(Migrations::last_spec_version(), 7);
+			Migrations::on_initialize(1);
+			assert_eq!(unhashed::get::<Vec<u32>>(APPLIED), None);
+		});
+	}
+
+	#[test]
+	fn runs_once_per_spec_version() {
+		with_externalities(&mut new_test_ext(), || {
+			Migrations::on_initialize(1);
+			<StorageVersion<Test>>::insert(b"Dummy".to_vec(), 0);
+			Migrations::on_initialize(2);
+			assert_eq!(unhashed::get::<Vec<u32>>(APPLIED), Some(vec![1, 2]));
+
+			<LastSpecVersion<Test>>::put(6);
+			Migrations::on_initialize(3);
+			assert_eq!(unhashed::get::<Vec<u32>>(APPLIED), Some(vec![1, 2, 1, 2]));
+		});
+	}
+}
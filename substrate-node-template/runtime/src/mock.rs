@@ -0,0 +1,54 @@
+//! Test runtime shared by the tests of the modules building on consensus: authorities are
+//! `UintAuthorityId`s and their seals are `TestSignature`s.
+
+use parity_codec::{Encode, Decode};
+use primitives::H256;
+use support::impl_outer_origin;
+use runtime_primitives::{
+	traits::{BlakeTwo256, IdentityLookup, Lazy, Verify},
+	testing::{Digest, DigestItem, Header, UintAuthorityId}
+};
+
+impl_outer_origin! {
+	pub enum Origin for Test {}
+}
+
+/// Signature of `message` by the authority `signer`.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, Debug)]
+pub struct TestSignature(pub u64, pub Vec<u8>);
+
+impl Verify for TestSignature {
+	type Signer = UintAuthorityId;
+
+	fn verify<L: Lazy<[u8]>>(&self, mut message: L, signer: &UintAuthorityId) -> bool {
+		self.0 == signer.0 && message.get() == &self.1[..]
+	}
+}
+
+#[derive(Clone, Eq, PartialEq)]
+pub struct Test;
+impl system::Trait for Test {
+	type Origin = Origin;
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type Digest = Digest;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = ();
+	type Log = DigestItem;
+}
+impl consensus::Trait for Test {
+	type Log = DigestItem;
+	type SessionKey = UintAuthorityId;
+	type InherentOfflineReport = ();
+}
+impl timestamp::Trait for Test {
+	type Moment = u64;
+	type OnTimestampSet = ();
+}
+impl aura::Trait for Test {
+	type HandleReport = ();
+}
@@ -0,0 +1,271 @@
+/// A runtime module removing authorities that stop authoring blocks.
+///
+/// Blocks are grouped into windows of `WindowLength` blocks. The author of every block is
+/// derived from its slot like Aura does, and at the end of a window every authority that did
+/// not author a block in it is reported offline. An authority offline for `MaxMissedWindows`
+/// consecutive windows is removed from the authority set, which the consensus module announces
+/// with an `AuthoritiesChange` log, so its slots are no longer skipped. The last authority is
+/// never removed.
+///
+/// A removed authority joins again with `rejoin`, signed by any account and carrying a
+/// signature of the authority key over `("rejoin", key, block it was removed at)`.
+
+use parity_codec::Encode;
+use rstd::prelude::*;
+use runtime_primitives::traits::{As, Verify, Zero};
+use support::{decl_module, decl_storage, decl_event, StorageValue, StorageMap, Parameter, dispatch::Result};
+use system::{ensure_signed, ensure_root};
+
+/// The module's configuration trait.
+pub trait Trait: consensus::Trait + aura::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+
+	/// The signature of authority keys.
+	type Signature: Parameter + Verify<Signer = Self::SessionKey>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as Online {
+		/// Number of blocks in a window.
+		pub WindowLength get(window_length) config(): T::BlockNumber;
+		/// Consecutive windows an authority may miss before it is removed.
+		pub MaxMissedWindows get(max_missed_windows) config(): u32;
+
+		/// Authorities that authored a block in the current window.
+		pub Authored get(authored): Vec<T::SessionKey>;
+		/// Consecutive windows an authority missed so far.
+		pub Missed get(missed): map T::SessionKey => u32;
+		/// Removed authorities, with the block they were removed at.
+		pub Removed get(removed_at): map T::SessionKey => Option<T::BlockNumber>;
+	}
+}
+
+decl_module! {
+	/// The module declaration.
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event<T>() = default;
+
+		/// Change the window length and the windows an authority may miss.
+		fn set_window(origin, window_length: T::BlockNumber, max_missed_windows: u32) -> Result {
+			ensure_root(origin)?;
+			if window_length.is_zero() || max_missed_windows == 0 {
+				return Err("window length and missed windows must be positive");
+			}
+			<WindowLength<T>>::put(window_length);
+			<MaxMissedWindows<T>>::put(max_missed_windows);
+			Ok(())
+		}
+
+		/// Add the removed authority `key` back to the authority set.
+		fn rejoin(origin, key: T::SessionKey, signature: <T as Trait>::Signature) -> Result {
+			ensure_signed(origin)?;
+			let removed_at = Self::removed_at(&key).ok_or("authority was not removed")?;
+			if !signature.verify(&(b"rejoin", &key, removed_at).encode()[..], &key) {
+				return Err("invalid signature");
+			}
+
+			<Removed<T>>::remove(&key);
+			let mut authorities = <consensus::Module<T>>::authorities();
+			authorities.push(key.clone());
+			<consensus::Module<T>>::set_authorities(&authorities);
+			// The authority has the rest of the window to author a block.
+			<Authored<T>>::mutate(|authored| authored.push(key.clone()));
+			Self::deposit_event(RawEvent::AuthorityRejoined(key));
+			Ok(())
+		}
+
+		fn on_initialize(n: T::BlockNumber) {
+			let window_length = Self::window_length();
+			if !n.is_zero() && !window_length.is_zero() && (n % window_length).is_zero() {
+				Self::end_window(n);
+			}
+		}
+
+		fn on_finalize(_n: T::BlockNumber) {
+			let slot_duration = <aura::Module<T>>::slot_duration();
+			let authorities = <consensus::Module<T>>::authorities();
+			if slot_duration == 0 || authorities.is_empty() {
+				return;
+			}
+			let slot = <timestamp::Module<T>>::now().as_() / slot_duration;
+			Self::note_author(authorities[(slot % authorities.len() as u64) as usize].clone());
+		}
+	}
+}
+
+decl_event!(
+	pub enum Event<T> where SessionKey = <T as consensus::Trait>::SessionKey {
+		/// An authority did not author a block in a window, for the given number of consecutive
+		/// windows.
+		AuthorityOffline(SessionKey, u32),
+		/// An authority was removed from the authority set for being offline.
+		AuthorityRemoved(SessionKey),
+		/// A removed authority joined the authority set again.
+		AuthorityRejoined(SessionKey),
+	}
+);
+
+impl<T: Trait> Module<T> {
+	/// Record that `author` authored a block in the current window.
+	pub fn note_author(author: T::SessionKey) {
+		if !Self::authored().contains(&author) {
+			<Authored<T>>::mutate(|authored| authored.push(author));
+		}
+	}
+
+	/// Report the authorities that did not author a block in the window ending before block
+	/// `n`, and remove those that missed too many windows.
+	fn end_window(n: T::BlockNumber) {
+		let authored = <Authored<T>>::take();
+		let max_missed = Self::max_missed_windows();
+		let mut remaining = Vec::new();
+		let mut removed = Vec::new();
+		for authority in <consensus::Module<T>>::authorities() {
+			if authored.contains(&authority) {
+				<Missed<T>>::remove(&authority);
+				remaining.push(authority);
+				continue;
+			}
+			let missed = Self::missed(&authority).saturating_add(1);
+			<Missed<T>>::insert(&authority, missed);
+			Self::deposit_event(RawEvent::AuthorityOffline(authority.clone(), missed));
+			if max_missed != 0 && missed >= max_missed {
+				removed.push(authority);
+			} else {
+				remaining.push(authority);
+			}
+		}
+
+		if removed.is_empty() || remaining.is_empty() {
+			return;
+		}
+		<consensus::Module<T>>::set_authorities(&remaining);
+		for authority in removed {
+			<Missed<T>>::remove(&authority);
+			<Removed<T>>::insert(&authority, n);
+			Self::deposit_event(RawEvent::AuthorityRemoved(authority));
+		}
+	}
+}
+
+/// tests for this module
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use parity_codec::Decode;
+	use runtime_io::with_externalities;
+	use primitives::Blake2Hasher;
+	use support::{assert_ok, assert_noop};
+	use runtime_primitives::{
+		BuildStorage,
+		traits::OnInitialize,
+		testing::UintAuthorityId
+	};
+	use crate::mock::{Origin, Test, TestSignature};
+
+	impl Trait for Test {
+		type Event = ();
+		type Signature = TestSignature;
+	}
+	type Online = Module<Test>;
+	type Consensus = consensus::Module<Test>;
+
+	fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+		let mut t = system::GenesisConfig::<Test>::default().build_storage().unwrap().0;
+		t.extend(consensus::GenesisConfig::<Test> {
+			code: vec![],
+			authorities: vec![UintAuthorityId(1), UintAuthorityId(2), UintAuthorityId(3)],
+		}.build_storage().unwrap().0);
+		t.extend(GenesisConfig::<Test> {
+			window_length: 10,
+			max_missed_windows: 2,
+		}.build_storage().unwrap().0);
+		t.into()
+	}
+
+	fn authorities(ids: &[u64]) -> Vec<UintAuthorityId> {
+		ids.iter().cloned().map(UintAuthorityId).collect()
+	}
+
+	/// Run a window in which only `authors` author blocks.
+	fn window(end: u64, authors: &[u64]) {
+		for author in authors {
+			Online::note_author(UintAuthorityId(*author));
+		}
+		Online::on_initialize(end);
+	}
+
+	#[test]
+	fn offline_authorities_are_removed_after_missed_windows() {
+		with_externalities(&mut new_test_ext(), || {
+			window(10, &[1, 2]);
+			assert_eq!(Online::missed(UintAuthorityId(3)), 1);
+			assert_eq!(Consensus::authorities(), authorities(&[1, 2, 3]));
+
+			// Windows end every 10 blocks only.
+			window(15, &[1, 2]);
+			assert_eq!(Online::missed(UintAuthorityId(3)), 1);
+
+			window(20, &[1]);
+			assert_eq!(Consensus::authorities(), authorities(&[1, 2]));
+			assert_eq!(Online::removed_at(UintAuthorityId(3)), Some(20));
+			assert_eq!(Online::missed(UintAuthorityId(2)), 1);
+
+			// Authoring again resets the count.
+			window(30, &[1, 2]);
+			assert_eq!(Online::missed(UintAuthorityId(2)), 0);
+			assert_eq!(Consensus::authorities(), authorities(&[1, 2]));
+		});
+	}
+
+	#[test]
+	fn last_authority_is_kept() {
+		with_externalities(&mut new_test_ext(), || {
+			window(10, &[]);
+			window(20, &[]);
+			assert_eq!(Consensus::authorities(), authorities(&[1, 2, 3]));
+		});
+	}
+
+	#[test]
+	fn removed_authorities_rejoin_with_a_signature() {
+		with_externalities(&mut new_test_ext(), || {
+			window(10, &[1, 2]);
+			window(20, &[1, 2]);
+			assert_eq!(Consensus::authorities(), authorities(&[1, 2]));
+
+			let key = UintAuthorityId(3);
+			let payload = (b"rejoin", &key, 20u64).encode();
+			assert_noop!(
+				Online::rejoin(Origin::signed(7), key.clone(), TestSignature(2, payload.clone())),
+				"invalid signature"
+			);
+			assert_noop!(
+				Online::rejoin(Origin::signed(7), UintAuthorityId(2), TestSignature(2, payload.clone())),
+				"authority was not removed"
+			);
+			assert_ok!(Online::rejoin(Origin::signed(7), key.clone(), TestSignature(3, payload.clone())));
+			assert_eq!(Consensus::authorities(), authorities(&[1, 2, 3]));
+			assert_eq!(Online::removed_at(key.clone()), None);
+
+			// The signature is bound to the removal it answers.
+			window(30, &[1, 2]);
+			window(40, &[1, 2]);
+			window(50, &[1, 2]);
+			assert_eq!(Online::removed_at(key.clone()), Some(50));
+			assert_noop!(Online::rejoin(Origin::signed(7), key, TestSignature(3, payload)), "invalid signature");
+		});
+	}
+
+	#[test]
+	fn only_root_sets_the_window() {
+		with_externalities(&mut new_test_ext(), || {
+			assert!(Online::set_window(Origin::signed(1), 5, 1).is_err());
+			assert_noop!(Online::set_window(Origin::ROOT, 0, 1), "window length and missed windows must be positive");
+			assert_ok!(Online::set_window(Origin::ROOT, 5, 1));
+			assert_eq!(Online::window_length(), 5);
+		});
+	}
+}
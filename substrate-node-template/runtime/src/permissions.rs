@@ -0,0 +1,271 @@
+/// A runtime module restricting a permissioned chain to allow-listed accounts and nodes.
+///
+/// While the allow-list is enforced, signed transactions are only accepted from allow-listed
+/// accounts and the admin, both in the pool and in blocks: the module is one of the
+/// `TransactionFilter`s of the validation module. Unsigned transactions, such as inherents and
+/// equivocation reports, are not affected. Make sure the sudo key is on the allow-list before
+/// enforcing it, or root can no longer be reached.
+///
+/// The module also keeps the IDs of the nodes allowed to join the network, as base58 strings,
+/// which nodes started with `--peer-allow-list` restrict their peers to.
+///
+/// The lists are managed by root, or by an admin account root appoints.
+
+use rstd::prelude::*;
+use runtime_primitives::traits::StaticLookup;
+use support::{decl_module, decl_storage, decl_event, StorageValue, StorageMap, dispatch::Result};
+use system::{ensure_root, RawOrigin};
+use crate::validation::TransactionFilter;
+
+/// Longest node ID accepted, base58 peer IDs being 46 to 53 characters long.
+const MAX_NODE_ID_LENGTH: usize = 128;
+
+/// The module's configuration trait.
+pub trait Trait: system::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as Permissions {
+		/// Whether only allow-listed accounts may sign transactions.
+		pub Enforced get(is_enforced) config(): bool;
+		/// Accounts allowed to sign transactions.
+		pub Allowed get(is_allowed) build(|config: &GenesisConfig<T>| {
+			config.allowed.iter().cloned().map(|who| (who, true)).collect::<Vec<_>>()
+		}): map T::AccountId => bool;
+		/// IDs of the nodes allowed to join the network.
+		pub Nodes get(nodes) config(): Vec<Vec<u8>>;
+		/// Account managing the lists along with root.
+		pub Admin get(admin): Option<T::AccountId>;
+	}
+	add_extra_genesis {
+		config(allowed): Vec<T::AccountId>;
+	}
+}
+
+decl_module! {
+	/// The module declaration.
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event<T>() = default;
+
+		/// Allow `who` to sign transactions.
+		fn allow(origin, who: <T::Lookup as StaticLookup>::Source) -> Result {
+			Self::ensure_admin(origin)?;
+			let who = T::Lookup::lookup(who)?;
+			<Allowed<T>>::insert(&who, true);
+			Self::deposit_event(RawEvent::AccountAllowed(who));
+			Ok(())
+		}
+
+		/// Remove `who` from the allow-list.
+		fn disallow(origin, who: <T::Lookup as StaticLookup>::Source) -> Result {
+			Self::ensure_admin(origin)?;
+			let who = T::Lookup::lookup(who)?;
+			<Allowed<T>>::remove(&who);
+			Self::deposit_event(RawEvent::AccountDisallowed(who));
+			Ok(())
+		}
+
+		/// Enforce the allow-list, or stop enforcing it.
+		fn set_enforced(origin, enforced: bool) -> Result {
+			Self::ensure_admin(origin)?;
+			<Enforced<T>>::put(enforced);
+			Self::deposit_event(RawEvent::EnforcementChanged(enforced));
+			Ok(())
+		}
+
+		/// Allow the node with the base58 ID `node` to join the network.
+		fn add_node(origin, node: Vec<u8>) -> Result {
+			Self::ensure_admin(origin)?;
+			if node.is_empty() || node.len() > MAX_NODE_ID_LENGTH {
+				return Err("invalid node ID length");
+			}
+			let mut nodes = Self::nodes();
+			if nodes.contains(&node) {
+				return Err("node is already allowed");
+			}
+			nodes.push(node.clone());
+			<Nodes<T>>::put(nodes);
+			Self::deposit_event(RawEvent::NodeAdded(node));
+			Ok(())
+		}
+
+		/// Remove the node with the ID `node` from the network.
+		fn remove_node(origin, node: Vec<u8>) -> Result {
+			Self::ensure_admin(origin)?;
+			let mut nodes = Self::nodes();
+			let len = nodes.len();
+			nodes.retain(|allowed| *allowed != node);
+			if nodes.len() == len {
+				return Err("node is not allowed");
+			}
+			<Nodes<T>>::put(nodes);
+			Self::deposit_event(RawEvent::NodeRemoved(node));
+			Ok(())
+		}
+
+		/// Let `admin` manage the lists along with root, or only root if `None`.
+		fn set_admin(origin, admin: Option<<T::Lookup as StaticLookup>::Source>) -> Result {
+			ensure_root(origin)?;
+			let admin = match admin {
+				Some(admin) => Some(T::Lookup::lookup(admin)?),
+				None => None,
+			};
+			match admin {
+				Some(ref admin) => <Admin<T>>::put(admin),
+				None => <Admin<T>>::kill(),
+			}
+			Self::deposit_event(RawEvent::AdminChanged(admin));
+			Ok(())
+		}
+	}
+}
+
+decl_event!(
+	pub enum Event<T> where AccountId = <T as system::Trait>::AccountId {
+		/// An account was added to the allow-list.
+		AccountAllowed(AccountId),
+		/// An account was removed from the allow-list.
+		AccountDisallowed(AccountId),
+		/// The allow-list is now enforced, or no longer.
+		EnforcementChanged(bool),
+		/// A node ID was added to the allowed nodes.
+		NodeAdded(Vec<u8>),
+		/// A node ID was removed from the allowed nodes.
+		NodeRemoved(Vec<u8>),
+		/// The admin changed.
+		AdminChanged(Option<AccountId>),
+	}
+);
+
+impl<T: Trait> Module<T> {
+	/// Whether `who` may sign transactions.
+	pub fn may_transact(who: &T::AccountId) -> bool {
+		!Self::is_enforced() || Self::is_allowed(who) || Self::admin().as_ref() == Some(who)
+	}
+
+	fn ensure_admin(origin: T::Origin) -> Result {
+		match origin.into() {
+			Some(RawOrigin::Root) => Ok(()),
+			Some(RawOrigin::Signed(ref who)) if Self::admin().as_ref() == Some(who) => Ok(()),
+			_ => Err("bad origin: expected root or the admin"),
+		}
+	}
+}
+
+impl<T: Trait, Call> TransactionFilter<T::AccountId, Call> for Module<T> {
+	fn filter(who: &T::AccountId, _: &Call) -> Result {
+		if !Self::may_transact(who) {
+			return Err("account is not on the allow-list");
+		}
+		Ok(())
+	}
+}
+
+/// tests for this module
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use runtime_io::with_externalities;
+	use primitives::{H256, Blake2Hasher};
+	use support::{impl_outer_origin, assert_ok, assert_noop};
+	use runtime_primitives::{
+		BuildStorage,
+		traits::{BlakeTwo256, IdentityLookup},
+		testing::{Digest, DigestItem, Header}
+	};
+
+	impl_outer_origin! {
+		pub enum Origin for Test {}
+	}
+
+	#[derive(Clone, Eq, PartialEq)]
+	pub struct Test;
+	impl system::Trait for Test {
+		type Origin = Origin;
+		type Index = u64;
+		type BlockNumber = u64;
+		type Hash = H256;
+		type Hashing = BlakeTwo256;
+		type Digest = Digest;
+		type AccountId = u64;
+		type Lookup = IdentityLookup<Self::AccountId>;
+		type Header = Header;
+		type Event = ();
+		type Log = DigestItem;
+	}
+	impl Trait for Test {
+		type Event = ();
+	}
+	type Permissions = Module<Test>;
+
+	fn new_test_ext(enforced: bool) -> runtime_io::TestExternalities<Blake2Hasher> {
+		let mut t = system::GenesisConfig::<Test>::default().build_storage().unwrap().0;
+		t.extend(GenesisConfig::<Test> {
+			enforced,
+			allowed: vec![1, 2],
+			nodes: vec![b"QmNode".to_vec()],
+		}.build_storage().unwrap().0);
+		t.into()
+	}
+
+	fn filter(who: u64) -> Result {
+		<Permissions as TransactionFilter<u64, ()>>::filter(&who, &())
+	}
+
+	#[test]
+	fn only_allowed_accounts_pass_when_enforced() {
+		with_externalities(&mut new_test_ext(true), || {
+			assert_ok!(filter(1));
+			assert_noop!(filter(3), "account is not on the allow-list");
+
+			assert_ok!(Permissions::allow(Origin::ROOT, 3));
+			assert_ok!(filter(3));
+			assert_ok!(Permissions::disallow(Origin::ROOT, 1));
+			assert_noop!(filter(1), "account is not on the allow-list");
+
+			assert_ok!(Permissions::set_enforced(Origin::ROOT, false));
+			assert_ok!(filter(1));
+		});
+	}
+
+	#[test]
+	fn nothing_is_filtered_unless_enforced() {
+		with_externalities(&mut new_test_ext(false), || {
+			assert_ok!(filter(3));
+		});
+	}
+
+	#[test]
+	fn admin_manages_the_lists() {
+		with_externalities(&mut new_test_ext(true), || {
+			assert_noop!(Permissions::allow(Origin::signed(5), 3), "bad origin: expected root or the admin");
+			assert!(Permissions::set_admin(Origin::signed(5), Some(5)).is_err());
+
+			assert_ok!(Permissions::set_admin(Origin::ROOT, Some(5)));
+			// The admin can always transact, so it cannot lock itself out.
+			assert_ok!(filter(5));
+			assert_ok!(Permissions::allow(Origin::signed(5), 3));
+			assert!(Permissions::is_allowed(3));
+
+			assert_ok!(Permissions::set_admin(Origin::ROOT, None));
+			assert_noop!(Permissions::disallow(Origin::signed(5), 3), "bad origin: expected root or the admin");
+		});
+	}
+
+	#[test]
+	fn nodes_are_added_and_removed() {
+		with_externalities(&mut new_test_ext(true), || {
+			assert_noop!(Permissions::add_node(Origin::ROOT, b"QmNode".to_vec()), "node is already allowed");
+			assert_noop!(Permissions::add_node(Origin::ROOT, vec![]), "invalid node ID length");
+			assert_ok!(Permissions::add_node(Origin::ROOT, b"QmOther".to_vec()));
+			assert_eq!(Permissions::nodes(), vec![b"QmNode".to_vec(), b"QmOther".to_vec()]);
+
+			assert_ok!(Permissions::remove_node(Origin::ROOT, b"QmNode".to_vec()));
+			assert_noop!(Permissions::remove_node(Origin::ROOT, b"QmNode".to_vec()), "node is not allowed");
+			assert_eq!(Permissions::nodes(), vec![b"QmOther".to_vec()]);
+		});
+	}
+}
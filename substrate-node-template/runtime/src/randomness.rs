@@ -0,0 +1,340 @@
+/// A runtime module providing randomness mixed from the VRF outputs of block authors.
+///
+/// Authorities register an sr25519 VRF key, signed with their authority key. The author of a
+/// block then evaluates its VRF over the slot and the seed of the parent block, and includes
+/// the output and its proof with a `set_vrf_output` inherent, the first extrinsic of the block.
+/// The digest of this Substrate version has no pre-runtime item for the author to put the
+/// output in, so the inherent deposits it as a `VrfOutput` log, along with the seed it was
+/// evaluated over: the header then carries the output the author chose, and light clients can
+/// check it without the body. The runtime cannot verify VRF proofs itself, so nodes check the
+/// proof against the key of the slot author when they import the block. They also reject
+/// blocks whose author has a registered key but which carry no output, so the output is as
+/// mandatory as a digest item.
+///
+/// The outputs of a window of `WindowLength` blocks are mixed with the previous seed into a new
+/// seed at the first block after the window. Authors cannot choose their outputs, only withhold
+/// their block, so unlike `System::random_seed` the seed cannot be ground by a single author.
+/// `random` derives values for a subject from the seed, along with the block the seed was mixed
+/// at: values are only unpredictable for commitments made before that block.
+
+#[cfg(feature = "std")]
+use serde_derive::Serialize;
+use parity_codec::{Encode, Decode};
+use rstd::prelude::*;
+use runtime_primitives::traits::{Hash, Verify, Zero};
+use runtime_primitives::transaction_validity::{TransactionValidity, TransactionLongevity};
+use support::{decl_module, decl_storage, decl_event, StorageValue, StorageMap, Parameter, dispatch::Result};
+use system::ensure_inherent;
+use inherents::{RuntimeString, InherentIdentifier, InherentData, ProvideInherent, MakeFatalError};
+
+/// The identifier of the VRF output inherent.
+pub const INHERENT_IDENTIFIER: InherentIdentifier = *b"vrfoutpt";
+
+/// The VRF output and proof of the block author.
+pub type InherentType = ([u8; 32], Vec<u8>);
+
+/// `TransactionValidity::Invalid` code of VRF key registrations with an invalid signature.
+pub const INVALID_REGISTRATION: i8 = -4;
+
+/// Number of blocks a registration stays in the pool.
+const REGISTRATION_LONGEVITY: TransactionLongevity = 64;
+
+/// The module's configuration trait.
+pub trait Trait: consensus::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+
+	/// The public key authors evaluate their VRF with.
+	type VrfKey: Parameter;
+
+	/// The signature of authority keys.
+	type Signature: Parameter + Verify<Signer = Self::SessionKey>;
+
+	/// The overarching log type.
+	type Log: From<Log<Self>> + Into<system::DigestItemOf<Self>>;
+}
+
+/// The log of this module.
+pub type Log<T> = RawLog<<T as system::Trait>::Hash>;
+
+/// A header digest item of this module.
+#[cfg_attr(feature = "std", derive(Serialize, Debug))]
+#[derive(Encode, Decode, PartialEq, Eq, Clone)]
+pub enum RawLog<Hash> {
+	/// The VRF output of the block author over the given seed, with its proof.
+	VrfOutput(Hash, [u8; 32], Vec<u8>),
+}
+
+impl<Hash: Clone> RawLog<Hash> {
+	/// The seed, VRF output and proof of the block author, if this log carries them.
+	pub fn as_vrf_output(&self) -> Option<(Hash, [u8; 32], Vec<u8>)> {
+		match self {
+			RawLog::VrfOutput(seed, output, proof) => Some((seed.clone(), *output, proof.clone())),
+		}
+	}
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as Randomness {
+		/// Number of blocks whose VRF outputs are mixed into a seed.
+		pub WindowLength get(window_length) config(): T::BlockNumber;
+		/// VRF keys of authorities.
+		pub VrfKeys get(vrf_key): map T::SessionKey => Option<T::VrfKey>;
+		/// VRF outputs of the current window.
+		pub Outputs get(outputs): Vec<[u8; 32]>;
+		/// The seed randomness is derived from, with the block it was mixed at.
+		pub Seed get(seed): (T::Hash, T::BlockNumber);
+		/// Whether the VRF output of the current block was set.
+		DidSet: bool;
+		/// The seed the VRF output of the current block is evaluated over, that of its parent.
+		BlockSeed: T::Hash;
+	}
+}
+
+decl_module! {
+	/// The module declaration.
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event<T>() = default;
+
+		/// Register `vrf_key` as the VRF key of the authority `key`. The signature of `key` is
+		/// over `("vrf_key", vrf_key, currently registered key)`, so it cannot be replayed once
+		/// the key changes.
+		fn register_vrf_key(origin, key: T::SessionKey, vrf_key: T::VrfKey, signature: <T as Trait>::Signature) -> Result {
+			ensure_inherent(origin)?;
+			Self::check_registration(&key, &vrf_key, &signature)?;
+			<VrfKeys<T>>::insert(&key, vrf_key.clone());
+			Self::deposit_event(RawEvent::VrfKeyRegistered(key, vrf_key));
+			Ok(())
+		}
+
+		/// Note the VRF output of the block author and deposit it in the header. The proof is
+		/// checked by nodes on import.
+		fn set_vrf_output(origin, output: [u8; 32], proof: Vec<u8>) -> Result {
+			ensure_inherent(origin)?;
+			if <DidSet<T>>::exists() {
+				return Err("VRF output must be set only once in a block");
+			}
+			<DidSet<T>>::put(true);
+			<Outputs<T>>::mutate(|outputs| outputs.push(output));
+			Self::deposit_log(RawLog::VrfOutput(<BlockSeed<T>>::get(), output, proof));
+			Ok(())
+		}
+
+		fn on_initialize(n: T::BlockNumber) {
+			let (seed, _) = Self::seed();
+			<BlockSeed<T>>::put(seed);
+			let window_length = Self::window_length();
+			if !n.is_zero() && !window_length.is_zero() && (n % window_length).is_zero() {
+				let outputs = <Outputs<T>>::take();
+				<Seed<T>>::put((T::Hashing::hash_of(&(seed, outputs)), n));
+			}
+		}
+
+		fn on_finalize(_n: T::BlockNumber) {
+			<DidSet<T>>::kill();
+			<BlockSeed<T>>::kill();
+		}
+	}
+}
+
+decl_event!(
+	pub enum Event<T> where SessionKey = <T as consensus::Trait>::SessionKey, VrfKey = <T as Trait>::VrfKey {
+		/// An authority registered a VRF key.
+		VrfKeyRegistered(SessionKey, VrfKey),
+	}
+);
+
+impl<T: Trait> Module<T> {
+	fn deposit_log(log: Log<T>) {
+		let log: <T as Trait>::Log = log.into();
+		<system::Module<T>>::deposit_log(log.into());
+	}
+
+	/// Randomness for `subject`, so that modules drawing from the same seed get independent
+	/// values. Only unpredictable for commitments made before `Seed`'s block.
+	pub fn random(subject: &[u8]) -> T::Hash {
+		let (seed, _) = Self::seed();
+		T::Hashing::hash_of(&(seed, subject))
+	}
+
+	/// Check the signature of a VRF key registration.
+	pub fn check_registration(
+		key: &T::SessionKey,
+		vrf_key: &T::VrfKey,
+		signature: &<T as Trait>::Signature,
+	) -> Result {
+		let current = Self::vrf_key(key);
+		if current.as_ref() == Some(vrf_key) {
+			return Err("VRF key already registered");
+		}
+		if !signature.verify(&(b"vrf_key", vrf_key, current).encode()[..], key) {
+			return Err("invalid signature");
+		}
+		Ok(())
+	}
+
+	/// Validity of a VRF key registration in the transaction pool, one per authority.
+	pub fn validate_registration(
+		key: &T::SessionKey,
+		vrf_key: &T::VrfKey,
+		signature: &<T as Trait>::Signature,
+	) -> TransactionValidity {
+		if Self::check_registration(key, vrf_key, signature).is_err() {
+			return TransactionValidity::Invalid(INVALID_REGISTRATION);
+		}
+		TransactionValidity::Valid {
+			priority: 0,
+			requires: Vec::new(),
+			provides: vec![(b"vrf_key", key).encode()],
+			longevity: REGISTRATION_LONGEVITY,
+		}
+	}
+}
+
+impl<T: Trait> ProvideInherent for Module<T> {
+	type Call = Call<T>;
+	type Error = MakeFatalError<RuntimeString>;
+	const INHERENT_IDENTIFIER: InherentIdentifier = INHERENT_IDENTIFIER;
+
+	fn create_inherent(data: &InherentData) -> Option<Self::Call> {
+		// Authors without a registered VRF key provide no output.
+		data.get_data::<InherentType>(&INHERENT_IDENTIFIER)
+			.ok()
+			.and_then(|data| data)
+			.map(|(output, proof)| Call::set_vrf_output(output, proof))
+	}
+}
+
+/// tests for this module
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use runtime_io::with_externalities;
+	use primitives::{H256, Blake2Hasher};
+	use support::{assert_ok, assert_noop};
+	use runtime_primitives::{
+		BuildStorage,
+		traits::{OnInitialize, OnFinalize},
+		testing::{DigestItem, UintAuthorityId}
+	};
+	use crate::mock::{Origin, Test, TestSignature};
+
+	impl From<RawLog<H256>> for DigestItem {
+		fn from(log: RawLog<H256>) -> Self {
+			DigestItem::Other(log.encode())
+		}
+	}
+
+	impl Trait for Test {
+		type Event = ();
+		type VrfKey = u64;
+		type Signature = TestSignature;
+		type Log = DigestItem;
+	}
+	type Randomness = Module<Test>;
+	type System = system::Module<Test>;
+
+	fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+		let mut t = system::GenesisConfig::<Test>::default().build_storage().unwrap().0;
+		t.extend(GenesisConfig::<Test> {
+			window_length: 10,
+		}.build_storage().unwrap().0);
+		t.into()
+	}
+
+	/// Run block `n`, authored with the VRF output `output` if any.
+	fn block(n: u64, output: Option<u8>) {
+		Randomness::on_initialize(n);
+		if let Some(output) = output {
+			assert_ok!(Randomness::set_vrf_output(Origin::INHERENT, [output; 32], vec![]));
+		}
+		Randomness::on_finalize(n);
+	}
+
+	#[test]
+	fn outputs_are_mixed_at_the_end_of_the_window() {
+		with_externalities(&mut new_test_ext(), || {
+			for n in 1..10 {
+				block(n, Some(n as u8));
+			}
+			assert_eq!(Randomness::outputs().len(), 9);
+			assert_eq!(Randomness::seed(), (H256::default(), 0));
+			let before = Randomness::random(b"subject");
+
+			block(10, Some(10));
+			let (seed, at) = Randomness::seed();
+			assert_eq!(at, 10);
+			assert_ne!(seed, H256::default());
+			assert_eq!(Randomness::outputs(), vec![[10; 32]]);
+			assert_ne!(Randomness::random(b"subject"), before);
+			assert_ne!(Randomness::random(b"subject"), Randomness::random(b"other subject"));
+		});
+	}
+
+	#[test]
+	fn output_is_deposited_with_the_seed_of_the_parent() {
+		with_externalities(&mut new_test_ext(), || {
+			for n in 1..10 {
+				block(n, Some(n as u8));
+			}
+			let (parent_seed, _) = Randomness::seed();
+
+			// The seed is mixed at the start of block 10, after its author evaluated its VRF.
+			Randomness::on_initialize(10);
+			assert_ok!(Randomness::set_vrf_output(Origin::INHERENT, [10; 32], vec![1, 2]));
+			assert_ne!(Randomness::seed().0, parent_seed);
+			let log = RawLog::VrfOutput(parent_seed, [10; 32], vec![1, 2]);
+			assert!(System::digest().logs.contains(&DigestItem::Other(log.encode())));
+			assert_eq!(log.as_vrf_output(), Some((parent_seed, [10; 32], vec![1, 2])));
+		});
+	}
+
+	#[test]
+	fn output_is_set_once_per_block_by_inherent() {
+		with_externalities(&mut new_test_ext(), || {
+			assert!(Randomness::set_vrf_output(Origin::signed(1), [1; 32], vec![]).is_err());
+			assert_ok!(Randomness::set_vrf_output(Origin::INHERENT, [1; 32], vec![]));
+			assert_noop!(
+				Randomness::set_vrf_output(Origin::INHERENT, [2; 32], vec![]),
+				"VRF output must be set only once in a block"
+			);
+			Randomness::on_finalize(1);
+			assert_ok!(Randomness::set_vrf_output(Origin::INHERENT, [2; 32], vec![]));
+		});
+	}
+
+	#[test]
+	fn vrf_keys_are_registered_with_a_signature() {
+		with_externalities(&mut new_test_ext(), || {
+			let key = UintAuthorityId(1);
+			let first = TestSignature(1, (b"vrf_key", 7u64, None::<u64>).encode());
+			assert_noop!(
+				Randomness::register_vrf_key(Origin::INHERENT, key.clone(), 7, TestSignature(2, first.1.clone())),
+				"invalid signature"
+			);
+			assert_ok!(Randomness::register_vrf_key(Origin::INHERENT, key.clone(), 7, first.clone()));
+			assert_eq!(Randomness::vrf_key(key.clone()), Some(7));
+			assert_noop!(
+				Randomness::register_vrf_key(Origin::INHERENT, key.clone(), 7, first.clone()),
+				"VRF key already registered"
+			);
+
+			let second = TestSignature(1, (b"vrf_key", 8u64, Some(7u64)).encode());
+			assert_ok!(Randomness::register_vrf_key(Origin::INHERENT, key.clone(), 8, second));
+			// The first registration cannot be replayed to go back to the old key.
+			assert_eq!(
+				Randomness::validate_registration(&key, &7, &first),
+				TransactionValidity::Invalid(INVALID_REGISTRATION)
+			);
+		});
+	}
+
+	#[test]
+	fn inherent_is_only_created_with_an_output() {
+		let mut data = InherentData::new();
+		assert!(Randomness::create_inherent(&data).is_none());
+		data.put_data(INHERENT_IDENTIFIER, &([3u8; 32], vec![1u8, 2])).unwrap();
+		assert_eq!(Randomness::create_inherent(&data), Some(Call::set_vrf_output([3; 32], vec![1, 2])));
+	}
+}
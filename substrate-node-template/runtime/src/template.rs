@@ -8,8 +8,12 @@
 /// For more guidance on Substrate modules, see the example module
 /// https://github.com/paritytech/substrate/blob/master/srml/example/src/lib.rs
 
+use rstd::prelude::*;
 use support::{decl_module, decl_storage, decl_event, StorageValue, dispatch::Result};
 use system::ensure_signed;
+use crate::migrations::{Migration, VersionedStorage};
+use crate::weights::{DispatchInfo, GetDispatchInfo};
+use crate::call_weights;
 
 /// The module's configuration trait.
 pub trait Trait: system::Trait {
@@ -26,6 +30,9 @@ decl_storage! {
 		// Here we are declaring a StorageValue, `Something` as a Option<u32>
 		// `get(something)` is the default getter which returns either the stored `u32` or `None` if nothing stored
 		Something get(something): Option<u32>;
+
+		// Block at which `Something` was last stored. Added in storage version 1.
+		LastUpdate get(last_update): Option<T::BlockNumber>;
 	}
 }
 
@@ -46,6 +53,7 @@ decl_module! {
 			// TODO: Code to execute when something calls this.
 			// For example: the following line stores the passed in u32 in the storage
 			<Something<T>>::put(something);
+			<LastUpdate<T>>::put(<system::Module<T>>::block_number());
 
 			// here we are raising the Something event
 			Self::deposit_event(RawEvent::SomethingStored(something, who));
@@ -54,6 +62,34 @@ decl_module! {
 	}
 }
 
+impl<T: Trait> GetDispatchInfo for Call<T> {
+	fn dispatch_info(&self) -> DispatchInfo {
+		match self {
+			Call::do_something(..) => DispatchInfo::normal(call_weights::template::DO_SOMETHING),
+			_ => DispatchInfo::normal(call_weights::DEFAULT),
+		}
+	}
+}
+
+impl<T: Trait> VersionedStorage for Module<T> {
+	const MODULE: &'static [u8] = b"TemplateModule";
+
+	fn migrations() -> Vec<Migration> {
+		vec![
+			Migration { version: 1, migrate: Self::migrate_to_v1 },
+		]
+	}
+}
+
+impl<T: Trait> Module<T> {
+	// Version 0 did not record when `Something` was stored, so we assume it was just now.
+	fn migrate_to_v1() {
+		if Self::something().is_some() && Self::last_update().is_none() {
+			<LastUpdate<T>>::put(<system::Module<T>>::block_number());
+		}
+	}
+}
+
 decl_event!(
 	pub enum Event<T> where AccountId = <T as system::Trait>::AccountId {
 		// Just a dummy event.
@@ -120,4 +156,31 @@ mod tests {
 			assert_eq!(TemplateModule::something(), Some(42));
 		});
 	}
+
+	#[test]
+	fn migrate_to_v1_records_last_update() {
+		with_externalities(&mut new_test_ext(), || {
+			// storage version 0 only had `Something`
+			<Something<Test>>::put(42);
+			assert_eq!(TemplateModule::last_update(), None);
+
+			<system::Module<Test>>::set_block_number(5);
+			for migration in TemplateModule::migrations() {
+				(migration.migrate)();
+			}
+			assert_eq!(TemplateModule::something(), Some(42));
+			assert_eq!(TemplateModule::last_update(), Some(5));
+		});
+	}
+
+	#[test]
+	fn migrate_to_v1_leaves_empty_storage_alone() {
+		with_externalities(&mut new_test_ext(), || {
+			for migration in TemplateModule::migrations() {
+				(migration.migrate)();
+			}
+			assert_eq!(TemplateModule::something(), None);
+			assert_eq!(TemplateModule::last_update(), None);
+		});
+	}
 }
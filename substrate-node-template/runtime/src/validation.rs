@@ -0,0 +1,253 @@
+/// A runtime module deciding which signed transactions the pool accepts and in which order.
+///
+/// Every signed transaction is run through the `Filter` of the runtime, a tuple of
+/// `TransactionFilter`s, both when it enters the pool and when it is applied in a block. The
+/// module itself is such a filter, rejecting transactions from accounts on its blacklist.
+///
+/// Transactions are prioritised by their fee per byte plus an optional tip. A tip is paid by
+/// wrapping a call in `with_tip`, which burns the tip before dispatching the call with the
+/// origin of the transaction. Like the fee, the tip is paid even if the call fails.
+
+use rstd::prelude::*;
+use runtime_primitives::traits::{As, Dispatchable, StaticLookup, Saturating};
+use runtime_primitives::transaction_validity::TransactionPriority;
+use support::{decl_module, decl_storage, decl_event, StorageMap, Parameter, dispatch::Result};
+use support::traits::{Currency, WithdrawReason, ExistenceRequirement};
+use system::{ensure_signed, ensure_root};
+
+/// `TransactionValidity::Invalid` code of transactions rejected by a filter.
+pub const FILTERED: i8 = -2;
+
+/// A check every signed transaction must pass.
+pub trait TransactionFilter<AccountId, Call> {
+	/// Accept or reject `call` signed by `who`.
+	fn filter(who: &AccountId, call: &Call) -> Result;
+}
+
+impl<AccountId, Call> TransactionFilter<AccountId, Call> for () {
+	fn filter(_: &AccountId, _: &Call) -> Result {
+		Ok(())
+	}
+}
+
+macro_rules! impl_filter_for_tuple {
+	($($filter:ident),+) => {
+		impl<AccountId, Call, $($filter: TransactionFilter<AccountId, Call>),+>
+			TransactionFilter<AccountId, Call> for ($($filter,)+)
+		{
+			fn filter(who: &AccountId, call: &Call) -> Result {
+				$( $filter::filter(who, call)?; )+
+				Ok(())
+			}
+		}
+	}
+}
+
+impl_filter_for_tuple!(A);
+impl_filter_for_tuple!(A, B);
+impl_filter_for_tuple!(A, B, C);
+impl_filter_for_tuple!(A, B, C, D);
+
+type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+
+/// The module's configuration trait.
+pub trait Trait: system::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+
+	/// The currency tips are paid in.
+	type Currency: Currency<Self::AccountId>;
+
+	/// The calls that can be tipped.
+	type Call: Parameter + Dispatchable<Origin = Self::Origin>;
+
+	/// The checks run on every signed transaction.
+	type Filter: TransactionFilter<Self::AccountId, <Self as Trait>::Call>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as Validation {
+		/// Accounts whose transactions are rejected.
+		pub Blacklisted get(is_blacklisted): map T::AccountId => bool;
+	}
+}
+
+decl_module! {
+	/// The module declaration.
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event<T>() = default;
+
+		/// Dispatch `call` with the origin of this transaction, paying `tip` to raise its priority.
+		fn with_tip(origin, #[compact] tip: BalanceOf<T>, call: Box<<T as Trait>::Call>) -> Result {
+			let who = ensure_signed(origin.clone())?;
+			// Dropping the imbalance burns the tip.
+			T::Currency::withdraw(&who, tip, WithdrawReason::TransactionPayment, ExistenceRequirement::KeepAlive)?;
+			call.dispatch(origin)
+		}
+
+		/// Reject all further transactions signed by `who`.
+		fn blacklist(origin, who: <T::Lookup as StaticLookup>::Source) -> Result {
+			ensure_root(origin)?;
+			let who = T::Lookup::lookup(who)?;
+			<Blacklisted<T>>::insert(&who, true);
+			Self::deposit_event(RawEvent::Blacklisted(who));
+			Ok(())
+		}
+
+		/// Accept transactions signed by `who` again.
+		fn unblacklist(origin, who: <T::Lookup as StaticLookup>::Source) -> Result {
+			ensure_root(origin)?;
+			let who = T::Lookup::lookup(who)?;
+			<Blacklisted<T>>::remove(&who);
+			Self::deposit_event(RawEvent::Unblacklisted(who));
+			Ok(())
+		}
+	}
+}
+
+decl_event!(
+	pub enum Event<T> where AccountId = <T as system::Trait>::AccountId {
+		/// An account was added to the blacklist.
+		Blacklisted(AccountId),
+		/// An account was removed from the blacklist.
+		Unblacklisted(AccountId),
+	}
+);
+
+impl<T: Trait> Module<T> {
+	/// Check a signed transaction before it enters the pool.
+	pub fn check(who: &T::AccountId, call: &<T as Trait>::Call, tip: BalanceOf<T>) -> Result {
+		T::Filter::filter(who, call)?;
+		if T::Currency::free_balance(who) < tip {
+			return Err("too few free funds to pay the tip");
+		}
+		Ok(())
+	}
+
+	/// Priority of a transaction paying `fee` for `len` bytes on top of `tip`.
+	pub fn priority(fee: BalanceOf<T>, tip: BalanceOf<T>, len: u32) -> TransactionPriority {
+		let per_byte = fee / <BalanceOf<T> as As<u64>>::sa(u64::from(len.max(1)));
+		per_byte.saturating_add(tip)
+			.min(<BalanceOf<T> as As<u64>>::sa(TransactionPriority::max_value()))
+			.as_()
+	}
+}
+
+impl<T: Trait> TransactionFilter<T::AccountId, <T as Trait>::Call> for Module<T> {
+	fn filter(who: &T::AccountId, _: &<T as Trait>::Call) -> Result {
+		if Self::is_blacklisted(who) {
+			return Err("account is blacklisted");
+		}
+		Ok(())
+	}
+}
+
+/// tests for this module
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use runtime_io::with_externalities;
+	use primitives::{H256, Blake2Hasher};
+	use support::{impl_outer_origin, assert_ok, assert_noop};
+	use runtime_primitives::{
+		BuildStorage,
+		traits::{BlakeTwo256, IdentityLookup},
+		testing::{Digest, DigestItem, Header}
+	};
+
+	impl_outer_origin! {
+		pub enum Origin for Test {}
+	}
+
+	#[derive(Clone, Eq, PartialEq)]
+	pub struct Test;
+	impl system::Trait for Test {
+		type Origin = Origin;
+		type Index = u64;
+		type BlockNumber = u64;
+		type Hash = H256;
+		type Hashing = BlakeTwo256;
+		type Digest = Digest;
+		type AccountId = u64;
+		type Lookup = IdentityLookup<Self::AccountId>;
+		type Header = Header;
+		type Event = ();
+		type Log = DigestItem;
+	}
+	impl balances::Trait for Test {
+		type Balance = u64;
+		type OnFreeBalanceZero = ();
+		type OnNewAccount = ();
+		type Event = ();
+		type TransactionPayment = ();
+		type TransferPayment = ();
+		type DustRemoval = ();
+	}
+	impl Trait for Test {
+		type Event = ();
+		type Currency = balances::Module<Test>;
+		type Call = Call<Test>;
+		type Filter = (Module<Test>,);
+	}
+	type Validation = Module<Test>;
+	type Balances = balances::Module<Test>;
+
+	fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+		let mut t = system::GenesisConfig::<Test>::default().build_storage().unwrap().0;
+		t.extend(balances::GenesisConfig::<Test> {
+			balances: vec![(1, 1_000)],
+			transaction_base_fee: 0,
+			transaction_byte_fee: 0,
+			existential_deposit: 0,
+			transfer_fee: 0,
+			creation_fee: 0,
+			vesting: vec![],
+		}.build_storage().unwrap().0);
+		t.into()
+	}
+
+	#[test]
+	fn blacklisted_accounts_are_filtered() {
+		with_externalities(&mut new_test_ext(), || {
+			let call = Call::unblacklist(2);
+			assert_ok!(Validation::check(&1, &call, 0));
+
+			assert!(Validation::blacklist(Origin::signed(2), 1).is_err());
+			assert_ok!(Validation::blacklist(Origin::ROOT, 1));
+			assert_noop!(Validation::check(&1, &call, 0), "account is blacklisted");
+
+			assert_ok!(Validation::unblacklist(Origin::ROOT, 1));
+			assert_ok!(Validation::check(&1, &call, 0));
+		});
+	}
+
+	#[test]
+	fn tips_must_be_affordable() {
+		with_externalities(&mut new_test_ext(), || {
+			let call = Call::unblacklist(2);
+			assert_ok!(Validation::check(&1, &call, 1_000));
+			assert_noop!(Validation::check(&1, &call, 1_001), "too few free funds to pay the tip");
+		});
+	}
+
+	#[test]
+	fn priority_is_fee_per_byte_plus_tip() {
+		with_externalities(&mut new_test_ext(), || {
+			assert_eq!(Validation::priority(1_000, 0, 100), 10);
+			assert_eq!(Validation::priority(1_000, 5, 100), 15);
+			assert_eq!(Validation::priority(1_000, 0, 0), 1_000);
+		});
+	}
+
+	#[test]
+	fn tip_is_paid_even_if_the_call_fails() {
+		with_externalities(&mut new_test_ext(), || {
+			// Only root may blacklist, so the wrapped call fails.
+			let call = Box::new(Call::blacklist(2));
+			assert!(Validation::with_tip(Origin::signed(1), 10, call).is_err());
+			assert_eq!(Balances::free_balance(&1), 990);
+			assert!(!Validation::is_blacklisted(&2));
+		});
+	}
+}
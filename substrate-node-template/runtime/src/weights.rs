@@ -0,0 +1,309 @@
+/// A runtime module bounding the weight and length of blocks, and charging fees by weight.
+///
+/// Every call declares a `DispatchInfo` through `GetDispatchInfo`. Before the `apply_extrinsic`
+/// runtime API applies an extrinsic its weight and length are added to the block's totals, and
+/// it is rejected with `ApplyError::FullBlock` if that would exceed `MaximumBlockWeight` or
+/// `MaximumBlockLength`.
+/// Normal extrinsics may only use `AvailableBlockRatio` percent of those limits, leaving the
+/// rest to operational extrinsics such as `sudo` or `set_code`.
+///
+/// The module is also the `MakePayment` of the `Executive`, charging a base fee, a fee per
+/// byte and a fee per unit of weight.
+///
+/// Limits and fees default to the values of the development chain spec, so chains that
+/// upgrade to this runtime, or start from a snapshot of a chain that had no limits, neither
+/// reject every extrinsic nor let them through for free.
+
+use rstd::prelude::*;
+use parity_codec::{Encode, Decode};
+use support::{decl_module, decl_storage, StorageValue, dispatch::Result};
+use support::traits::{Currency, MakePayment, WithdrawReason, ExistenceRequirement};
+use runtime_primitives::traits::{As, Saturating, CheckedSub};
+
+/// Weight of a call, roughly the nanoseconds it takes to execute on reference hardware.
+pub type Weight = u32;
+
+/// Whether a call competes for the normal share of a block or may use the reserved share.
+#[derive(Clone, Copy, PartialEq, Eq, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum DispatchClass {
+	/// Regular user transactions.
+	Normal,
+	/// Governance, sudo and inherent calls that must always fit in a block.
+	Operational,
+}
+
+impl Default for DispatchClass {
+	fn default() -> Self {
+		DispatchClass::Normal
+	}
+}
+
+/// The weight and class of a call.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct DispatchInfo {
+	/// Weight of the call.
+	pub weight: Weight,
+	/// Class of the call.
+	pub class: DispatchClass,
+}
+
+impl DispatchInfo {
+	/// A normal call of the given weight.
+	pub fn normal(weight: Weight) -> Self {
+		DispatchInfo { weight, class: DispatchClass::Normal }
+	}
+
+	/// An operational call of the given weight.
+	pub fn operational(weight: Weight) -> Self {
+		DispatchInfo { weight, class: DispatchClass::Operational }
+	}
+}
+
+/// Implemented by calls to declare their weight.
+pub trait GetDispatchInfo {
+	/// The weight and class of this call.
+	fn dispatch_info(&self) -> DispatchInfo;
+}
+
+type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+
+/// The module's configuration trait.
+pub trait Trait: system::Trait {
+	/// The currency fees are paid in.
+	type Currency: Currency<Self::AccountId>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as Weights {
+		/// Maximum total weight of the extrinsics in a block.
+		pub MaximumBlockWeight get(maximum_block_weight) config(): Weight = 2_000_000_000;
+		/// Maximum total encoded length of the extrinsics in a block.
+		pub MaximumBlockLength get(maximum_block_length) config(): u32 = 5 * 1024 * 1024;
+		/// Percentage of the block limits normal extrinsics may use.
+		pub AvailableBlockRatio get(available_block_ratio) config(): u32 = 75;
+
+		/// Fee charged for every transaction.
+		pub TransactionBaseFee get(transaction_base_fee) config(): BalanceOf<T> = As::sa(1);
+		/// Fee charged per byte of a transaction.
+		pub TransactionByteFee get(transaction_byte_fee) config(): BalanceOf<T>;
+		/// Fee charged per unit of weight of a transaction.
+		pub WeightFee get(weight_fee) config(): BalanceOf<T> = As::sa(1);
+
+		/// Weight of the extrinsics applied so far in this block.
+		BlockWeight get(block_weight): Weight;
+		/// Encoded length of the extrinsics applied so far in this block.
+		BlockLength get(block_length): u32;
+		/// Weight of the extrinsic being applied, read when its fee is charged.
+		ExtrinsicWeight get(extrinsic_weight): Weight;
+	}
+}
+
+decl_module! {
+	/// The module declaration.
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn on_finalize(_n: T::BlockNumber) {
+			<BlockWeight<T>>::kill();
+			<BlockLength<T>>::kill();
+			<ExtrinsicWeight<T>>::kill();
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// The total weight and length the extrinsics of `class` may fill a block up to.
+	pub fn limits(class: DispatchClass) -> (Weight, u32) {
+		let (weight, length) = (Self::maximum_block_weight(), Self::maximum_block_length());
+		match class {
+			DispatchClass::Operational => (weight, length),
+			DispatchClass::Normal => {
+				let ratio = u64::from(Self::available_block_ratio().min(100));
+				((u64::from(weight) * ratio / 100) as Weight, (u64::from(length) * ratio / 100) as u32)
+			},
+		}
+	}
+
+	/// Whether an extrinsic could fit in an empty block.
+	pub fn fits_empty_block(info: DispatchInfo, len: u32) -> bool {
+		let (max_weight, max_length) = Self::limits(info.class);
+		info.weight <= max_weight && len <= max_length
+	}
+
+	/// Account for an extrinsic in the current block, failing if it does not fit.
+	pub fn note_extrinsic(info: DispatchInfo, len: u32) -> Result {
+		let (max_weight, max_length) = Self::limits(info.class);
+		let weight = Self::block_weight().checked_add(info.weight)
+			.filter(|weight| *weight <= max_weight)
+			.ok_or("block weight limit reached")?;
+		let length = Self::block_length().checked_add(len)
+			.filter(|length| *length <= max_length)
+			.ok_or("block length limit reached")?;
+
+		<BlockWeight<T>>::put(weight);
+		<BlockLength<T>>::put(length);
+		<ExtrinsicWeight<T>>::put(info.weight);
+		Ok(())
+	}
+
+	/// Check that `who` can pay the fee of an extrinsic of the given weight and length, without
+	/// charging it.
+	pub fn ensure_can_pay(who: &T::AccountId, weight: Weight, len: u32) -> Result {
+		let fee = Self::compute_fee(weight, len);
+		let new_balance = T::Currency::free_balance(who).checked_sub(&fee)
+			.ok_or("too few free funds in account")?;
+		if new_balance < T::Currency::minimum_balance() {
+			return Err("payment would kill account");
+		}
+		T::Currency::ensure_can_withdraw(who, fee, WithdrawReason::TransactionPayment, new_balance)
+	}
+
+	/// The fee of an extrinsic of the given weight and encoded length.
+	pub fn compute_fee(weight: Weight, len: u32) -> BalanceOf<T> {
+		let byte_fee = Self::transaction_byte_fee().saturating_mul(<BalanceOf<T> as As<u64>>::sa(u64::from(len)));
+		let weight_fee = Self::weight_fee().saturating_mul(<BalanceOf<T> as As<u64>>::sa(u64::from(weight)));
+		Self::transaction_base_fee().saturating_add(byte_fee).saturating_add(weight_fee)
+	}
+}
+
+impl<T: Trait> MakePayment<T::AccountId> for Module<T> {
+	fn make_payment(transactor: &T::AccountId, encoded_len: usize) -> Result {
+		let fee = Self::compute_fee(Self::extrinsic_weight(), encoded_len as u32);
+		// Dropping the imbalance burns the fee.
+		T::Currency::withdraw(transactor, fee, WithdrawReason::TransactionPayment, ExistenceRequirement::KeepAlive)?;
+		Ok(())
+	}
+}
+
+/// tests for this module
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use runtime_io::with_externalities;
+	use primitives::{H256, Blake2Hasher};
+	use support::{impl_outer_origin, assert_ok, assert_noop};
+	use runtime_primitives::{
+		BuildStorage,
+		traits::{BlakeTwo256, IdentityLookup, OnFinalize},
+		testing::{Digest, DigestItem, Header}
+	};
+
+	impl_outer_origin! {
+		pub enum Origin for Test {}
+	}
+
+	#[derive(Clone, Eq, PartialEq)]
+	pub struct Test;
+	impl system::Trait for Test {
+		type Origin = Origin;
+		type Index = u64;
+		type BlockNumber = u64;
+		type Hash = H256;
+		type Hashing = BlakeTwo256;
+		type Digest = Digest;
+		type AccountId = u64;
+		type Lookup = IdentityLookup<Self::AccountId>;
+		type Header = Header;
+		type Event = ();
+		type Log = DigestItem;
+	}
+	impl balances::Trait for Test {
+		type Balance = u64;
+		type OnFreeBalanceZero = ();
+		type OnNewAccount = ();
+		type Event = ();
+		type TransactionPayment = ();
+		type TransferPayment = ();
+		type DustRemoval = ();
+	}
+	impl Trait for Test {
+		type Currency = balances::Module<Test>;
+	}
+	type Weights = Module<Test>;
+	type Balances = balances::Module<Test>;
+
+	fn new_test_ext() -> runtime_io::TestExternalities<Blake2Hasher> {
+		let mut t = system::GenesisConfig::<Test>::default().build_storage().unwrap().0;
+		t.extend(balances::GenesisConfig::<Test> {
+			balances: vec![(1, 1_000)],
+			transaction_base_fee: 0,
+			transaction_byte_fee: 0,
+			existential_deposit: 0,
+			transfer_fee: 0,
+			creation_fee: 0,
+			vesting: vec![],
+		}.build_storage().unwrap().0);
+		t.extend(GenesisConfig::<Test> {
+			maximum_block_weight: 1_000,
+			maximum_block_length: 100,
+			available_block_ratio: 75,
+			transaction_base_fee: 1,
+			transaction_byte_fee: 2,
+			weight_fee: 3,
+		}.build_storage().unwrap().0);
+		t.into()
+	}
+
+	#[test]
+	fn normal_extrinsics_use_available_ratio() {
+		with_externalities(&mut new_test_ext(), || {
+			assert_eq!(Weights::limits(DispatchClass::Normal), (750, 75));
+			assert_ok!(Weights::note_extrinsic(DispatchInfo::normal(700), 10));
+			assert_noop!(Weights::note_extrinsic(DispatchInfo::normal(100), 10), "block weight limit reached");
+			assert_noop!(Weights::note_extrinsic(DispatchInfo::normal(10), 70), "block length limit reached");
+			assert_eq!(Weights::block_weight(), 700);
+		});
+	}
+
+	#[test]
+	fn operational_extrinsics_use_reserved_share() {
+		with_externalities(&mut new_test_ext(), || {
+			assert_ok!(Weights::note_extrinsic(DispatchInfo::normal(750), 10));
+			assert_ok!(Weights::note_extrinsic(DispatchInfo::operational(250), 10));
+			assert_noop!(Weights::note_extrinsic(DispatchInfo::operational(1), 10), "block weight limit reached");
+		});
+	}
+
+	#[test]
+	fn totals_reset_on_finalize() {
+		with_externalities(&mut new_test_ext(), || {
+			assert_ok!(Weights::note_extrinsic(DispatchInfo::normal(700), 10));
+			Weights::on_finalize(1);
+			assert_eq!(Weights::block_weight(), 0);
+			assert_eq!(Weights::block_length(), 0);
+		});
+	}
+
+	#[test]
+	fn limits_and_fees_default_when_not_configured() {
+		let mut t: runtime_io::TestExternalities<Blake2Hasher> =
+			system::GenesisConfig::<Test>::default().build_storage().unwrap().0.into();
+		with_externalities(&mut t, || {
+			assert_eq!(Weights::limits(DispatchClass::Operational), (2_000_000_000, 5 * 1024 * 1024));
+			assert_ok!(Weights::note_extrinsic(DispatchInfo::normal(1_000), 100));
+			assert_eq!(Weights::compute_fee(10, 5), 1 + 10);
+		});
+	}
+
+	#[test]
+	fn checks_payment_without_charging() {
+		with_externalities(&mut new_test_ext(), || {
+			assert_ok!(Weights::ensure_can_pay(&1, 10, 5));
+			assert_eq!(Balances::free_balance(&1), 1_000);
+			assert_eq!(Weights::extrinsic_weight(), 0);
+			assert_noop!(Weights::ensure_can_pay(&1, 400, 5), "too few free funds in account");
+			assert_noop!(Weights::ensure_can_pay(&2, 0, 0), "too few free funds in account");
+		});
+	}
+
+	#[test]
+	fn fee_depends_on_weight_and_length() {
+		with_externalities(&mut new_test_ext(), || {
+			assert_eq!(Weights::compute_fee(10, 5), 1 + 2 * 5 + 3 * 10);
+			assert_ok!(Weights::note_extrinsic(DispatchInfo::normal(10), 5));
+			assert_ok!(Weights::make_payment(&1, 5));
+			assert_eq!(Balances::free_balance(&1), 1_000 - 41);
+		});
+	}
+}
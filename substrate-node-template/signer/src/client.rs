@@ -0,0 +1,272 @@
+//! A blocking JSON-RPC client submitting transactions to a node over WebSocket.
+
+use std::collections::HashMap;
+use std::sync::{Arc, mpsc};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use log::warn;
+use parity_codec::{Decode, Encode};
+use parking_lot::Mutex;
+use primitives::{blake2_256, Bytes};
+use primitives::hexdisplay::HexDisplay;
+use runtime_primitives::traits::Header as HeaderT;
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+use node_template_runtime::{AccountId, BlockNumber, Hash, Header, Nonce, UncheckedExtrinsic};
+use crate::Era;
+use crate::error::{Error, ErrorKind, Result};
+
+/// Status of a watched transaction, as notified by the node.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Status {
+	/// Waiting in the pool for a transaction it depends on.
+	Future,
+	/// Ready to be included in a block.
+	Ready,
+	/// Included in the block of the given hash.
+	Finalized(Hash),
+	/// Replaced by the transaction of the given hash.
+	Usurped(Hash),
+	/// Broadcast to the given peers.
+	Broadcast(Vec<String>),
+	/// Dropped from the pool, e.g. because it was full.
+	Dropped,
+	/// Found invalid.
+	Invalid,
+}
+
+impl Status {
+	/// Whether no further status will follow.
+	pub fn is_final(&self) -> bool {
+		match self {
+			Status::Finalized(_) | Status::Usurped(_) | Status::Dropped | Status::Invalid => true,
+			Status::Future | Status::Ready | Status::Broadcast(_) => false,
+		}
+	}
+}
+
+/// Iterator over the statuses of a submitted transaction, ending after a final one.
+pub struct Watcher {
+	notifications: mpsc::Receiver<Value>,
+	done: bool,
+}
+
+impl Iterator for Watcher {
+	type Item = Status;
+
+	fn next(&mut self) -> Option<Status> {
+		while !self.done {
+			let notification = self.notifications.recv().ok()?;
+			match serde_json::from_value::<Status>(notification) {
+				Ok(status) => {
+					self.done = status.is_final();
+					return Some(status);
+				},
+				Err(e) => warn!("Unable to decode transaction status: {}", e),
+			}
+		}
+		None
+	}
+}
+
+/// A connection to the WebSocket RPC server of a node.
+pub struct Client {
+	out: ws::Sender,
+	shared: Arc<Mutex<Shared>>,
+	next_id: AtomicU64,
+}
+
+impl Client {
+	/// Connect to the node at `url`, e.g. `ws://127.0.0.1:9944`.
+	pub fn connect(url: &str) -> Result<Self> {
+		let shared = Arc::new(Mutex::new(Shared::default()));
+		let (opened, open) = mpsc::channel();
+
+		let url = url.to_string();
+		let handler_shared = shared.clone();
+		thread::spawn(move || {
+			let result = ws::connect(url, |out| Handler {
+				out,
+				opened: Some(opened.clone()),
+				shared: handler_shared.clone(),
+			});
+			if let Err(e) = result {
+				let _ = opened.send(Err(e.into()));
+			}
+		});
+
+		let out = open.recv().map_err(|_| ErrorKind::Disconnected)??;
+		Ok(Client { out, shared, next_id: AtomicU64::new(0) })
+	}
+
+	/// Call `method` with `params`, waiting for its result.
+	pub fn request(&self, method: &str, params: Value) -> Result<Value> {
+		let (reply, response) = mpsc::channel();
+		self.send(method, params, Pending::Call(reply))?;
+		response.recv().map_err(|_| ErrorKind::Disconnected)?
+	}
+
+	/// Hash of the genesis block, signed over by immortal transactions.
+	pub fn genesis_hash(&self) -> Result<Hash> {
+		self.block_hash(0)
+	}
+
+	/// Hash of the best block numbered `number`.
+	pub fn block_hash(&self, number: BlockNumber) -> Result<Hash> {
+		let hash = self.request("chain_getBlockHash", json!([number]))?;
+		serde_json::from_value::<Option<Hash>>(hash)?
+			.ok_or_else(|| format!("Unknown block {}", number).into())
+	}
+
+	/// Header of the best block.
+	pub fn best_header(&self) -> Result<Header> {
+		Ok(serde_json::from_value(self.request("chain_getHeader", json!([]))?)?)
+	}
+
+	/// An era of `period` blocks starting at the best block, along with the hash of the block it
+	/// is counted from.
+	pub fn mortal_era(&self, period: u64) -> Result<(Era, Hash)> {
+		let header = self.best_header()?;
+		let era = Era::mortal(period, header.number);
+		let birth = era.birth(header.number);
+		let checkpoint = if birth == header.number { header.hash() } else { self.block_hash(birth)? };
+		Ok((era, checkpoint))
+	}
+
+	/// The nonce the next transaction of `who` must use, as of the best block.
+	pub fn account_nonce(&self, who: &AccountId) -> Result<Nonce> {
+		// Key of `System::AccountNonce`, a map hashed with blake2.
+		let mut key = b"System AccountNonce".to_vec();
+		who.encode_to(&mut key);
+		let value = self.request("state_getStorage", json!([hex(&blake2_256(&key))]))?;
+		match serde_json::from_value::<Option<Bytes>>(value)? {
+			Some(bytes) => Nonce::decode(&mut &bytes.0[..])
+				.ok_or_else(|| "Unable to decode account nonce".into()),
+			None => Ok(0),
+		}
+	}
+
+	/// Submit a transaction to the pool of the node, returning its hash.
+	pub fn submit(&self, tx: &UncheckedExtrinsic) -> Result<Hash> {
+		let hash = self.request("author_submitExtrinsic", json!([hex(&tx.encode())]))?;
+		Ok(serde_json::from_value(hash)?)
+	}
+
+	/// Submit a transaction to the pool of the node, following its status until it is included
+	/// in a block or leaves the pool.
+	pub fn submit_and_watch(&self, tx: &UncheckedExtrinsic) -> Result<Watcher> {
+		let (reply, response) = mpsc::channel();
+		let (notify, notifications) = mpsc::channel();
+		self.send("author_submitAndWatchExtrinsic", json!([hex(&tx.encode())]), Pending::Subscribe(reply, notify))?;
+		response.recv().map_err(|_| ErrorKind::Disconnected)??;
+		Ok(Watcher { notifications, done: false })
+	}
+
+	fn send(&self, method: &str, params: Value, pending: Pending) -> Result<()> {
+		let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+		self.shared.lock().pending.insert(id, pending);
+		let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+		if let Err(e) = self.out.send(request.to_string()) {
+			self.shared.lock().pending.remove(&id);
+			return Err(e.into());
+		}
+		Ok(())
+	}
+}
+
+impl Drop for Client {
+	fn drop(&mut self) {
+		let _ = self.out.close(ws::CloseCode::Normal);
+	}
+}
+
+/// A request waiting for its response.
+enum Pending {
+	Call(mpsc::Sender<Result<Value>>),
+	/// A subscription, whose notifications go to the second sender once it is accepted.
+	Subscribe(mpsc::Sender<Result<Value>>, mpsc::Sender<Value>),
+}
+
+/// Requests and subscriptions shared between the client and the connection thread.
+#[derive(Default)]
+struct Shared {
+	pending: HashMap<u64, Pending>,
+	/// Subscriptions by the JSON encoding of their id.
+	subscriptions: HashMap<String, mpsc::Sender<Value>>,
+}
+
+impl Shared {
+	fn dispatch(&mut self, message: Value) {
+		if let Some(id) = message.get("id").and_then(Value::as_u64) {
+			let result = match message.get("error") {
+				Some(error) => Err(ErrorKind::Rpc(error.clone()).into()),
+				None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+			};
+			match self.pending.remove(&id) {
+				Some(Pending::Call(reply)) => {
+					let _ = reply.send(result);
+				},
+				Some(Pending::Subscribe(reply, notify)) => {
+					// Registered before replying, so that no notification is missed.
+					if let Ok(subscription) = &result {
+						self.subscriptions.insert(subscription.to_string(), notify);
+					}
+					let _ = reply.send(result);
+				},
+				None => warn!("Response to unknown request {}", id),
+			}
+		} else if let Some(params) = message.get("params") {
+			let subscription = params.get("subscription").map(Value::to_string).unwrap_or_default();
+			let result = params.get("result").cloned().unwrap_or(Value::Null);
+			let delivered = self.subscriptions.get(&subscription).map(|notify| notify.send(result).is_ok());
+			if delivered == Some(false) {
+				// The watcher was dropped.
+				self.subscriptions.remove(&subscription);
+			}
+		}
+	}
+}
+
+struct Handler {
+	out: ws::Sender,
+	opened: Option<mpsc::Sender<Result<ws::Sender>>>,
+	shared: Arc<Mutex<Shared>>,
+}
+
+impl ws::Handler for Handler {
+	fn on_open(&mut self, _: ws::Handshake) -> ws::Result<()> {
+		if let Some(opened) = self.opened.take() {
+			let _ = opened.send(Ok(self.out.clone()));
+		}
+		Ok(())
+	}
+
+	fn on_message(&mut self, message: ws::Message) -> ws::Result<()> {
+		match serde_json::from_str(message.as_text()?) {
+			Ok(message) => self.shared.lock().dispatch(message),
+			Err(e) => warn!("Unable to decode RPC message: {}", e),
+		}
+		Ok(())
+	}
+
+	fn on_close(&mut self, _: ws::CloseCode, _: &str) {
+		// Dropping the senders wakes up everyone waiting with `Disconnected`.
+		let mut shared = self.shared.lock();
+		shared.pending.clear();
+		shared.subscriptions.clear();
+	}
+
+	fn on_error(&mut self, e: ws::Error) {
+		match self.opened.take() {
+			Some(opened) => {
+				let _ = opened.send(Err(Error::from(e)));
+			},
+			None => warn!("RPC connection error: {}", e),
+		}
+	}
+}
+
+fn hex(bytes: &[u8]) -> String {
+	format!("0x{}", HexDisplay::from(&bytes))
+}
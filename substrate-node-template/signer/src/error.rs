@@ -0,0 +1,20 @@
+//! Errors of the RPC client.
+
+error_chain! {
+	foreign_links {
+		Ws(::ws::Error) #[doc="WebSocket error"];
+		Json(::serde_json::Error) #[doc="JSON error"];
+	}
+	errors {
+		/// The node answered a request with an error.
+		Rpc(error: ::serde_json::Value) {
+			description("RPC error"),
+			display("RPC error: {}", error),
+		}
+		/// The connection to the node was closed.
+		Disconnected {
+			description("Disconnected"),
+			display("Disconnected from the node"),
+		}
+	}
+}
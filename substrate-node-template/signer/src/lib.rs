@@ -0,0 +1,98 @@
+//! Offline construction and signing of transactions for the node template runtime.
+//!
+//! Transactions are built from `Call`s of `node_template_runtime` and signed with an sr25519
+//! key pair, without access to a node. A `Client` connected to a node over WebSocket can then
+//! submit them and follow their status in the transaction pool:
+//!
+//! ```no_run
+//! use node_template_runtime::{BalancesCall, Call};
+//! use node_template_signer::{Client, sign};
+//! use primitives::{Pair, sr25519};
+//!
+//! let client = Client::connect("ws://127.0.0.1:9944").unwrap();
+//! let alice = sr25519::Pair::from_string("//Alice", None).unwrap();
+//! let bob = sr25519::Pair::from_string("//Bob", None).unwrap();
+//!
+//! let (era, checkpoint) = client.mortal_era(64).unwrap();
+//! let nonce = client.account_nonce(&alice.public()).unwrap();
+//! let call = Call::Balances(BalancesCall::transfer(bob.public().into(), 1_000));
+//! let tx = sign(call, &alice, nonce, era, checkpoint);
+//! for status in client.submit_and_watch(&tx).unwrap() {
+//!     println!("{:?}", status);
+//! }
+//! ```
+
+#[macro_use]
+extern crate error_chain;
+
+mod client;
+pub mod error;
+
+pub use client::{Client, Status, Watcher};
+pub use error::{Error, ErrorKind, Result};
+pub use runtime_primitives::generic::Era;
+
+use parity_codec::{Compact, Encode};
+use primitives::{blake2_256, sr25519, Pair};
+use node_template_runtime::{Address, Call, Hash, Nonce, UncheckedExtrinsic};
+
+/// Sign `call` as the transaction of index `nonce` of the account of `signer`.
+///
+/// `checkpoint` is the hash of the block the era is counted from: the genesis hash for
+/// `Era::Immortal`, and the hash of the block numbered `era.birth(current)` for mortal eras.
+/// `Client::mortal_era` and `Client::genesis_hash` provide both.
+pub fn sign(call: Call, signer: &sr25519::Pair, nonce: Nonce, era: Era, checkpoint: Hash) -> UncheckedExtrinsic {
+	let payload = (Compact(nonce), call, era, checkpoint);
+	// Payloads longer than 256 bytes are hashed before being signed, as the runtime expects.
+	let signature = payload.using_encoded(|encoded| if encoded.len() > 256 {
+		signer.sign(&blake2_256(encoded)[..])
+	} else {
+		signer.sign(encoded)
+	});
+	let (_, call, era, _) = payload;
+	UncheckedExtrinsic::new_signed(nonce, call, Address::Id(signer.public()), signature, era)
+}
+
+/// Build an unsigned transaction, for calls that are valid without a sender.
+pub fn unsigned(call: Call) -> UncheckedExtrinsic {
+	UncheckedExtrinsic::new_unsigned(call)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use parity_codec::Decode;
+	use runtime_primitives::traits::Verify;
+	use node_template_runtime::BalancesCall;
+
+	fn transfer() -> Call {
+		let bob = sr25519::Pair::from_string("//Bob", None).unwrap();
+		Call::Balances(BalancesCall::transfer(bob.public().into(), 1_000))
+	}
+
+	#[test]
+	fn signed_transaction_verifies_against_payload() {
+		let alice = sr25519::Pair::from_string("//Alice", None).unwrap();
+		let era = Era::mortal(64, 100);
+		let checkpoint = Hash::repeat_byte(7);
+
+		let tx = sign(transfer(), &alice, 5, era, checkpoint);
+		let tx = UncheckedExtrinsic::decode(&mut &tx.encode()[..]).unwrap();
+		let (address, signature, nonce, tx_era) = tx.signature.clone().unwrap();
+
+		assert_eq!(address, Address::Id(alice.public()));
+		assert_eq!(nonce.0, 5);
+		assert_eq!(tx_era, era);
+		let payload = (nonce, tx.function, tx_era, checkpoint).encode();
+		assert!(signature.verify(&payload[..], &alice.public()));
+		// A different checkpoint, e.g. another chain, invalidates the signature.
+		let payload = (Compact(5 as Nonce), transfer(), era, Hash::repeat_byte(8)).encode();
+		assert!(!signature.verify(&payload[..], &alice.public()));
+	}
+
+	#[test]
+	fn unsigned_transaction_has_no_signature() {
+		assert!(unsigned(transfer()).signature.is_none());
+	}
+}
@@ -0,0 +1,136 @@
+//! Authority set tracking of light clients.
+//!
+//! Light clients only sync headers, so instead of trusting the authority set a full node
+//! reports for every block, `AuthorityTracker` follows the `AuthoritiesChange` logs the
+//! consensus module deposits whenever the set changes, starting from the set of the genesis
+//! storage. The set in force after every imported block is kept in the aux storage of the
+//! client, keyed by block hash so that forks keep their own set, and the seal of every block
+//! is checked against the set of its parent before the block is imported.
+
+use std::sync::Arc;
+use log::warn;
+use parity_codec::{Decode, Encode};
+use consensus::CompatibleDigestItem;
+use consensus_common::{BlockImport, Error as ConsensusError, ErrorKind as ConsensusErrorKind, ImportBlock, ImportResult};
+use primitives::{ed25519, Pair};
+use primitives::storage::well_known_keys;
+use runtime_primitives::StorageOverlay;
+use runtime_primitives::traits::{DigestItem, Header as HeaderT};
+use substrate_client::backend::AuxStore;
+use node_template_runtime::{AuthorityId, Hash, opaque::Block};
+
+/// Prefix of the aux storage keys the authority set after each block is stored under.
+const AUTHORITIES_KEY: &[u8] = b"node_template:authorities:";
+
+fn aux_key(hash: &Hash) -> Vec<u8> {
+	let mut key = AUTHORITIES_KEY.to_vec();
+	key.extend_from_slice(hash.as_bytes());
+	key
+}
+
+/// The authority set stored by the consensus module in `storage`.
+pub fn genesis_authorities(storage: &StorageOverlay) -> Result<Vec<AuthorityId>, String> {
+	let count = storage.get(well_known_keys::AUTHORITY_COUNT)
+		.and_then(|count| u32::decode(&mut &count[..]))
+		.ok_or_else(|| "Genesis storage holds no authority count".to_string())?;
+	(0..count)
+		.map(|i| {
+			let mut key = well_known_keys::AUTHORITY_PREFIX.to_vec();
+			i.encode_to(&mut key);
+			storage.get(&key)
+				.and_then(|authority| AuthorityId::decode(&mut &authority[..]))
+				.ok_or_else(|| format!("Genesis storage holds no authority #{}", i))
+		})
+		.collect()
+}
+
+/// Block import checking the seal of imported blocks against the authority set tracked from
+/// their headers, before passing them on to `inner`.
+pub struct AuthorityTracker<I, C> {
+	inner: Arc<I>,
+	client: Arc<C>,
+}
+
+impl<I, C: AuxStore> AuthorityTracker<I, C> {
+	/// Wrap `inner`, keeping the authority sets in the aux storage of `client`. The set of the
+	/// genesis block is recorded unless it already is.
+	pub fn new(
+		inner: Arc<I>,
+		client: Arc<C>,
+		genesis_hash: Hash,
+		genesis_authorities: Vec<AuthorityId>,
+	) -> Result<Self, String> {
+		let tracker = AuthorityTracker { inner, client };
+		if tracker.authorities(&genesis_hash).is_err() {
+			tracker.record(&genesis_hash, &genesis_authorities)?;
+		}
+		Ok(tracker)
+	}
+
+	/// The authority set in force after the block `hash`.
+	fn authorities(&self, hash: &Hash) -> Result<Vec<AuthorityId>, String> {
+		self.client.get_aux(&aux_key(hash))
+			.map_err(|e| format!("{:?}", e))?
+			.and_then(|authorities| Decode::decode(&mut &authorities[..]))
+			.ok_or_else(|| format!("Unknown authority set after block {}", hash))
+	}
+
+	fn record(&self, hash: &Hash, authorities: &[AuthorityId]) -> Result<(), String> {
+		self.client.insert_aux(&[(&aux_key(hash)[..], &authorities.encode()[..])], &[])
+			.map_err(|e| format!("Unable to store the authority set: {:?}", e))
+	}
+
+	/// Check the seal of `block` against the authority set of its parent, returning the set in
+	/// force after it.
+	fn check(&self, block: &ImportBlock<Block>) -> Result<Vec<AuthorityId>, String> {
+		let (slot, signature) = block.post_digests.iter()
+			.filter_map(|item| CompatibleDigestItem::<ed25519::Pair>::as_aura_seal(item))
+			.next()
+			.ok_or_else(|| "Block without a seal".to_string())?;
+
+		let authorities = self.authorities(&block.header.parent_hash)?;
+		if authorities.is_empty() {
+			return Err("No authorities".into());
+		}
+		let author = &authorities[(slot % authorities.len() as u64) as usize];
+		// Aura seals the slot along with the hash of the header without its seal.
+		let sealed = (slot, block.header.hash()).encode();
+		if !ed25519::Pair::verify(&signature, &sealed[..], author) {
+			return Err(format!("Seal of slot {} not signed by its author {}", slot, author));
+		}
+
+		Ok(block.header.digest.logs.iter()
+			.filter_map(|item| item.as_authorities_change())
+			.next()
+			.map(|authorities| authorities.to_vec())
+			.unwrap_or(authorities))
+	}
+}
+
+impl<I, C> BlockImport<Block> for AuthorityTracker<I, C> where
+	I: BlockImport<Block, Error = ConsensusError>,
+	C: AuxStore,
+{
+	type Error = ConsensusError;
+
+	fn check_block(&self, hash: Hash, parent_hash: Hash) -> Result<ImportResult, Self::Error> {
+		self.inner.check_block(hash, parent_hash)
+	}
+
+	fn import_block(
+		&self,
+		block: ImportBlock<Block>,
+		new_authorities: Option<Vec<AuthorityId>>,
+	) -> Result<ImportResult, Self::Error> {
+		let hash = block.header.hash();
+		let authorities = match self.check(&block) {
+			Ok(authorities) => authorities,
+			Err(e) => {
+				warn!("Rejecting block #{} ({}): {}", block.header.number, hash, e);
+				return Err(ConsensusErrorKind::ClientImport(e).into());
+			},
+		};
+		self.record(&hash, &authorities).map_err(ConsensusErrorKind::ClientImport)?;
+		self.inner.import_block(block, new_authorities)
+	}
+}
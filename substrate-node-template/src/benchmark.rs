@@ -0,0 +1,243 @@
+//! Benchmarking of runtime calls to derive their weights.
+//!
+//! Benchmarks are run against the genesis state of the chain spec, with both the native
+//! runtime of this binary and the Wasm runtime of the chain spec. Both must be built with
+//! the `runtime-benchmarks` feature:
+//!
+//! ```bash
+//! ./scripts/build.sh --features runtime-benchmarks
+//! cargo build --release --features runtime-benchmarks
+//! ```
+//!
+//! Every component of the benchmark is varied over its range in `steps` steps while the others
+//! stay at their lowest value, and a line is fitted through the median timings of each. The
+//! slower of native and Wasm execution is written out as the call's weight.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use log::info;
+use parity_codec::{Decode, Encode};
+use primitives::Blake2Hasher;
+use runtime_primitives::BuildStorage;
+use state_machine::InMemory;
+use structopt::StructOpt;
+use substrate_cli::{SharedParams, VersionInfo, create_config_with_db_path, error};
+use substrate_executor::NativeExecutor;
+use crate::replay::Execution;
+use crate::service;
+
+/// The `benchmark` command.
+#[derive(Debug, StructOpt, Clone)]
+pub struct BenchmarkCmd {
+	/// Module of the call to benchmark, e.g. `template` or `balances`.
+	#[structopt(long = "module", value_name = "NAME")]
+	pub module: String,
+
+	/// Call to benchmark, e.g. `do_something` or `transfer`.
+	#[structopt(long = "call", value_name = "NAME")]
+	pub call: String,
+
+	/// Number of steps each component's range is split into.
+	#[structopt(long = "steps", value_name = "COUNT", default_value = "10")]
+	pub steps: u32,
+
+	/// Number of times every step is repeated.
+	#[structopt(long = "repeat", value_name = "COUNT", default_value = "20")]
+	pub repeat: u32,
+
+	/// File to write the weights to. Defaults to stdout.
+	#[structopt(long = "output", short = "o", value_name = "PATH", parse(from_os_str))]
+	pub output: Option<PathBuf>,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub shared_params: SharedParams,
+}
+
+/// A line fitted through timings, in nanoseconds.
+#[derive(Debug, Clone, Copy, Default)]
+struct Fit {
+	base: f64,
+	slope: f64,
+}
+
+impl BenchmarkCmd {
+	/// Run the benchmark and write out the weights it yields.
+	pub fn run(self, version: &VersionInfo) -> error::Result<()> {
+		if self.steps == 0 || self.repeat == 0 {
+			return Err("`--steps` and `--repeat` must be at least 1".into());
+		}
+
+		let config = create_config_with_db_path::<service::Factory, _>(
+			crate::cli::load_spec,
+			&self.shared_params,
+			version,
+		)?;
+		let (storage, _) = config.chain_spec.build_storage()?;
+		let state = InMemory::<Blake2Hasher>::from(storage);
+		let executor = NativeExecutor::<service::Executor>::new(config.default_heap_pages);
+
+		let components = runtime_call::<Vec<(Vec<u8>, u32, u32)>>(
+			&mut Execution::new(&state, &executor),
+			"Benchmark_components",
+			&(self.module.as_bytes(), self.call.as_bytes()).encode(),
+		)?;
+
+		let mut fits = Vec::new();
+		for &wasm in &[false, true] {
+			let fit = self.measure(&state, &executor, &components, wasm)?;
+			info!("{} execution: {:?}", if wasm { "Wasm" } else { "Native" }, fit);
+			fits.push(fit);
+		}
+
+		// Weights must hold whichever way the block ends up being executed.
+		let worst = components.iter().enumerate().map(|(i, _)| {
+			fits.iter().map(|fit| fit[i].slope).fold(0.0, f64::max)
+		}).collect::<Vec<_>>();
+		let base = fits.iter()
+			.map(|fit| fit.iter().map(|f| f.base).sum::<f64>() / fit.len().max(1) as f64)
+			.fold(0.0, f64::max);
+
+		let output: Box<dyn Write> = match self.output {
+			Some(ref path) => Box::new(File::create(path)?),
+			None => Box::new(io::stdout()),
+		};
+		self.write_weights(output, base, &components, &worst)?;
+		Ok(())
+	}
+
+	/// Fit a line per component through the median dispatch times.
+	fn measure(
+		&self,
+		state: &InMemory<Blake2Hasher>,
+		executor: &NativeExecutor<service::Executor>,
+		components: &[(Vec<u8>, u32, u32)],
+		wasm: bool,
+	) -> Result<Vec<Fit>, String> {
+		let lowest: Vec<(Vec<u8>, u32)> = components.iter().map(|(name, low, _)| (name.clone(), *low)).collect();
+
+		// Benchmarks without components are measured at a single point.
+		if components.is_empty() {
+			let median = self.median_time(state, executor, &lowest, wasm)?;
+			return Ok(vec![Fit { base: median, slope: 0.0 }]);
+		}
+
+		let mut fits = Vec::new();
+		for (i, (name, low, high)) in components.iter().enumerate() {
+			let mut points = Vec::new();
+			for step in 0..=self.steps {
+				let value = low + (high - low) * step / self.steps;
+				let mut values = lowest.clone();
+				values[i].1 = value;
+				points.push((f64::from(value), self.median_time(state, executor, &values, wasm)?));
+			}
+			info!(
+				"{} {}: {:?}",
+				String::from_utf8_lossy(name),
+				if wasm { "wasm" } else { "native" },
+				points,
+			);
+			fits.push(fit_line(&points));
+		}
+		Ok(fits)
+	}
+
+	/// Median time in nanoseconds to dispatch the call with the given component values.
+	fn median_time(
+		&self,
+		state: &InMemory<Blake2Hasher>,
+		executor: &NativeExecutor<service::Executor>,
+		components: &[(Vec<u8>, u32)],
+		wasm: bool,
+	) -> Result<f64, String> {
+		let mut times = Vec::with_capacity(self.repeat as usize);
+		for _ in 0..self.repeat {
+			// Every repetition starts from the same state.
+			let mut execution = Execution::new(state, executor);
+			if wasm {
+				execution = execution.always_wasm();
+			}
+			let call = runtime_call::<Vec<u8>>(
+				&mut execution,
+				"Benchmark_setup",
+				&(self.module.as_bytes(), self.call.as_bytes(), components).encode(),
+			)?;
+
+			// Subtract the cost of calling into the runtime at all.
+			let started = Instant::now();
+			execution.call("Core_version", &[])?;
+			let overhead = started.elapsed();
+
+			let started = Instant::now();
+			runtime_call::<()>(&mut execution, "Benchmark_dispatch", &call.encode())?;
+			let elapsed = started.elapsed();
+
+			times.push(nanos(elapsed).saturating_sub(nanos(overhead)) as f64);
+		}
+
+		times.sort_by(|a, b| a.partial_cmp(b).expect("times are never NaN; qed"));
+		Ok(times[times.len() / 2])
+	}
+
+	fn write_weights(
+		&self,
+		mut output: Box<dyn Write>,
+		base: f64,
+		components: &[(Vec<u8>, u32, u32)],
+		slopes: &[f64],
+	) -> io::Result<()> {
+		let name = self.call.to_uppercase();
+		writeln!(output, "/// Weights of `{}::{}`.", self.module, self.call)?;
+		writeln!(output, "///")?;
+		writeln!(
+			output,
+			"/// Generated by `node-template benchmark --module {} --call {} --steps {} --repeat {}`.",
+			self.module, self.call, self.steps, self.repeat,
+		)?;
+		writeln!(output, "pub mod {} {{", self.module)?;
+		writeln!(output, "\tuse super::Weight;")?;
+		writeln!(output)?;
+		writeln!(output, "\t/// `{}`.", self.call)?;
+		writeln!(output, "\tpub const {}: Weight = {};", name, base.ceil().max(0.0) as u64)?;
+		for ((component, _, _), slope) in components.iter().zip(slopes) {
+			let component = String::from_utf8_lossy(component);
+			writeln!(output, "\t/// Additional weight of `{}` per unit of `{}`.", self.call, component)?;
+			writeln!(
+				output,
+				"\tpub const {}_PER_{}: Weight = {};",
+				name,
+				component.to_uppercase(),
+				slope.ceil().max(0.0) as u64,
+			)?;
+		}
+		writeln!(output, "}}")
+	}
+}
+
+/// Call a benchmark runtime API returning `Result<R, Vec<u8>>`.
+fn runtime_call<R: Decode>(execution: &mut Execution<InMemory<Blake2Hasher>>, method: &str, data: &[u8]) -> Result<R, String> {
+	let result = execution.call(method, data)?;
+	match Result::<R, Vec<u8>>::decode(&mut &result[..]) {
+		Some(Ok(result)) => Ok(result),
+		Some(Err(e)) => Err(format!("{}: {}", method, String::from_utf8_lossy(&e))),
+		None => Err(format!("{}: unable to decode result", method)),
+	}
+}
+
+/// Least squares fit of a line through `points`.
+fn fit_line(points: &[(f64, f64)]) -> Fit {
+	let n = points.len() as f64;
+	let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+	let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+	let covariance: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+	let variance: f64 = points.iter().map(|(x, _)| (x - mean_x) * (x - mean_x)).sum();
+
+	let slope = if variance == 0.0 { 0.0 } else { covariance / variance };
+	Fit { base: mean_y - slope * mean_x, slope }
+}
+
+fn nanos(duration: Duration) -> u64 {
+	duration.as_secs() * 1_000_000_000 + u64::from(duration.subsec_nanos())
+}
@@ -0,0 +1,188 @@
+//! Following changes of the slot duration made by the `BlockTime` runtime module.
+//!
+//! Aura reads the slot duration once, at genesis, and caches it in the aux storage of the
+//! client. The block that changes it carries a `SlotDurationChange` log, so full nodes and light
+//! clients alike follow the logs of the best chain, recording every change along with the block
+//! that applied it. Changes of blocks retracted by a reorg are dropped again, and whenever the
+//! duration in force at the best block differs from the one in use, they:
+//!
+//! - update the slot duration used by `SlotInherentDataProvider`, which Aura registers in
+//!   place of its own provider, so the import queue computes slots with the new duration,
+//! - update the cached value, so `SlotDuration::get_or_compute` returns it after a restart,
+//! - let the authoring code restart the slot worker with the new duration.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use futures::{Future, IntoFuture, Stream};
+use log::{info, warn};
+use parity_codec::{Decode, Encode};
+use inherents::{InherentData, InherentDataProviders, InherentIdentifier, ProvideInherentData};
+use runtime_primitives::RuntimeString;
+use runtime_primitives::generic::BlockId;
+use runtime_primitives::traits::Header as HeaderT;
+use substrate_client::BlockchainEvents;
+use substrate_client::backend::AuxStore;
+use substrate_client::blockchain::HeaderBackend;
+use substrate_service::TaskExecutor;
+use timestamp::TimestampInherentData;
+use node_template_runtime::{BlockNumber, Hash, Header as RuntimeHeader, opaque::{Block, Header}};
+
+/// Aux storage key Aura caches the slot duration under.
+const SLOT_DURATION_KEY: &[u8] = b"aura_slot_duration";
+/// Aux storage key of the best block the changes were followed up to, and the changes.
+const CHANGES_KEY: &[u8] = b"node_template:slot_duration_changes";
+
+/// A slot duration in force from the block that applied it on, starting with the genesis one.
+type Change = (BlockNumber, Hash, u64);
+
+/// Provides the current slot to Aura, computed with a slot duration that can change.
+pub struct SlotInherentDataProvider {
+	slot_duration: Arc<AtomicU64>,
+}
+
+impl ProvideInherentData for SlotInherentDataProvider {
+	fn on_register(&self, providers: &InherentDataProviders) -> Result<(), RuntimeString> {
+		if !providers.has_provider(&timestamp::INHERENT_IDENTIFIER) {
+			providers.register_provider(timestamp::InherentDataProvider)
+		} else {
+			Ok(())
+		}
+	}
+
+	fn inherent_identifier(&self) -> &'static InherentIdentifier {
+		&aura::INHERENT_IDENTIFIER
+	}
+
+	fn provide_inherent_data(&self, inherent_data: &mut InherentData) -> Result<(), RuntimeString> {
+		let timestamp = inherent_data.timestamp_inherent_data()?;
+		let slot = timestamp / self.slot_duration.load(Ordering::Relaxed).max(1);
+		inherent_data.put_data(aura::INHERENT_IDENTIFIER, &slot)
+	}
+
+	fn error_to_string(&self, error: &[u8]) -> Option<String> {
+		RuntimeString::decode(&mut &error[..]).map(Into::into)
+	}
+}
+
+/// Register the slot provider before Aura registers its own, starting at `current`, the
+/// slot duration cached by Aura. Does nothing if a slot provider is registered already.
+pub fn register(
+	providers: &InherentDataProviders,
+	slot_duration: &Arc<AtomicU64>,
+	current: u64,
+) -> Result<(), String> {
+	if providers.has_provider(&aura::INHERENT_IDENTIFIER) {
+		return Ok(());
+	}
+	slot_duration.store(current, Ordering::Relaxed);
+	providers.register_provider(SlotInherentDataProvider { slot_duration: slot_duration.clone() })
+		.map_err(|e| format!("{:?}", e))
+}
+
+/// The slot duration changes of the best chain, tracked from the headers of new best blocks.
+struct Changes<C> {
+	client: Arc<C>,
+	changes: Vec<Change>,
+	best: (BlockNumber, Hash),
+}
+
+impl<C: AuxStore + HeaderBackend<Block>> Changes<C> {
+	/// Load the recorded changes, starting from `current` at genesis on a fresh client.
+	fn load(client: Arc<C>, current: u64) -> Result<Self, String> {
+		let info = client.info().map_err(|e| format!("{:?}", e))?;
+		// Blocks imported since the changes were last stored are caught up with on the next
+		// new best block.
+		let (best, changes) = client.get_aux(CHANGES_KEY)
+			.map_err(|e| format!("{:?}", e))?
+			.and_then(|changes| Decode::decode(&mut &changes[..]))
+			.unwrap_or_else(|| ((0, info.genesis_hash), vec![(0, info.genesis_hash, current)]));
+		Ok(Changes { client, changes, best })
+	}
+
+	/// The slot duration in force at the best block.
+	fn current(&self) -> Option<u64> {
+		self.changes.last().map(|(_, _, duration)| *duration)
+	}
+
+	fn header(&self, hash: Hash) -> Result<Header, String> {
+		self.client.header(BlockId::Hash(hash))
+			.map_err(|e| format!("{:?}", e))?
+			.ok_or_else(|| format!("Unknown block {}", hash))
+	}
+
+	/// Move to the new best block `header`, dropping the changes of the blocks it retracts and
+	/// recording those of the blocks it enacts.
+	fn set_best(&mut self, header: &Header) -> Result<(), String> {
+		let (mut retracted, mut enacted) = (self.best, (header.number, header.hash()));
+		let mut headers = Vec::new();
+		while retracted.1 != enacted.1 {
+			if retracted.0 >= enacted.0 && retracted.0 > 0 {
+				retracted = (retracted.0 - 1, self.header(retracted.1)?.parent_hash);
+			} else {
+				let enacted_header = self.header(enacted.1)?;
+				enacted = (enacted.0.saturating_sub(1), enacted_header.parent_hash);
+				headers.push(enacted_header);
+			}
+		}
+
+		let ancestor = enacted.0;
+		self.changes.retain(|(number, _, _)| *number <= ancestor);
+		for header in headers.into_iter().rev() {
+			let change = RuntimeHeader::decode(&mut &header.encode()[..])
+				.and_then(|header| node_template_runtime::slot_duration_change(&header));
+			if let Some(duration) = change {
+				self.changes.push((header.number, header.hash(), duration));
+			}
+		}
+		self.best = (header.number, header.hash());
+
+		self.client.insert_aux(&[(CHANGES_KEY, &(self.best, &self.changes).encode()[..])], &[])
+			.map_err(|e| format!("Unable to store the slot duration changes: {:?}", e))
+	}
+}
+
+/// Follow the slot duration changes of the best chain of `client` until `exit` fires, updating
+/// `slot_duration` and the cached value, then calling `on_change` with the new duration.
+pub fn follow<C, E, F>(
+	client: Arc<C>,
+	slot_duration: Arc<AtomicU64>,
+	exit: E,
+	executor: &TaskExecutor,
+	mut on_change: F,
+) where
+	C: BlockchainEvents<Block> + AuxStore + HeaderBackend<Block> + Send + Sync + 'static,
+	E: IntoFuture<Item=(), Error=()>,
+	E::Future: Send + 'static,
+	F: FnMut(u64) + Send + 'static,
+{
+	let mut changes = match Changes::load(client.clone(), slot_duration.load(Ordering::Relaxed)) {
+		Ok(changes) => changes,
+		Err(e) => {
+			warn!("Unable to follow slot duration changes: {}", e);
+			return;
+		},
+	};
+
+	let follow = client.import_notification_stream()
+		.filter(|notification| notification.is_new_best)
+		.for_each(move |notification| {
+			if let Err(e) = changes.set_best(&notification.header) {
+				warn!("Unable to follow slot duration changes at block #{}: {}", notification.header.number, e);
+				return Ok(());
+			}
+			let duration = match changes.current() {
+				Some(duration) if duration != slot_duration.load(Ordering::Relaxed) => duration,
+				_ => return Ok(()),
+			};
+
+			info!("Slot duration is {}s from block #{} on", duration, notification.header.number);
+			slot_duration.store(duration, Ordering::Relaxed);
+			if let Err(e) = client.insert_aux(&[(SLOT_DURATION_KEY, &duration.encode()[..])], &[]) {
+				warn!("Unable to store the new slot duration: {:?}", e);
+			}
+			on_change(duration);
+			Ok(())
+		});
+
+	executor.spawn(exit.into_future().select(follow).then(|_| Ok(())));
+}
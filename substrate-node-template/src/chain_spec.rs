@@ -1,9 +1,15 @@
-use primitives::{ed25519, sr25519, Pair};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use parity_codec::Encode;
+use primitives::{ed25519, sr25519, Pair, twox_128};
+use primitives::storage::{StorageKey, StorageData, well_known_keys};
 use node_template_runtime::{
 	AccountId, GenesisConfig, ConsensusConfig, TimestampConfig, BalancesConfig,
-	SudoConfig, IndicesConfig,
+	SudoConfig, IndicesConfig, WeightsConfig, OnlineConfig, BlockTimeConfig, RandomnessConfig,
+	PermissionsConfig, MigrationsConfig, BuildStorage,
 };
 use substrate_service;
+use crate::state_snapshot::StateSnapshot;
 
 use ed25519::Public as AuthorityId;
 
@@ -22,15 +28,22 @@ pub enum Alternative {
 	Development,
 	/// Whatever the current runtime is, with simple Alice/Bob auths.
 	LocalTestnet,
+	/// Genesis storage taken from a state snapshot, optionally with Alice as auth and sudo key.
+	Snapshot {
+		/// Snapshot written by `export-state`.
+		path: PathBuf,
+		/// Replace the authorities and sudo key with development keys.
+		dev_keys: bool,
+	},
 }
 
-fn authority_key(s: &str) -> AuthorityId {
+pub(crate) fn authority_key(s: &str) -> AuthorityId {
 	ed25519::Pair::from_string(&format!("//{}", s), None)
 		.expect("static values are valid; qed")
 		.public()
 }
 
-fn account_key(s: &str) -> AccountId {
+pub(crate) fn account_key(s: &str) -> AccountId {
 	sr25519::Pair::from_string(&format!("//{}", s), None)
 		.expect("static values are valid; qed")
 		.public()
@@ -78,6 +91,7 @@ impl Alternative {
 				None,
 				None
 			),
+			Alternative::Snapshot { path, dev_keys } => from_snapshot(&StateSnapshot::read(&path)?, dev_keys)?,
 		})
 	}
 
@@ -85,11 +99,92 @@ impl Alternative {
 		match s {
 			"dev" => Some(Alternative::Development),
 			"" | "local" => Some(Alternative::LocalTestnet),
+			s if s.starts_with("snapshot:") => Some(Alternative::Snapshot {
+				path: s["snapshot:".len()..].into(),
+				dev_keys: false,
+			}),
+			s if s.starts_with("snapshot-dev:") => Some(Alternative::Snapshot {
+				path: s["snapshot-dev:".len()..].into(),
+				dev_keys: true,
+			}),
 			_ => None,
 		}
 	}
 }
 
+/// A raw chain spec whose genesis storage is `snapshot`.
+///
+/// With `dev_keys`, Alice becomes the only authority and the sudo key so the forked chain
+/// can be run locally with `--alice`.
+pub fn from_snapshot(snapshot: &StateSnapshot, dev_keys: bool) -> Result<ChainSpec, String> {
+	let mut storage = snapshot.storage.clone();
+	if dev_keys {
+		storage.retain(|key, _| !key.0.starts_with(well_known_keys::AUTHORITY_PREFIX));
+		let authorities = vec![authority_key("Alice")];
+		storage.insert(
+			StorageKey(well_known_keys::AUTHORITY_COUNT.to_vec()),
+			StorageData((authorities.len() as u32).encode()),
+		);
+		for (i, authority) in authorities.iter().enumerate() {
+			let mut key = well_known_keys::AUTHORITY_PREFIX.to_vec();
+			key.extend((i as u32).encode());
+			storage.insert(StorageKey(key), StorageData(authority.encode()));
+		}
+		storage.insert(
+			StorageKey(twox_128(b"Sudo Key").to_vec()),
+			StorageData(account_key("Alice").encode()),
+		);
+	}
+
+	// Reuse the development spec for everything but the name and the genesis storage.
+	let mut spec: serde_json::Value = serde_json::from_str(&Alternative::Development.load()?.to_json(true)?)
+		.map_err(|e| format!("{}", e))?;
+	spec["name"] = format!("Snapshot of {} at #{}", snapshot.chain, snapshot.block).into();
+	spec["id"] = "snapshot".into();
+	spec["genesis"] = serde_json::json!({ "raw": storage });
+
+	ChainSpec::from_json_bytes(serde_json::to_vec(&spec).map_err(|e| format!("{}", e))?)
+}
+
+/// A raw chain spec for a local testnet of `validators`, which are also endowed along with
+/// the development accounts, reached through `boot_nodes`.
+pub fn testnet(validators: &[String], boot_nodes: Vec<String>) -> Result<ChainSpec, String> {
+	let authorities = validators.iter().map(|name| authority_key(name)).collect();
+	let mut endowed = ["Alice", "Bob", "Charlie", "Dave", "Eve", "Ferdie"].iter()
+		.map(|name| account_key(name))
+		.collect::<Vec<_>>();
+	for name in validators {
+		let account = account_key(name);
+		if !endowed.contains(&account) {
+			endowed.push(account);
+		}
+	}
+	let mut genesis = testnet_genesis(authorities, endowed.clone(), account_key("Alice"));
+	// The validators are the allowed nodes, for networks started with `--peer-allow-list`.
+	genesis.permissions = Some(PermissionsConfig {
+		enforced: false,
+		allowed: endowed,
+		nodes: boot_nodes.iter()
+			.filter_map(|address| address.rsplit("/p2p/").next())
+			.map(|id| id.as_bytes().to_vec())
+			.collect(),
+	});
+	let (storage, _) = genesis.build_storage()?;
+	let storage = storage.into_iter()
+		.map(|(key, value)| (StorageKey(key), StorageData(value)))
+		.collect::<HashMap<_, _>>();
+
+	// Reuse the development spec for everything but the name, boot nodes and genesis storage.
+	let mut spec: serde_json::Value = serde_json::from_str(&Alternative::Development.load()?.to_json(true)?)
+		.map_err(|e| format!("{}", e))?;
+	spec["name"] = format!("Testnet of {} validators", validators.len()).into();
+	spec["id"] = "testnet".into();
+	spec["bootNodes"] = boot_nodes.into();
+	spec["genesis"] = serde_json::json!({ "raw": storage });
+
+	ChainSpec::from_json_bytes(serde_json::to_vec(&spec).map_err(|e| format!("{}", e))?)
+}
+
 fn testnet_genesis(initial_authorities: Vec<AuthorityId>, endowed_accounts: Vec<AccountId>, root_key: AccountId) -> GenesisConfig {
 	GenesisConfig {
 		consensus: Some(ConsensusConfig {
@@ -97,6 +192,7 @@ fn testnet_genesis(initial_authorities: Vec<AuthorityId>, endowed_accounts: Vec<
 			authorities: initial_authorities.clone(),
 		}),
 		system: None,
+		migrations: Some(MigrationsConfig::default()),
 		timestamp: Some(TimestampConfig {
 			minimum_period: 5, // 10 second block time.
 		}),
@@ -104,7 +200,8 @@ fn testnet_genesis(initial_authorities: Vec<AuthorityId>, endowed_accounts: Vec<
 			ids: endowed_accounts.clone(),
 		}),
 		balances: Some(BalancesConfig {
-			transaction_base_fee: 1,
+			// Transaction fees are charged by the `Weights` module.
+			transaction_base_fee: 0,
 			transaction_byte_fee: 0,
 			existential_deposit: 500,
 			transfer_fee: 0,
@@ -115,5 +212,30 @@ fn testnet_genesis(initial_authorities: Vec<AuthorityId>, endowed_accounts: Vec<
 		sudo: Some(SudoConfig {
 			key: root_key,
 		}),
+		weights: Some(WeightsConfig {
+			maximum_block_weight: 2_000_000_000, // 2 seconds of execution per block.
+			maximum_block_length: 5 * 1024 * 1024,
+			available_block_ratio: 75,
+			transaction_base_fee: 1,
+			transaction_byte_fee: 0,
+			weight_fee: 1,
+		}),
+		online: Some(OnlineConfig {
+			window_length: 100, // About 17 minutes.
+			max_missed_windows: 3,
+		}),
+		block_time: Some(BlockTimeConfig {
+			epoch_length: 100,
+		}),
+		randomness: Some(RandomnessConfig {
+			window_length: 20,
+		}),
+		permissions: Some(PermissionsConfig {
+			enforced: false,
+			allowed: endowed_accounts,
+			// Node IDs are unknown here. Nodes started with `--peer-allow-list` still reach
+			// their boot nodes until synced, and root adds the others with `add_node`.
+			nodes: vec![],
+		}),
 	}
 }
@@ -1,13 +1,141 @@
 use crate::service;
-use futures::{future, Future, sync::oneshot};
-use std::cell::RefCell;
+use futures::{future, Future, Stream};
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::runtime::Runtime;
+use tokio::timer::{Interval, Timeout};
 pub use substrate_cli::{VersionInfo, IntoExit, error};
-use substrate_cli::{informant, parse_and_execute, NoCustom};
+use substrate_cli::{informant, parse_and_execute, impl_augment_clap, GetLogFilter};
 use substrate_service::{ServiceFactory, Roles as ServiceRoles};
+use structopt::StructOpt;
 use crate::chain_spec;
+use crate::metrics::{self, Metrics};
+use crate::pool::PoolPolicy;
+use crate::rpc;
+use crate::explorer;
+use std::path::PathBuf;
+use substrate_client::runtime_api::ProvideRuntimeApi;
+use node_template_runtime::{api_ext::{AccountApi, TemplateApi, TransactionPayment}, opaque::Block};
+use consensus_authorities::AuthoritiesApi;
+use crate::state_snapshot::{ExportStateCmd, ImportStateCmd};
+use crate::replay::ReplayBlocksCmd;
+use crate::try_upgrade::TryUpgradeCmd;
+use crate::benchmark::BenchmarkCmd;
+use crate::testnet::TestnetCmd;
+use crate::keystore::{self, AuthorityKey, KeyCmd, Keystore};
+use primitives::{ed25519, Pair};
 use std::ops::Deref;
-use log::info;
+use log::{info, warn};
+
+/// Node specific options, in addition to the ones of the default `run` command.
+#[derive(Debug, StructOpt, Clone)]
+pub struct NodeParams {
+	/// Serve Prometheus metrics on this port of the local interface.
+	#[structopt(long = "prometheus-port", value_name = "PORT")]
+	pub prometheus_port: Option<u16>,
+
+	/// Seconds to wait on shutdown for the import queue to drain, then again for background tasks to finish.
+	#[structopt(long = "shutdown-timeout", value_name = "SECS", default_value = "30")]
+	pub shutdown_timeout: u64,
+
+	/// Format of log records: `text` or `json` (one object per line).
+	#[structopt(long = "log-format", value_name = "FORMAT", default_value = "text")]
+	pub log_format: crate::logging::LogFormat,
+
+	/// Also write logs into this directory, one file per log target.
+	#[structopt(long = "log-dir", value_name = "PATH", parse(from_os_str))]
+	pub log_dir: Option<std::path::PathBuf>,
+
+	/// Size in MiB after which a log file in `--log-dir` is rotated.
+	#[structopt(long = "log-max-size", value_name = "MIB", default_value = "64")]
+	pub log_max_size: u64,
+
+	/// Number of rotated files kept per log target.
+	#[structopt(long = "log-keep", value_name = "COUNT", default_value = "5")]
+	pub log_keep: usize,
+
+	/// Maximum number of transactions a sender may have in the pool, ready or future.
+	#[structopt(long = "pool-sender-limit", value_name = "COUNT", default_value = "16")]
+	pub pool_sender_limit: usize,
+
+	/// Seconds for which transactions that can never become valid are rejected without validating them again.
+	#[structopt(long = "pool-ban-time", value_name = "SECS", default_value = "1800")]
+	pub pool_ban_time: u64,
+
+	/// Serve the node specific RPC methods over HTTP on this port of the local interface.
+	#[structopt(long = "ext-rpc-port", value_name = "PORT")]
+	pub ext_rpc_port: Option<u16>,
+
+	/// Serve the node specific RPC methods over WebSocket on this port of the local interface.
+	#[structopt(long = "ext-ws-port", value_name = "PORT")]
+	pub ext_ws_port: Option<u16>,
+
+	/// Also serve the RPC methods managing keys, which take passwords, on the node specific RPC ports.
+	#[structopt(long = "unsafe-ext-rpc")]
+	pub unsafe_ext_rpc: bool,
+
+	/// Index the chain and serve the block explorer API on this port of the local interface.
+	#[structopt(long = "explorer-port", value_name = "PORT")]
+	pub explorer_port: Option<u16>,
+
+	/// SS58 address of the ed25519 key of the encrypted keystore to seal blocks with, instead of `--key`.
+	#[structopt(long = "authority-key", value_name = "ADDRESS")]
+	pub authority_key: Option<String>,
+
+	/// Only connect to the reserved nodes and boot nodes whose ID is allowed by the `Permissions` module.
+	#[structopt(long = "peer-allow-list")]
+	pub peer_allow_list: bool,
+
+	/// SS58 address of the sr25519 key of the encrypted keystore to contribute randomness with.
+	#[structopt(long = "vrf-key", value_name = "ADDRESS")]
+	pub vrf_key: Option<String>,
+
+	/// File holding the password of `--authority-key` and `--vrf-key`. Defaults to `NODE_TEMPLATE_PASSWORD`, then to stdin.
+	#[structopt(long = "password-file", value_name = "PATH", parse(from_os_str))]
+	pub password_file: Option<PathBuf>,
+}
+
+impl_augment_clap!(NodeParams);
+
+/// Node specific subcommands.
+#[derive(Debug, StructOpt, Clone)]
+pub enum CustomCommands {
+	/// Export all storage key/value pairs at a block into a snapshot file.
+	#[structopt(name = "export-state")]
+	ExportState(ExportStateCmd),
+	/// Build a raw chain spec whose genesis storage is a snapshot written by `export-state`.
+	#[structopt(name = "import-state")]
+	ImportState(ImportStateCmd),
+	/// Re-execute blocks from the local database and check their storage roots.
+	#[structopt(name = "replay-blocks")]
+	ReplayBlocks(ReplayBlocksCmd),
+	/// Dry-run a runtime upgrade against the state of the best block.
+	#[structopt(name = "try-upgrade")]
+	TryUpgrade(TryUpgradeCmd),
+	/// Benchmark a runtime call and write out its weight.
+	#[structopt(name = "benchmark")]
+	Benchmark(BenchmarkCmd),
+	/// Generate a chain spec for a local testnet and run its validator nodes.
+	#[structopt(name = "testnet")]
+	Testnet(TestnetCmd),
+	/// Manage the password-encrypted keys of the keystore.
+	#[structopt(name = "key")]
+	Key(KeyCmd),
+}
+
+impl GetLogFilter for CustomCommands {
+	fn get_log_filter(&self) -> Option<String> {
+		match self {
+			CustomCommands::ExportState(cmd) => cmd.shared_params.log.clone(),
+			CustomCommands::ImportState(cmd) => cmd.log.clone(),
+			CustomCommands::ReplayBlocks(cmd) => cmd.shared_params.log.clone(),
+			CustomCommands::TryUpgrade(cmd) => cmd.shared_params.log.clone(),
+			CustomCommands::Benchmark(cmd) => cmd.shared_params.log.clone(),
+			CustomCommands::Testnet(cmd) => cmd.log.clone(),
+			CustomCommands::Key(cmd) => cmd.shared_params().log.clone(),
+		}
+	}
+}
 
 /// Parse command line arguments into service configuration.
 pub fn run<I, T, E>(args: I, exit: E, version: VersionInfo) -> error::Result<()> where
@@ -15,34 +143,108 @@ pub fn run<I, T, E>(args: I, exit: E, version: VersionInfo) -> error::Result<()>
 	T: Into<std::ffi::OsString> + Clone,
 	E: IntoExit,
 {
-	parse_and_execute::<service::Factory, NoCustom, NoCustom, _, _, _, _, _>(
+	let custom = parse_and_execute::<service::Factory, CustomCommands, NodeParams, _, _, _, _, _>(
 		load_spec, &version, "substrate-node", args, exit,
-	 	|exit, _custom_args, config| {
+	 	|exit, params, mut config| {
+			crate::logging::init(crate::logging::LogConfig::from_params(&params))?;
 			info!("{}", version.name);
 			info!("  version {}", config.full_version());
 			info!("  by {}, 2017, 2018", version.author);
 			info!("Chain specification: {}", config.chain_spec.name());
 			info!("Node name: {}", config.name);
 			info!("Roles: {:?}", config.roles);
+			config.custom.pool_policy = Arc::new(PoolPolicy::new(params.pool_sender_limit, Duration::from_secs(params.pool_ban_time)));
+			config.custom.peer_allow_list = params.peer_allow_list;
+			if config.roles == ServiceRoles::AUTHORITY {
+				let mut password = None;
+				config.custom.authority_key = authority_key(&params, &config.keystore_path, &config.keys, &mut password)?;
+				config.custom.vrf_key = match params.vrf_key {
+					Some(ref public) => {
+						let password = read_password(&params, &mut password)?;
+						Some(Arc::new(Keystore::open(std::path::Path::new(&config.keystore_path))?.vrf(public, &password)?))
+					},
+					None => None,
+				};
+			}
 			let runtime = Runtime::new().map_err(|e| format!("{:?}", e))?;
 			let executor = runtime.executor();
+			let metrics = config.custom.metrics.clone();
 			match config.roles {
-				ServiceRoles::LIGHT => run_until_exit(
-					runtime,
-				 	service::Factory::new_light(config, executor).map_err(|e| format!("{:?}", e))?,
-					exit
-				),
-				_ => run_until_exit(
-					runtime,
-					service::Factory::new_full(config, executor).map_err(|e| format!("{:?}", e))?,
-					exit
-				),
+				ServiceRoles::LIGHT => {
+					if params.explorer_port.is_some() {
+						warn!("The block explorer is only available on full nodes");
+					}
+					if params.peer_allow_list {
+						warn!("Peer allow-listing is only available on full nodes");
+					}
+					run_until_exit(
+						runtime,
+						service::Factory::new_light(config, executor).map_err(|e| format!("{:?}", e))?,
+						params,
+						metrics,
+						None,
+						exit
+					)
+				},
+				_ => {
+					let explorer_path = PathBuf::from(&config.database_path).join("explorer");
+					let service = service::Factory::new_full(config, executor.clone()).map_err(|e| format!("{:?}", e))?;
+					// The chain is indexed for the explorer and the `events_*` RPC methods.
+					let index = match (params.explorer_port, params.ext_rpc_port, params.ext_ws_port) {
+						(None, None, None) => None,
+						_ => Some(explorer::open_index(service.client(), &explorer_path, service.on_exit(), &executor)?),
+					};
+					if let (Some(port), Some(index)) = (params.explorer_port, &index) {
+						explorer::start_server(port, service.client(), index.clone(), service.on_exit(), executor)?;
+					}
+					run_until_exit(runtime, service, params, metrics, index, exit)
+				},
 			}.map_err(|e| format!("{:?}", e))
 		}
-	).map_err(Into::into).map(|_| ())
+	)?;
+
+	match custom {
+		Some(CustomCommands::ExportState(cmd)) => cmd.run(&version),
+		Some(CustomCommands::ImportState(cmd)) => cmd.run(),
+		Some(CustomCommands::ReplayBlocks(cmd)) => cmd.run(&version),
+		Some(CustomCommands::TryUpgrade(cmd)) => cmd.run(&version),
+		Some(CustomCommands::Benchmark(cmd)) => cmd.run(&version),
+		Some(CustomCommands::Testnet(cmd)) => cmd.run(),
+		Some(CustomCommands::Key(cmd)) => cmd.run(&version),
+		None => Ok(()),
+	}
+}
+
+/// The key to seal blocks with: `--authority-key` from the encrypted keystore, or else the
+/// first `--key`.
+fn authority_key(
+	params: &NodeParams,
+	keystore_path: &str,
+	keys: &[String],
+	password: &mut Option<String>,
+) -> Result<Option<Arc<AuthorityKey>>, String> {
+	let pair = match params.authority_key {
+		Some(ref public) => {
+			let password = read_password(params, password)?;
+			Keystore::open(std::path::Path::new(keystore_path))?.ed25519(public, &password)?
+		},
+		None => match keys.first().and_then(|seed| ed25519::Pair::from_string(seed, None).ok()) {
+			Some(pair) => pair,
+			None => return Ok(None),
+		},
+	};
+	Ok(Some(Arc::new(AuthorityKey::new(pair))))
 }
 
-fn load_spec(id: &str) -> Result<Option<chain_spec::ChainSpec>, String> {
+/// The password of the encrypted keystore, read on first use only.
+fn read_password(params: &NodeParams, password: &mut Option<String>) -> Result<String, String> {
+	if password.is_none() {
+		*password = Some(keystore::read_password(params.password_file.as_ref().map(|path| path.as_path()))?);
+	}
+	Ok(password.clone().expect("set above; qed"))
+}
+
+pub(crate) fn load_spec(id: &str) -> Result<Option<chain_spec::ChainSpec>, String> {
 	Ok(match chain_spec::Alternative::from(id) {
 		Some(spec) => Some(spec.load()?),
 		None => None,
@@ -52,11 +254,18 @@ fn load_spec(id: &str) -> Result<Option<chain_spec::ChainSpec>, String> {
 fn run_until_exit<T, C, E>(
 	mut runtime: Runtime,
 	service: T,
+	params: NodeParams,
+	metrics: Arc<Metrics>,
+	index: Option<Arc<explorer::Index>>,
 	e: E,
 ) -> error::Result<()>
 	where
 		T: Deref<Target=substrate_service::Service<C>>,
-		C: substrate_service::Components,
+		C: substrate_service::Components<Factory = service::Factory>,
+		C::TransactionPoolApi: 'static,
+		substrate_service::ComponentClient<C>: ProvideRuntimeApi,
+		<substrate_service::ComponentClient<C> as ProvideRuntimeApi>::Api: AccountApi<Block> + TransactionPayment<Block>
+			+ TemplateApi<Block> + AuthoritiesApi<Block>,
 		E: IntoExit,
 {
 	let (exit_send, exit) = exit_future::signal();
@@ -64,31 +273,104 @@ fn run_until_exit<T, C, E>(
 	let executor = runtime.executor();
 	informant::start(&service, exit.clone(), executor.clone());
 
+	metrics::start_collecting(&service, metrics.clone(), exit.clone(), executor.clone());
+	if let Some(port) = params.prometheus_port {
+		metrics::start_server(port, metrics, exit.clone(), executor.clone())?;
+	}
+	let rpc_servers = rpc::start_servers(params.ext_rpc_port, params.ext_ws_port, || {
+		rpc::handler(&service, index.clone(), params.unsafe_ext_rpc, executor.clone())
+	})?;
+
 	let _ = runtime.block_on(e.into_exit());
 	exit_send.fire();
 
+	let timeout = Duration::from_secs(params.shutdown_timeout);
+	let import_queue = service.config.custom.import_queue.lock()
+		.expect("the lock is never held across a panic; qed")
+		.clone();
+	if let Some(import_queue) = import_queue {
+		info!("Waiting for the import queue to drain");
+		let _ = runtime.block_on(drain_import_queue(import_queue, timeout));
+	}
+
 	// we eagerly drop the service so that the internal exit future is fired,
 	// but we need to keep holding a reference to the global telemetry guard
 	let _telemetry = service.telemetry();
+	drop(rpc_servers);
 	drop(service);
-	Ok(())
+
+	// Background tasks still hold on to the client, so the database is only flushed
+	// and closed once all of them have wound down.
+	shutdown_runtime(runtime, timeout)
 }
 
-// handles ctrl-c
+/// Stops `import_queue` from taking new blocks and resolves once the blocks already queued
+/// are imported, or after `timeout`.
+fn drain_import_queue(
+	import_queue: service::SharedImportQueue,
+	timeout: Duration,
+) -> impl Future<Item=(), Error=()> {
+	import_queue.close();
+	let drained = Interval::new_interval(Duration::from_millis(100))
+		.skip_while(move |_| Ok(import_queue.importing() > 0))
+		.into_future()
+		.map(|_| ())
+		.map_err(|_| ());
+
+	Timeout::new(drained, timeout)
+		.map_err(|_| warn!("Import queue was not drained before the shutdown timeout"))
+}
+
+/// Wait for all tasks on `runtime` to finish, giving up after `timeout`.
+fn shutdown_runtime(runtime: Runtime, timeout: Duration) -> error::Result<()> {
+	let (done_send, done) = std::sync::mpsc::channel();
+	std::thread::spawn(move || {
+		let _ = runtime.shutdown_on_idle().wait();
+		let _ = done_send.send(());
+	});
+
+	match done.recv_timeout(timeout) {
+		Ok(()) => {
+			info!("Shutdown complete");
+			Ok(())
+		},
+		Err(_) => Err("Background tasks did not finish before the shutdown timeout".into()),
+	}
+}
+
+/// Resolves on the first SIGINT, SIGTERM or SIGHUP delivered to the process.
 pub struct Exit;
+
+#[cfg(unix)]
 impl IntoExit for Exit {
-	type Exit = future::MapErr<oneshot::Receiver<()>, fn(oneshot::Canceled) -> ()>;
+	type Exit = Box<dyn Future<Item=(), Error=()> + Send>;
 	fn into_exit(self) -> Self::Exit {
-		// can't use signal directly here because CtrlC takes only `Fn`.
-		let (exit_send, exit) = oneshot::channel();
+		use tokio_signal::unix::{Signal, SIGHUP, SIGINT, SIGTERM};
 
-		let exit_send_cell = RefCell::new(Some(exit_send));
-		ctrlc::set_handler(move || {
-			if let Some(exit_send) = exit_send_cell.try_borrow_mut().expect("signal handler not reentrant; qed").take() {
-				exit_send.send(()).expect("Error sending exit notification");
-			}
-		}).expect("Error setting Ctrl-C handler");
+		let signals = [(SIGINT, "SIGINT"), (SIGTERM, "SIGTERM"), (SIGHUP, "SIGHUP")]
+			.iter()
+			.map(|&(signal, name)| Signal::new(signal)
+				.flatten_stream()
+				.into_future()
+				.map(move |_| name)
+				.map_err(|(e, _)| e)
+			)
+			.collect::<Vec<_>>();
+
+		Box::new(future::select_all(signals)
+			.map(|(name, _, _)| info!("Received {}, shutting down", name))
+			.map_err(|(e, _, _)| warn!("Error listening for exit signals: {:?}", e)))
+	}
+}
 
-		exit.map_err(drop)
+#[cfg(not(unix))]
+impl IntoExit for Exit {
+	type Exit = Box<dyn Future<Item=(), Error=()> + Send>;
+	fn into_exit(self) -> Self::Exit {
+		Box::new(tokio_signal::ctrl_c()
+			.flatten_stream()
+			.into_future()
+			.map(|_| info!("Received Ctrl-C, shutting down"))
+			.map_err(|(e, _)| warn!("Error listening for Ctrl-C: {:?}", e)))
 	}
 }
@@ -0,0 +1,201 @@
+//! Detection and reporting of Aura equivocations.
+//!
+//! The import queue verifies the seal of every block and hands the block to the block import
+//! without it, so `EquivocationDetector` sees exactly the header the author signed. It remembers
+//! the first header of every recent slot, and a second header sealed for the same slot is an
+//! equivocation: the proof is queued in `Reports`, and submitted to the transaction pool as an
+//! unsigned `Equivocation::report` once the block is imported.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use futures::{Future, IntoFuture, Stream};
+use log::{info, warn};
+use parity_codec::{Decode, Encode};
+use parking_lot::Mutex;
+use consensus::CompatibleDigestItem;
+use consensus_common::{BlockImport, ImportBlock, ImportResult};
+use primitives::ed25519;
+use runtime_primitives::generic::BlockId;
+use runtime_primitives::traits::{DigestItemFor, Header as HeaderT};
+use substrate_service::TaskExecutor;
+use transaction_pool::txpool::{ChainApi, Pool};
+use node_template_runtime::{
+	AuthorityId, AuthoritySignature, Call, EquivocationCall, EquivocationProof, Hash, Header as RuntimeHeader,
+	UncheckedExtrinsic as RuntimeExtrinsic, opaque::{Block, Header, UncheckedExtrinsic},
+};
+use crate::explorer::FullClient;
+
+/// Number of slots headers are remembered for, the most recently seen ones.
+const KEEP_SLOTS: u64 = 1024;
+
+/// Equivocation proof as submitted to the runtime.
+pub type Proof = EquivocationProof<RuntimeHeader, AuthoritySignature>;
+
+/// The first header imported for each recent slot, without its seal, and the seal signature.
+#[derive(Default)]
+struct Seen {
+	headers: HashMap<u64, (Header, AuthoritySignature)>,
+	/// Slots of `headers`, in the order they were first seen.
+	order: VecDeque<u64>,
+}
+
+/// Headers of recent slots and equivocations waiting to be reported.
+#[derive(Default)]
+pub struct Reports {
+	seen: Mutex<Seen>,
+	/// Proofs not yet submitted to the pool.
+	pending: Mutex<Vec<Proof>>,
+}
+
+impl Reports {
+	/// Remember the header of a verified block, queueing a proof if another header was sealed
+	/// for the same slot.
+	fn note(&self, header: &Header, post_digests: &[DigestItemFor<Block>]) {
+		let seal = post_digests.iter()
+			.filter_map(|item| CompatibleDigestItem::<ed25519::Pair>::as_aura_seal(item))
+			.next();
+		let (slot, signature) = match seal {
+			Some(seal) => seal,
+			None => return,
+		};
+
+		let mut seen = self.seen.lock();
+		match seen.headers.get(&slot) {
+			Some((first, _)) if first.hash() == header.hash() => {},
+			Some(first) => {
+				warn!("Equivocation at slot {}: #{} ({}) and #{} ({})",
+					slot, first.0.number, first.0.hash(), header.number, header.hash());
+				match (to_runtime(&first.0), to_runtime(header)) {
+					(Some(first_header), Some(second_header)) => self.pending.lock().push(Proof {
+						slot,
+						first: (first_header, first.1.clone()),
+						second: (second_header, signature),
+					}),
+					_ => warn!("Unable to convert the headers of slot {} into a proof", slot),
+				}
+			},
+			None => {
+				seen.headers.insert(slot, (header.clone(), signature));
+				seen.order.push_back(slot);
+				// Slots are not seen in increasing order: blocks of forks and of the initial sync
+				// arrive late, and slot numbers drop when the slot duration grows.
+				while seen.order.len() as u64 > KEEP_SLOTS {
+					if let Some(oldest) = seen.order.pop_front() {
+						seen.headers.remove(&oldest);
+					}
+				}
+			},
+		}
+	}
+
+	fn take(&self) -> Vec<Proof> {
+		std::mem::replace(&mut *self.pending.lock(), Vec::new())
+	}
+}
+
+/// Block import checking the slots of imported blocks for equivocations before passing them
+/// on to `inner`.
+pub struct EquivocationDetector<I> {
+	inner: Arc<I>,
+	reports: Arc<Reports>,
+}
+
+impl<I> EquivocationDetector<I> {
+	/// Wrap `inner`, queueing the equivocations found into `reports`.
+	pub fn new(inner: Arc<I>, reports: Arc<Reports>) -> Self {
+		EquivocationDetector { inner, reports }
+	}
+}
+
+impl<I: BlockImport<Block>> BlockImport<Block> for EquivocationDetector<I> {
+	type Error = I::Error;
+
+	fn check_block(&self, hash: Hash, parent_hash: Hash) -> Result<ImportResult, Self::Error> {
+		self.inner.check_block(hash, parent_hash)
+	}
+
+	fn import_block(
+		&self,
+		block: ImportBlock<Block>,
+		new_authorities: Option<Vec<AuthorityId>>,
+	) -> Result<ImportResult, Self::Error> {
+		self.reports.note(&block.header, &block.post_digests);
+		self.inner.import_block(block, new_authorities)
+	}
+}
+
+/// Submit queued proofs to `pool` whenever a block is imported, until `exit` fires.
+pub fn start_reporting<A, E>(
+	client: Arc<FullClient>,
+	pool: Arc<Pool<A>>,
+	reports: Arc<Reports>,
+	exit: E,
+	executor: &TaskExecutor,
+) where
+	A: ChainApi<Block = Block> + 'static,
+	E: IntoFuture<Item=(), Error=()>,
+	E::Future: Send + 'static,
+{
+	let report = client.import_notification_stream().for_each(move |notification| {
+		for proof in reports.take() {
+			let slot = proof.slot;
+			let call = Call::Equivocation(EquivocationCall::report(proof));
+			let xt = RuntimeExtrinsic::new_unsigned(call).encode();
+			let xt = UncheckedExtrinsic::decode(&mut &xt[..])
+				.expect("runtime extrinsics encode as opaque extrinsics; qed");
+			match pool.submit_one(&BlockId::Hash(notification.hash), xt) {
+				Ok(_) => info!("Reported the equivocation at slot {}", slot),
+				Err(e) => warn!("Unable to report the equivocation at slot {}: {:?}", slot, e),
+			}
+		}
+		Ok(())
+	});
+
+	executor.spawn(exit.into_future().select(report).then(|_| Ok(())));
+}
+
+/// The runtime representation of an opaque header, which encodes the same way.
+fn to_runtime(header: &Header) -> Option<RuntimeHeader> {
+	RuntimeHeader::decode(&mut &header.encode()[..])
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn header(number: u64) -> Header {
+		Header::new(number, Default::default(), Default::default(), Default::default(), Default::default())
+	}
+
+	fn seal(slot: u64) -> Vec<DigestItemFor<Block>> {
+		vec![CompatibleDigestItem::<ed25519::Pair>::aura_seal(slot, ed25519::Signature::from_raw([0; 64]))]
+	}
+
+	#[test]
+	fn reports_a_second_header_for_a_slot() {
+		let reports = Reports::default();
+		reports.note(&header(1), &seal(7));
+		reports.note(&header(1), &seal(7));
+		assert!(reports.take().is_empty());
+
+		reports.note(&header(2), &seal(7));
+		let proofs = reports.take();
+		assert_eq!(proofs.len(), 1);
+		assert_eq!(proofs[0].slot, 7);
+	}
+
+	#[test]
+	fn forgets_the_least_recently_seen_slots() {
+		let reports = Reports::default();
+		reports.note(&header(1), &seal(5000));
+		// Slot numbers drop after the slot duration grows.
+		for slot in 0..KEEP_SLOTS {
+			reports.note(&header(2 + slot), &seal(slot));
+		}
+
+		reports.note(&header(9999), &seal(5000));
+		assert!(reports.take().is_empty());
+		reports.note(&header(9998), &seal(KEEP_SLOTS - 1));
+		assert_eq!(reports.take().len(), 1);
+	}
+}
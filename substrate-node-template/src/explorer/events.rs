@@ -0,0 +1,139 @@
+//! Decoded events as kept in the index, and filters over them.
+
+use parity_codec::Encode;
+use primitives::crypto::Ss58Codec;
+use serde_derive::{Serialize, Deserialize};
+use serde_json::Value;
+use node_template_runtime::AccountId;
+use super::index::EventRecords;
+use super::metadata::{Metadata, decode_arguments};
+
+/// An event decoded with the metadata of the runtime that emitted it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexedEvent {
+	/// Position of the event in its block.
+	pub index: u32,
+	/// Index of the extrinsic that emitted the event, `None` if emitted on finalization.
+	pub extrinsic: Option<u32>,
+	/// Name of the module that emitted the event.
+	pub module: Option<String>,
+	/// Name of the event variant.
+	pub event: Option<String>,
+	/// Decoded arguments of the event.
+	pub arguments: Vec<Value>,
+	/// SS58 addresses of the accounts among the arguments.
+	pub accounts: Vec<String>,
+	/// Debug representation of the event.
+	pub value: String,
+}
+
+impl IndexedEvent {
+	/// Topics the event can be looked up by.
+	pub fn topics(&self) -> Vec<String> {
+		let mut topics = Vec::new();
+		if let Some(module) = &self.module {
+			topics.push(module_topic(module));
+			if let Some(event) = &self.event {
+				topics.push(event_topic(module, event));
+			}
+		}
+		topics.extend(self.accounts.iter().map(|account| account_topic(account)));
+		topics
+	}
+}
+
+fn module_topic(module: &str) -> String {
+	format!("module:{}", module.to_lowercase())
+}
+
+fn event_topic(module: &str, event: &str) -> String {
+	format!("event:{}.{}", module.to_lowercase(), event.to_lowercase())
+}
+
+fn account_topic(account: &str) -> String {
+	format!("account:{}", account)
+}
+
+/// Decode the events of a block.
+///
+/// Returns the events along with every account involved in them.
+pub fn decode(records: &EventRecords, metadata: &Metadata) -> (Vec<IndexedEvent>, Vec<AccountId>) {
+	let mut all_accounts = Vec::new();
+	let events = records.iter().enumerate()
+		.map(|(index, record)| {
+			let extrinsic = match record.phase {
+				system::Phase::ApplyExtrinsic(index) => Some(index),
+				system::Phase::Finalization => None,
+			};
+			let encoded = record.event.encode();
+			let (module, event, arguments, accounts) = match metadata.event(&encoded) {
+				Some((module, item)) => {
+					let (arguments, accounts) = decode_arguments(&item.arguments, &encoded[2..]);
+					(Some(module.to_string()), Some(item.name.clone()), arguments, accounts)
+				},
+				None => (None, None, Vec::new(), Vec::new()),
+			};
+			let addresses = accounts.iter().map(|who| who.to_ss58check()).collect();
+			all_accounts.extend(accounts);
+
+			IndexedEvent {
+				index: index as u32,
+				extrinsic,
+				module,
+				event,
+				arguments,
+				accounts: addresses,
+				value: format!("{:?}", record.event),
+			}
+		})
+		.collect();
+	(events, all_accounts)
+}
+
+/// Selects events by module, event name and involved account. Unset fields match any event.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventFilter {
+	/// Name of the module, e.g. `balances`.
+	pub module: Option<String>,
+	/// Name of the event, e.g. `Transfer`. Only used together with `module`.
+	pub event: Option<String>,
+	/// SS58 address of an account among the arguments.
+	pub account: Option<String>,
+}
+
+impl EventFilter {
+	/// Check the filter is well formed.
+	pub fn validate(&self) -> Result<(), String> {
+		if self.event.is_some() && self.module.is_none() {
+			return Err("`event` requires `module` to be set".into());
+		}
+		if let Some(account) = &self.account {
+			AccountId::from_ss58check(account)
+				.map_err(|e| format!("Invalid address `{}`: {:?}", account, e))?;
+		}
+		Ok(())
+	}
+
+	/// The most selective topic of the filter, if any.
+	pub fn topic(&self) -> Option<String> {
+		match (&self.module, &self.event, &self.account) {
+			(_, _, Some(account)) => Some(account_topic(account)),
+			(Some(module), Some(event), None) => Some(event_topic(module, event)),
+			(Some(module), None, None) => Some(module_topic(module)),
+			(None, _, None) => None,
+		}
+	}
+
+	/// Whether `event` is selected by the filter.
+	pub fn matches(&self, event: &IndexedEvent) -> bool {
+		let matches_name = |filter: &Option<String>, name: &Option<String>| match (filter, name) {
+			(None, _) => true,
+			(Some(filter), Some(name)) => filter.eq_ignore_ascii_case(name),
+			(Some(_), None) => false,
+		};
+		matches_name(&self.module, &event.module)
+			&& matches_name(&self.event, &event.event)
+			&& self.account.as_ref().map_or(true, |account| event.accounts.contains(account))
+	}
+}
@@ -0,0 +1,443 @@
+//! On-disk index of the canonical chain, updated as blocks are imported.
+//!
+//! Events are only kept in state for as long as it is not pruned, so they are decoded and
+//! copied into the index along with the free balance of every account a block touched and
+//! the value of the template module whenever it changes. Every event is also indexed by its
+//! topics, so the blocks holding events of a module or involving an account are found without
+//! scanning the chain. Blocks whose events the native runtime cannot decode, such as blocks
+//! of an older runtime with another `Event` layout, are indexed without events and recorded
+//! as undecodable.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc as std_mpsc;
+use std::thread;
+use futures::{Future, Stream};
+use futures::sync::mpsc;
+use kvdb::{DBTransaction, KeyValueDB};
+use kvdb_rocksdb::{Database, DatabaseConfig};
+use log::{debug, info, warn};
+use parity_codec::{Decode, Encode};
+use parking_lot::Mutex;
+use primitives::{H256, blake2_256, twox_128};
+use primitives::storage::StorageKey;
+use runtime_primitives::generic::BlockId;
+use substrate_client::BlockchainEvents;
+use substrate_client::runtime_api::{Metadata as MetadataApi, ProvideRuntimeApi};
+use substrate_service::TaskExecutor;
+use node_template_runtime::{AccountId, Event, Hash, api_ext::TransactionInfo};
+use super::FullClient;
+use super::events::{self, EventFilter, IndexedEvent};
+use super::metadata::Metadata;
+
+/// Event records as stored by the system module.
+pub type EventRecords = Vec<system::EventRecord<Event>>;
+
+/// Balances are stored as encoded by the runtime.
+pub type Balance = u128;
+
+/// Layout of the index. Indexes written with another layout are rebuilt from scratch.
+///
+/// - 1: events encoded with SCALE.
+/// - 2: events encoded as JSON, with their topics indexed.
+const VERSION: u32 = 2;
+
+const VERSION_KEY: &[u8] = b"s";
+const LAST_INDEXED: &[u8] = b"l";
+const HASH: u8 = b'h';
+const EVENTS: u8 = b'e';
+const TOUCHED: u8 = b'a';
+const BALANCE: u8 = b'b';
+const TEMPLATE_VALUE: u8 = b'v';
+const TOPICS: u8 = b'p';
+const TOPIC: u8 = b'x';
+const UNDECODABLE: u8 = b'u';
+
+/// Longest range of blocks a single query may scan.
+pub const MAX_QUERY_RANGE: u64 = 10_000;
+
+fn key(prefix: u8, parts: &[&[u8]]) -> Vec<u8> {
+	let mut key = vec![prefix];
+	for part in parts {
+		key.extend_from_slice(part);
+	}
+	key
+}
+
+fn topic_hash(topic: &str) -> H256 {
+	H256::from(blake2_256(topic.as_bytes()))
+}
+
+/// The explorer index.
+pub struct Index {
+	db: Database,
+	subscribers: Mutex<Vec<mpsc::UnboundedSender<(u64, Hash)>>>,
+}
+
+impl Index {
+	/// Open or create the index at `path`, clearing it if it was written with another layout.
+	pub fn open(path: &Path) -> Result<Self, String> {
+		let path = path.to_str().ok_or_else(|| format!("Invalid explorer index path {:?}", path))?;
+		let db = Database::open(&DatabaseConfig::with_columns(None), path)
+			.map_err(|e| format!("Unable to open explorer index at {}: {}", path, e))?;
+		let index = Index { db, subscribers: Mutex::new(Vec::new()) };
+
+		// Indexes written before the layout was versioned hold blocks but no version.
+		let version = match index.get::<u32>(VERSION_KEY)? {
+			Some(version) => Some(version),
+			None if index.last_indexed()?.is_some() => Some(1),
+			None => None,
+		};
+		if version != Some(VERSION) {
+			if let Some(version) = version {
+				info!("Explorer index has layout version {}, rebuilding it with version {}", version, VERSION);
+			}
+			let mut tx = DBTransaction::new();
+			for (key, _) in index.db.iter(None) {
+				tx.delete(None, &key);
+			}
+			tx.put(None, VERSION_KEY, &VERSION.encode());
+			index.db.write(tx).map_err(|e| format!("Explorer index write failed: {}", e))?;
+		}
+		Ok(index)
+	}
+
+	fn get<T: Decode>(&self, key: &[u8]) -> Result<Option<T>, String> {
+		match self.db.get(None, key).map_err(|e| format!("Explorer index read failed: {}", e))? {
+			Some(value) => T::decode(&mut &value[..])
+				.map(Some)
+				.ok_or_else(|| "Corrupt explorer index entry".to_string()),
+			None => Ok(None),
+		}
+	}
+
+	/// Number of the last indexed block.
+	pub fn last_indexed(&self) -> Result<Option<u64>, String> {
+		self.get(LAST_INDEXED)
+	}
+
+	/// Hash of the canonical block `number`, if indexed.
+	pub fn hash(&self, number: u64) -> Result<Option<Hash>, String> {
+		self.get(&key(HASH, &[&number.to_be_bytes()]))
+	}
+
+	/// Whether the events of the canonical block `number` could not be decoded.
+	pub fn undecodable(&self, number: u64) -> Result<bool, String> {
+		self.db.get(None, &key(UNDECODABLE, &[&number.to_be_bytes()]))
+			.map(|value| value.is_some())
+			.map_err(|e| format!("Explorer index read failed: {}", e))
+	}
+
+	/// Events of the block `hash`, if indexed.
+	pub fn events(&self, hash: &Hash) -> Result<Option<Vec<IndexedEvent>>, String> {
+		match self.db.get(None, &key(EVENTS, &[hash.as_bytes()])).map_err(|e| format!("Explorer index read failed: {}", e))? {
+			Some(value) => serde_json::from_slice(&value)
+				.map(Some)
+				.map_err(|e| format!("Corrupt explorer index entry: {}", e)),
+			None => Ok(None),
+		}
+	}
+
+	/// Events of the canonical blocks `from..=to` selected by `filter`, with their block number and hash.
+	pub fn query(&self, filter: &EventFilter, from: u64, to: u64) -> Result<Vec<(u64, Hash, IndexedEvent)>, String> {
+		if to < from || to - from >= MAX_QUERY_RANGE {
+			return Err(format!("Block range must hold between 1 and {} blocks", MAX_QUERY_RANGE));
+		}
+
+		let blocks: BTreeSet<u64> = match filter.topic() {
+			Some(topic) => {
+				let prefix = key(TOPIC, &[topic_hash(&topic).as_bytes()]);
+				let mut blocks = BTreeSet::new();
+				for (key, _) in self.db.iter_from_prefix(None, &prefix) {
+					let number = decode_number(&key[prefix.len()..])?;
+					if number > to {
+						break;
+					}
+					if number >= from {
+						blocks.insert(number);
+					}
+				}
+				blocks
+			},
+			None => (from..=to).collect(),
+		};
+
+		let mut found = Vec::new();
+		for number in blocks {
+			let hash = match self.hash(number)? {
+				Some(hash) => hash,
+				None => continue,
+			};
+			for event in self.events(&hash)?.unwrap_or_default() {
+				if filter.matches(&event) {
+					found.push((number, hash, event));
+				}
+			}
+		}
+		Ok(found)
+	}
+
+	/// A stream of the number and hash of every block indexed from now on.
+	pub fn subscribe(&self) -> mpsc::UnboundedReceiver<(u64, Hash)> {
+		let (sender, receiver) = mpsc::unbounded();
+		self.subscribers.lock().push(sender);
+		receiver
+	}
+
+	/// Delete in `tx` what is indexed for the block at height `number`.
+	fn forget(&self, tx: &mut DBTransaction, number: u64) -> Result<(), String> {
+		let block_number = number.to_be_bytes();
+		if let Some(hash) = self.hash(number)? {
+			tx.delete(None, &key(EVENTS, &[hash.as_bytes()]));
+		}
+		if let Some(touched) = self.get::<Vec<AccountId>>(&key(TOUCHED, &[&block_number]))? {
+			for who in touched {
+				tx.delete(None, &key(BALANCE, &[who.as_ref(), &block_number]));
+			}
+		}
+		if let Some(topics) = self.get::<Vec<H256>>(&key(TOPICS, &[&block_number]))? {
+			for topic in topics {
+				tx.delete(None, &key(TOPIC, &[topic.as_bytes(), &block_number]));
+			}
+		}
+		for prefix in &[HASH, TOUCHED, TOPICS, TEMPLATE_VALUE, UNDECODABLE] {
+			tx.delete(None, &key(*prefix, &[&block_number]));
+		}
+		Ok(())
+	}
+
+	/// Forget the blocks above `best`, after a reorg to a shorter chain.
+	fn truncate(&self, best: u64) -> Result<(), String> {
+		let last = match self.last_indexed()? {
+			Some(last) if last > best => last,
+			_ => return Ok(()),
+		};
+		let mut tx = DBTransaction::new();
+		for number in best + 1..=last {
+			self.forget(&mut tx, number)?;
+		}
+		tx.put(None, LAST_INDEXED, &best.encode());
+		self.db.write(tx).map_err(|e| format!("Explorer index write failed: {}", e))?;
+		info!("Explorer index dropped blocks #{} to #{} after a reorg", best + 1, last);
+		Ok(())
+	}
+
+	fn notify(&self, number: u64, hash: Hash) {
+		self.subscribers.lock().retain(|subscriber| subscriber.unbounded_send((number, hash)).is_ok());
+	}
+
+	/// Free balance of `who` after every block that touched it, in block order.
+	pub fn balance_history(&self, who: &AccountId) -> Result<Vec<(u64, Balance)>, String> {
+		let prefix = key(BALANCE, &[who.as_ref()]);
+		self.db.iter_from_prefix(None, &prefix)
+			.map(|(key, value)| {
+				let number = decode_number(&key[prefix.len()..])?;
+				let balance = Balance::decode(&mut &value[..]).ok_or("Corrupt explorer index entry")?;
+				Ok((number, balance))
+			})
+			.collect()
+	}
+
+	/// Value of the template module after every block that changed it, in block order.
+	pub fn template_history(&self) -> Result<Vec<(u64, Option<u32>)>, String> {
+		self.db.iter_from_prefix(None, &[TEMPLATE_VALUE])
+			.map(|(key, value)| {
+				let number = decode_number(&key[1..])?;
+				let value = <Option<u32>>::decode(&mut &value[..]).ok_or("Corrupt explorer index entry")?;
+				Ok((number, value))
+			})
+			.collect()
+	}
+}
+
+fn decode_number(bytes: &[u8]) -> Result<u64, String> {
+	let mut raw = [0u8; 8];
+	if bytes.len() != raw.len() {
+		return Err("Corrupt explorer index key".into());
+	}
+	raw.copy_from_slice(bytes);
+	Ok(u64::from_be_bytes(raw))
+}
+
+/// Keeps the index in sync with the canonical chain of `client`.
+pub struct Indexer {
+	client: Arc<FullClient>,
+	index: Arc<Index>,
+	/// Metadata of the last runtime seen, by `spec_version`.
+	metadata: Mutex<Option<(u32, Arc<Metadata>)>>,
+	/// Set on shutdown, stopping a catch-up between two blocks.
+	stop: Arc<AtomicBool>,
+}
+
+impl Indexer {
+	/// Index every canonical block not indexed yet, re-indexing blocks replaced by a reorg.
+	pub fn catch_up(&self) -> Result<(), String> {
+		let best = self.client.info().map_err(|e| format!("{:?}", e))?.chain.best_number;
+		self.index.truncate(best)?;
+
+		let mut number = self.index.last_indexed()?.map(|n| n + 1).unwrap_or(0).min(best);
+		while number > 0 && self.index.hash(number - 1)? != self.block_hash(number - 1)? {
+			number -= 1;
+		}
+
+		while number <= best && !self.stop.load(Ordering::Relaxed) {
+			self.index_block(number)?;
+			number += 1;
+		}
+		Ok(())
+	}
+
+	fn block_hash(&self, number: u64) -> Result<Option<Hash>, String> {
+		self.client.block_hash(number).map_err(|e| format!("{:?}", e))
+	}
+
+	fn metadata(&self, at: &BlockId<node_template_runtime::opaque::Block>) -> Result<Arc<Metadata>, String> {
+		let version = self.client.runtime_version_at(at).map_err(|e| format!("{:?}", e))?.spec_version;
+		let mut cached = self.metadata.lock();
+		if let Some((cached_version, metadata)) = &*cached {
+			if *cached_version == version {
+				return Ok(metadata.clone());
+			}
+		}
+
+		let encoded = self.client.runtime_api().metadata(at).map_err(|e| format!("{:?}", e))?;
+		let metadata = Arc::new(Metadata::decode(&encoded)?);
+		*cached = Some((version, metadata.clone()));
+		Ok(metadata)
+	}
+
+	fn storage<T: Decode>(&self, at: &BlockId<node_template_runtime::opaque::Block>, key: Vec<u8>) -> Result<Option<T>, String> {
+		let value = self.client.storage(at, &StorageKey(key)).map_err(|e| format!("{:?}", e))?;
+		match value {
+			Some(value) => T::decode(&mut &value.0[..])
+				.map(Some)
+				.ok_or_else(|| "Unable to decode storage value".to_string()),
+			None => Ok(None),
+		}
+	}
+
+	fn index_block(&self, number: u64) -> Result<(), String> {
+		let hash = self.block_hash(number)?.ok_or_else(|| format!("Block {} not found", number))?;
+		let at = BlockId::Hash(hash);
+		let block_number = number.to_be_bytes();
+		let mut tx = DBTransaction::new();
+
+		// What was recorded for this height on a now abandoned fork.
+		self.index.forget(&mut tx, number)?;
+
+		let records = self.client.storage(&at, &StorageKey(twox_128(b"System Events").to_vec()))
+			.map_err(|e| format!("{:?}", e))?;
+		let records = match records {
+			Some(records) => EventRecords::decode(&mut &records.0[..]),
+			None => Some(Vec::new()),
+		};
+		let (events, mut touched) = match &records {
+			Some(records) => events::decode(records, &*self.metadata(&at)?),
+			None => {
+				warn!("Unable to decode the events of block #{} ({:?}), indexing it without them", number, hash);
+				tx.put(None, &key(UNDECODABLE, &[&block_number]), &[]);
+				(Vec::new(), Vec::new())
+			},
+		};
+
+		let mut topics = events.iter()
+			.flat_map(|event| event.topics())
+			.map(|topic| topic_hash(&topic))
+			.collect::<Vec<_>>();
+		topics.sort();
+		topics.dedup();
+		for topic in &topics {
+			tx.put(None, &key(TOPIC, &[topic.as_bytes(), &block_number]), &[]);
+		}
+
+		if let Some(block) = self.client.block(&at).map_err(|e| format!("{:?}", e))? {
+			for extrinsic in &block.block.extrinsics {
+				let sender = self.client.runtime_api().sender(&at, extrinsic.clone())
+					.map_err(|e| format!("{:?}", e))?;
+				if let Some((who, _, _)) = sender {
+					touched.push(who);
+				}
+			}
+		}
+		touched.sort();
+		touched.dedup();
+
+		for who in &touched {
+			let mut balance_key = b"Balances FreeBalance".to_vec();
+			who.encode_to(&mut balance_key);
+			let balance: Balance = self.storage(&at, blake2_256(&balance_key).to_vec())?.unwrap_or(0);
+			tx.put(None, &key(BALANCE, &[who.as_ref(), &block_number]), &balance.encode());
+		}
+
+		let template_key = twox_128(b"TemplateModule Something").to_vec();
+		let value: Option<u32> = self.storage(&at, template_key.clone())?;
+		let previous: Option<u32> = match number {
+			0 => None,
+			_ => self.storage(&BlockId::Number(number - 1), template_key)?,
+		};
+		if number == 0 || value != previous {
+			tx.put(None, &key(TEMPLATE_VALUE, &[&block_number]), &value.encode());
+		} else {
+			tx.delete(None, &key(TEMPLATE_VALUE, &[&block_number]));
+		}
+
+		tx.put(None, &key(HASH, &[&block_number]), &hash.encode());
+		if records.is_some() {
+			let events = serde_json::to_vec(&events).map_err(|e| format!("{}", e))?;
+			tx.put(None, &key(EVENTS, &[hash.as_bytes()]), &events);
+		}
+		tx.put(None, &key(TOUCHED, &[&block_number]), &touched.encode());
+		tx.put(None, &key(TOPICS, &[&block_number]), &topics.encode());
+		tx.put(None, LAST_INDEXED, &number.encode());
+		self.index.db.write(tx).map_err(|e| format!("Explorer index write failed: {}", e))?;
+		self.index.notify(number, hash);
+
+		debug!(target: "explorer", "Indexed block #{} ({:?})", number, hash);
+		Ok(())
+	}
+}
+
+/// Bring the index up to date, then follow the canonical chain until `exit` fires.
+///
+/// Indexing reads blocks and state and writes to disk, so it runs on a thread of its own
+/// rather than on the executor. New best blocks only wake the thread, which indexes every
+/// block imported meanwhile in one go.
+pub fn start_indexing(
+	client: Arc<FullClient>,
+	index: Arc<Index>,
+	exit: exit_future::Exit,
+	executor: &TaskExecutor,
+) -> Result<(), String> {
+	let stop = Arc::new(AtomicBool::new(false));
+	let indexer = Indexer { client: client.clone(), index, metadata: Mutex::new(None), stop: stop.clone() };
+
+	let (wake, woken) = std_mpsc::channel();
+	let follow = client.import_notification_stream()
+		.filter(|notification| notification.is_new_best)
+		.for_each(move |_| {
+			let _ = wake.send(());
+			Ok(())
+		});
+	// Dropping `follow`, and with it `wake`, ends the thread once it is done with the block at hand.
+	executor.spawn(exit.clone().select(follow).then(|_| Ok(())));
+	executor.spawn(exit.then(move |_| {
+		stop.store(true, Ordering::Relaxed);
+		Ok(())
+	}));
+
+	thread::Builder::new()
+		.name("explorer-indexer".into())
+		.spawn(move || loop {
+			if let Err(e) = indexer.catch_up() {
+				warn!("Unable to index the chain: {}", e);
+			}
+			if woken.recv().is_err() {
+				break;
+			}
+			while woken.try_recv().is_ok() {}
+		})
+		.map(|_| ())
+		.map_err(|e| format!("Unable to start the explorer indexer: {}", e))
+}
@@ -0,0 +1,126 @@
+//! Names of calls and events, and the arguments of events, from the runtime metadata.
+//!
+//! The metadata is walked through its JSON serialization, so that only the layout shared by
+//! every metadata version is relied upon.
+
+use parity_codec::Decode;
+use primitives::crypto::Ss58Codec;
+use primitives::hexdisplay::HexDisplay;
+use serde_json::{json, Value};
+use srml_metadata::RuntimeMetadataPrefixed;
+use node_template_runtime::{AccountId, Hash};
+
+/// A call or event of a module.
+pub struct Item {
+	/// Name of the call or event.
+	pub name: String,
+	/// Arguments as described by the metadata.
+	pub arguments: Value,
+}
+
+/// The calls or events of a module.
+struct Module {
+	name: String,
+	items: Vec<Item>,
+}
+
+/// Calls and events of the modules of a runtime, in the order they are indexed by.
+pub struct Metadata {
+	calls: Vec<Module>,
+	events: Vec<Module>,
+}
+
+impl Metadata {
+	/// Decode the metadata returned by the `Metadata` runtime API.
+	pub fn decode(encoded: &[u8]) -> Result<Self, String> {
+		let metadata = RuntimeMetadataPrefixed::decode(&mut &encoded[..])
+			.ok_or_else(|| "Unable to decode runtime metadata".to_string())?;
+		let json = serde_json::to_value(&metadata).map_err(|e| format!("{}", e))?;
+
+		// `RuntimeMetadataPrefixed` serializes as `[magic, { "V<version>": { "modules": [..] } }]`.
+		let modules = json.get(1)
+			.and_then(Value::as_object)
+			.and_then(|versioned| versioned.values().next())
+			.and_then(|metadata| metadata.get("modules"))
+			.and_then(Value::as_array)
+			.ok_or_else(|| "Unexpected runtime metadata layout".to_string())?;
+
+		let mut calls = Vec::new();
+		let mut events = Vec::new();
+		for module in modules {
+			let name = module.get("name").and_then(Value::as_str).unwrap_or_default();
+			if let Some(items) = module.get("calls").and_then(Value::as_array) {
+				calls.push(Module { name: name.into(), items: items.iter().map(item).collect() });
+			}
+			if let Some(items) = module.get("event").and_then(Value::as_array) {
+				events.push(Module { name: name.into(), items: items.iter().map(item).collect() });
+			}
+		}
+		Ok(Metadata { calls, events })
+	}
+
+	/// Module name and description of an encoded call.
+	pub fn call(&self, encoded: &[u8]) -> Option<(&str, &Item)> {
+		lookup(&self.calls, encoded)
+	}
+
+	/// Module name and description of an encoded event.
+	pub fn event(&self, encoded: &[u8]) -> Option<(&str, &Item)> {
+		lookup(&self.events, encoded)
+	}
+}
+
+fn item(value: &Value) -> Item {
+	Item {
+		name: value.get("name").and_then(Value::as_str).unwrap_or_default().into(),
+		arguments: value.get("arguments").cloned().unwrap_or(Value::Null),
+	}
+}
+
+/// Decode the arguments of an event, given the type names listed by its metadata.
+///
+/// Returns the arguments along with the accounts among them. Decoding stops at the first type
+/// that is not known here, leaving `null` for it and every following argument.
+pub fn decode_arguments(types: &Value, mut input: &[u8]) -> (Vec<Value>, Vec<AccountId>) {
+	let types = types.as_array().map(|types| &types[..]).unwrap_or_default();
+	let mut arguments = Vec::with_capacity(types.len());
+	let mut accounts = Vec::new();
+
+	for ty in types {
+		match decode_argument(ty.as_str().unwrap_or_default(), &mut input) {
+			Some((value, account)) => {
+				arguments.push(value);
+				accounts.extend(account);
+			},
+			None => break,
+		}
+	}
+	arguments.resize(types.len(), Value::Null);
+	(arguments, accounts)
+}
+
+fn decode_argument(ty: &str, input: &mut &[u8]) -> Option<(Value, Option<AccountId>)> {
+	Some(match ty {
+		"AccountId" => {
+			let who = AccountId::decode(input)?;
+			(json!(who.to_ss58check()), Some(who))
+		},
+		// Balances are serialized as strings as they do not fit in a JSON number.
+		"Balance" => (json!(u128::decode(input)?.to_string()), None),
+		"bool" => (json!(bool::decode(input)?), None),
+		"u8" => (json!(u8::decode(input)?), None),
+		"u16" => (json!(u16::decode(input)?), None),
+		"u32" | "AccountIndex" => (json!(u32::decode(input)?), None),
+		"u64" | "BlockNumber" | "Moment" => (json!(u64::decode(input)?), None),
+		"Hash" => (json!(Hash::decode(input)?), None),
+		"Vec<u8>" => (json!(format!("0x{}", HexDisplay::from(&<Vec<u8>>::decode(input)?))), None),
+		_ => return None,
+	})
+}
+
+/// Calls and events are encoded as the index of their module followed by their own index.
+fn lookup<'a>(modules: &'a [Module], encoded: &[u8]) -> Option<(&'a str, &'a Item)> {
+	let module = modules.get(*encoded.get(0)? as usize)?;
+	let item = module.items.get(*encoded.get(1)? as usize)?;
+	Some((&module.name, item))
+}
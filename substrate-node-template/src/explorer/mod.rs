@@ -0,0 +1,214 @@
+//! Block explorer REST API served by full nodes started with `--explorer-port`.
+//!
+//! Every response is JSON:
+//!
+//! - `GET /blocks/<number or 0x hash>`: header, decoded extrinsics and events of a block, and
+//!   whether its events could not be decoded by the native runtime.
+//! - `GET /blocks/<number or 0x hash>/events`: events of a block.
+//! - `GET /accounts/<ss58 address>/balances`: free balance after every block touching the account.
+//! - `GET /template/values`: value of the template module after every block changing it.
+//!
+//! Events and histories are served from an index in the `explorer` directory of the database,
+//! built as blocks are imported. The index also backs the `events_*` RPC methods.
+
+mod events;
+mod index;
+mod metadata;
+
+pub use self::events::{EventFilter, IndexedEvent};
+pub use self::index::{Index, MAX_QUERY_RANGE};
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use futures::{Future, IntoFuture};
+use hyper::{Body, Request, Response, Server, StatusCode, header, service::service_fn_ok};
+use log::{info, warn};
+use parity_codec::{Decode, Encode};
+use primitives::crypto::Ss58Codec;
+use runtime_primitives::generic::BlockId;
+use runtime_primitives::traits::Header as HeaderT;
+use serde_json::{json, Value};
+use substrate_client::runtime_api::{Metadata as MetadataApi, ProvideRuntimeApi};
+use substrate_service::TaskExecutor;
+use node_template_runtime::{AccountId, Hash, UncheckedExtrinsic, opaque::Header};
+use crate::service;
+use self::metadata::Metadata;
+
+/// The client of a full node.
+pub type FullClient = substrate_service::FullClient<service::Factory>;
+
+/// A failed request.
+enum ApiError {
+	BadRequest(String),
+	NotFound,
+	Internal(String),
+}
+
+impl From<String> for ApiError {
+	fn from(e: String) -> Self {
+		ApiError::Internal(e)
+	}
+}
+
+struct Explorer {
+	client: Arc<FullClient>,
+	index: Arc<Index>,
+}
+
+impl Explorer {
+	fn route(&self, path: &str) -> Result<Value, ApiError> {
+		let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+		match &segments[..] {
+			["blocks", id] => self.block(id),
+			["blocks", id, "events"] => {
+				let (hash, _) = self.header(id)?;
+				self.events(hash)
+			},
+			["accounts", account, "balances"] => self.balances(account),
+			["template", "values"] => self.template_values(),
+			_ => Err(ApiError::NotFound),
+		}
+	}
+
+	fn header(&self, id: &str) -> Result<(Hash, Header), ApiError> {
+		let id = crate::state_snapshot::parse_block_id(id).map_err(ApiError::BadRequest)?;
+		let header = self.client.header(&id)
+			.map_err(|e| format!("{:?}", e))?
+			.ok_or(ApiError::NotFound)?;
+		Ok((header.hash(), header))
+	}
+
+	fn metadata(&self, hash: Hash) -> Result<Metadata, ApiError> {
+		let metadata = self.client.runtime_api().metadata(&BlockId::Hash(hash))
+			.map_err(|e| format!("{:?}", e))?;
+		Ok(Metadata::decode(&metadata)?)
+	}
+
+	fn block(&self, id: &str) -> Result<Value, ApiError> {
+		let (hash, header) = self.header(id)?;
+		let block = self.client.block(&BlockId::Hash(hash))
+			.map_err(|e| format!("{:?}", e))?
+			.ok_or(ApiError::NotFound)?;
+		let metadata = self.metadata(hash)?;
+
+		let extrinsics = block.block.extrinsics.iter()
+			.map(|extrinsic| {
+				// The opaque extrinsic holds the encoding of the runtime extrinsic without the length prefix.
+				let extrinsic = UncheckedExtrinsic::decode(&mut &extrinsic.encode()[..])
+					.ok_or_else(|| "Unable to decode extrinsic".to_string())?;
+				Ok(extrinsic_json(&extrinsic, &metadata))
+			})
+			.collect::<Result<Vec<_>, ApiError>>()?;
+
+		Ok(json!({
+			"number": header.number(),
+			"hash": hash,
+			"parentHash": header.parent_hash(),
+			"stateRoot": header.state_root(),
+			"extrinsicsRoot": header.extrinsics_root(),
+			"extrinsics": extrinsics,
+			"events": self.events(hash)?,
+			"eventsUndecodable": self.undecodable(hash, *header.number())?,
+		}))
+	}
+
+	/// Events of a block, or `null` if it is not indexed yet.
+	fn events(&self, hash: Hash) -> Result<Value, ApiError> {
+		Ok(json!(self.index.events(&hash)?))
+	}
+
+	/// Whether the block was indexed without its events, which could not be decoded.
+	fn undecodable(&self, hash: Hash, number: u64) -> Result<bool, ApiError> {
+		Ok(self.index.hash(number)? == Some(hash) && self.index.undecodable(number)?)
+	}
+
+	fn balances(&self, account: &str) -> Result<Value, ApiError> {
+		let who = AccountId::from_ss58check(account)
+			.map_err(|e| ApiError::BadRequest(format!("Invalid address `{}`: {:?}", account, e)))?;
+		let history = self.index.balance_history(&who)?.into_iter()
+			.map(|(number, free)| json!({ "block": number, "free": free.to_string() }))
+			.collect::<Vec<_>>();
+		Ok(Value::Array(history))
+	}
+
+	fn template_values(&self) -> Result<Value, ApiError> {
+		let history = self.index.template_history()?.into_iter()
+			.map(|(number, value)| json!({ "block": number, "value": value }))
+			.collect::<Vec<_>>();
+		Ok(Value::Array(history))
+	}
+}
+
+fn extrinsic_json(extrinsic: &UncheckedExtrinsic, metadata: &Metadata) -> Value {
+	let signature = extrinsic.signature.as_ref().map(|(address, _, nonce, era)| json!({
+		"address": format!("{:?}", address),
+		"nonce": nonce.0,
+		"era": format!("{:?}", era),
+	}));
+	let call = extrinsic.function.encode();
+	let (module, description) = match metadata.call(&call) {
+		Some((module, item)) => (json!(module), json!({ "name": item.name, "arguments": item.arguments })),
+		None => (Value::Null, Value::Null),
+	};
+
+	json!({
+		"signature": signature,
+		"module": module,
+		"call": description,
+		"value": format!("{:?}", extrinsic.function),
+	})
+}
+
+fn respond(explorer: &Explorer, request: Request<Body>) -> Response<Body> {
+	let (status, body) = match explorer.route(request.uri().path()) {
+		Ok(value) => (StatusCode::OK, value),
+		Err(ApiError::BadRequest(e)) => (StatusCode::BAD_REQUEST, json!({ "error": e })),
+		Err(ApiError::NotFound) => (StatusCode::NOT_FOUND, json!({ "error": "Not found" })),
+		Err(ApiError::Internal(e)) => {
+			warn!("Explorer request for {} failed: {}", request.uri(), e);
+			(StatusCode::INTERNAL_SERVER_ERROR, json!({ "error": e }))
+		},
+	};
+
+	Response::builder()
+		.status(status)
+		.header(header::CONTENT_TYPE, "application/json")
+		.body(Body::from(body.to_string()))
+		.expect("static header and JSON body are valid; qed")
+}
+
+/// Open the index at `path` and keep it in sync with the chain until `exit` fires.
+pub fn open_index(
+	client: Arc<FullClient>,
+	path: &Path,
+	exit: exit_future::Exit,
+	executor: &TaskExecutor,
+) -> Result<Arc<Index>, String> {
+	let index = Arc::new(Index::open(path)?);
+	index::start_indexing(client, index.clone(), exit, executor)?;
+	Ok(index)
+}
+
+/// Serve the explorer API on `port` until `exit` fires.
+pub fn start_server(
+	port: u16,
+	client: Arc<FullClient>,
+	index: Arc<Index>,
+	exit: exit_future::Exit,
+	executor: TaskExecutor,
+) -> Result<(), String> {
+	let explorer = Arc::new(Explorer { client, index });
+	let addr = SocketAddr::from(([127, 0, 0, 1], port));
+	let server = Server::try_bind(&addr)
+		.map_err(|e| format!("Unable to bind explorer API to {}: {}", addr, e))?
+		.serve(move || {
+			let explorer = explorer.clone();
+			service_fn_ok(move |request| respond(&explorer, request))
+		})
+		.map_err(|e| warn!("Explorer API error: {:?}", e));
+
+	info!("Explorer API served on http://{}", addr);
+	executor.spawn(exit.into_future().select(server).then(|_| Ok(())));
+	Ok(())
+}
@@ -0,0 +1,88 @@
+//! An authority key that can be replaced while the node runs.
+
+use std::sync::Arc;
+use parking_lot::RwLock;
+use primitives::{ed25519, Pair};
+use primitives::crypto::{DeriveJunction, SecretStringError};
+
+/// The ed25519 key Aura seals blocks with.
+///
+/// Aura holds on to the key it was started with, so this wraps the actual key and forwards
+/// signing to whichever key was last set with `rotate`. Blocks are sealed with the new key from
+/// the next slot on.
+pub struct AuthorityKey {
+	inner: RwLock<Arc<ed25519::Pair>>,
+}
+
+impl AuthorityKey {
+	/// Wrap `pair`.
+	pub fn new(pair: ed25519::Pair) -> Self {
+		Self::shared(Arc::new(pair))
+	}
+
+	/// Wrap a key shared with other users.
+	pub fn shared(pair: Arc<ed25519::Pair>) -> Self {
+		AuthorityKey { inner: RwLock::new(pair) }
+	}
+
+	/// Seal blocks with `pair` from now on, returning the key used until now.
+	pub fn rotate(&self, pair: ed25519::Pair) -> Arc<ed25519::Pair> {
+		std::mem::replace(&mut *self.inner.write(), Arc::new(pair))
+	}
+}
+
+impl Pair for AuthorityKey {
+	type Public = ed25519::Public;
+	type Seed = <ed25519::Pair as Pair>::Seed;
+	type Signature = ed25519::Signature;
+	type DeriveError = <ed25519::Pair as Pair>::DeriveError;
+
+	fn generate() -> Self {
+		Self::new(ed25519::Pair::generate())
+	}
+
+	fn generate_with_phrase(password: Option<&str>) -> (Self, String) {
+		let (pair, phrase) = ed25519::Pair::generate_with_phrase(password);
+		(Self::new(pair), phrase)
+	}
+
+	fn from_phrase(phrase: &str, password: Option<&str>) -> Result<Self, SecretStringError> {
+		ed25519::Pair::from_phrase(phrase, password).map(Self::new)
+	}
+
+	fn derive<Iter: Iterator<Item=DeriveJunction>>(&self, path: Iter) -> Result<Self, Self::DeriveError> {
+		self.inner.read().derive(path).map(Self::new)
+	}
+
+	fn from_seed(seed: Self::Seed) -> Self {
+		Self::new(ed25519::Pair::from_seed(seed))
+	}
+
+	fn from_seed_slice(seed: &[u8]) -> Result<Self, SecretStringError> {
+		ed25519::Pair::from_seed_slice(seed).map(Self::new)
+	}
+
+	fn from_standard_components<I: Iterator<Item=DeriveJunction>>(
+		seed: &str,
+		password: Option<&str>,
+		path: I,
+	) -> Result<Self, SecretStringError> {
+		ed25519::Pair::from_standard_components(seed, password, path).map(Self::new)
+	}
+
+	fn sign(&self, message: &[u8]) -> Self::Signature {
+		self.inner.read().sign(message)
+	}
+
+	fn verify<P: AsRef<Self::Public>, M: AsRef<[u8]>>(sig: &Self::Signature, message: M, pubkey: P) -> bool {
+		ed25519::Pair::verify(sig, message, pubkey)
+	}
+
+	fn verify_weak<P: AsRef<[u8]>, M: AsRef<[u8]>>(sig: &[u8], message: M, pubkey: P) -> bool {
+		ed25519::Pair::verify_weak(sig, message, pubkey)
+	}
+
+	fn public(&self) -> Self::Public {
+		self.inner.read().public()
+	}
+}
@@ -0,0 +1,104 @@
+//! The `key` command, managing the encrypted keystore of a chain.
+
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+use substrate_cli::{SharedParams, VersionInfo, create_config_with_db_path, error};
+use crate::service;
+use log::warn;
+use super::{KeyType, Keystore, read_password, read_secret};
+
+/// Options shared by the `key` subcommands.
+#[derive(Debug, StructOpt, Clone)]
+pub struct KeystoreParams {
+	/// File holding the keystore password. Defaults to `NODE_TEMPLATE_PASSWORD`, then to stdin.
+	#[structopt(long = "password-file", value_name = "PATH", parse(from_os_str))]
+	pub password_file: Option<PathBuf>,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub shared_params: SharedParams,
+}
+
+/// The `key` command.
+#[derive(Debug, StructOpt, Clone)]
+pub enum KeyCmd {
+	/// Generate a key and store it encrypted in the keystore.
+	#[structopt(name = "generate")]
+	Generate {
+		/// Type of the key: `ed25519` for authority keys, `sr25519` for accounts.
+		#[structopt(long = "type", value_name = "TYPE", default_value = "ed25519")]
+		key_type: KeyType,
+
+		#[allow(missing_docs)]
+		#[structopt(flatten)]
+		params: KeystoreParams,
+	},
+	/// Encrypt an existing key and store it in the keystore.
+	#[structopt(name = "insert")]
+	Insert {
+		/// Type of the key: `ed25519` for authority keys, `sr25519` for accounts.
+		#[structopt(long = "type", value_name = "TYPE", default_value = "ed25519")]
+		key_type: KeyType,
+
+		/// Secret URI of the key: a seed, a phrase or a derivation like `//Alice`. Unsafe, as it
+		/// shows in the process list and the shell history: the secret URI is read from stdin,
+		/// without echoing it, if not given.
+		#[structopt(long = "unsafe-suri", value_name = "SURI")]
+		suri: Option<String>,
+
+		#[allow(missing_docs)]
+		#[structopt(flatten)]
+		params: KeystoreParams,
+	},
+	/// List the keys of the keystore.
+	#[structopt(name = "list")]
+	List {
+		#[allow(missing_docs)]
+		#[structopt(flatten)]
+		shared_params: SharedParams,
+	},
+}
+
+impl KeyCmd {
+	/// The shared parameters of the subcommand.
+	pub fn shared_params(&self) -> &SharedParams {
+		match self {
+			KeyCmd::Generate { params, .. } | KeyCmd::Insert { params, .. } => &params.shared_params,
+			KeyCmd::List { shared_params } => shared_params,
+		}
+	}
+
+	/// Run the subcommand against the keystore of the chain.
+	pub fn run(self, version: &VersionInfo) -> error::Result<()> {
+		let config = create_config_with_db_path::<service::Factory, _>(
+			crate::cli::load_spec,
+			self.shared_params(),
+			version,
+		)?;
+		let keystore = Keystore::open(Path::new(&config.keystore_path))?;
+
+		match self {
+			KeyCmd::Generate { key_type, params } => {
+				let password = read_password(params.password_file.as_ref().map(|path| path.as_path()))?;
+				println!("{}", keystore.generate(key_type, &password)?);
+			},
+			KeyCmd::Insert { key_type, suri, params } => {
+				let suri = match suri {
+					Some(suri) => {
+						warn!("The secret URI given with --unsafe-suri may be read from the process list and the shell history");
+						suri
+					},
+					None => read_secret("Secret URI")?,
+				};
+				let password = read_password(params.password_file.as_ref().map(|path| path.as_path()))?;
+				println!("{}", keystore.insert(key_type, &suri, &password)?);
+			},
+			KeyCmd::List { .. } => {
+				for (key_type, public) in keystore.list()? {
+					println!("{} {}", key_type, public);
+				}
+			},
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,336 @@
+//! Password-encrypted keys kept on disk.
+//!
+//! Each key is a JSON file named after its type and public key, holding the secret URI of the
+//! key (a seed or a phrase, with an optional derivation path) encrypted with ChaCha20-Poly1305
+//! under a key derived from the password with PBKDF2-HMAC-SHA256. The directory lives inside
+//! the keystore of the chain, as `<keystore-path>/encrypted`. On Unix, only the owner may read
+//! the directory and its keys.
+
+mod authority;
+mod cmd;
+
+pub use authority::AuthorityKey;
+pub use cmd::KeyCmd;
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use primitives::{ed25519, sr25519, Pair};
+use primitives::crypto::Ss58Codec;
+use primitives::hexdisplay::HexDisplay;
+use ring::{aead, digest, pbkdf2};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_derive::{Serialize, Deserialize};
+
+/// PBKDF2 iterations for newly encrypted keys.
+const ITERATIONS: u32 = 100_000;
+/// Environment variable the keystore password is read from, if set.
+pub const PASSWORD_ENV: &str = "NODE_TEMPLATE_PASSWORD";
+
+/// Signature scheme of a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyType {
+	/// Used by authorities to seal blocks.
+	Ed25519,
+	/// Used by accounts to sign transactions.
+	Sr25519,
+}
+
+impl FromStr for KeyType {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, String> {
+		match s {
+			"ed25519" => Ok(KeyType::Ed25519),
+			"sr25519" => Ok(KeyType::Sr25519),
+			_ => Err(format!("Unknown key type `{}`, expected `ed25519` or `sr25519`", s)),
+		}
+	}
+}
+
+impl fmt::Display for KeyType {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			KeyType::Ed25519 => write!(f, "ed25519"),
+			KeyType::Sr25519 => write!(f, "sr25519"),
+		}
+	}
+}
+
+/// A key as stored on disk.
+#[derive(Serialize, Deserialize)]
+struct KeyFile {
+	#[serde(rename = "type")]
+	key_type: KeyType,
+	/// SS58 address of the public key.
+	public: String,
+	/// Hex encoded PBKDF2 salt.
+	salt: String,
+	iterations: u32,
+	/// Hex encoded ChaCha20-Poly1305 nonce.
+	nonce: String,
+	/// Hex encoded secret URI followed by the authentication tag.
+	ciphertext: String,
+}
+
+/// A directory of encrypted keys.
+pub struct Keystore {
+	path: PathBuf,
+}
+
+impl Keystore {
+	/// Open the encrypted keystore inside the keystore directory of a chain, creating it if needed.
+	pub fn open(keystore_path: &Path) -> Result<Self, String> {
+		let path = keystore_path.join("encrypted");
+		fs::create_dir_all(&path).map_err(|e| format!("Unable to create keystore {:?}: {}", path, e))?;
+		restrict(&path, 0o700).map_err(|e| format!("Unable to restrict access to keystore {:?}: {}", path, e))?;
+		Ok(Keystore { path })
+	}
+
+	/// Encrypt the key of the secret URI `suri` with `password` and store it, returning its
+	/// SS58 address.
+	pub fn insert(&self, key_type: KeyType, suri: &str, password: &str) -> Result<String, String> {
+		let public = public_of(key_type, suri)?;
+		let rng = SystemRandom::new();
+		let mut salt = [0u8; 16];
+		let mut nonce = [0u8; 12];
+		rng.fill(&mut salt).and_then(|_| rng.fill(&mut nonce))
+			.map_err(|_| "Unable to generate randomness".to_string())?;
+
+		let key = aead::SealingKey::new(&aead::CHACHA20_POLY1305, &derive_key(password, &salt, ITERATIONS))
+			.map_err(|_| "Unable to create encryption key".to_string())?;
+		let tag_len = aead::CHACHA20_POLY1305.tag_len();
+		let mut in_out = suri.as_bytes().to_vec();
+		in_out.resize(suri.len() + tag_len, 0);
+		let len = aead::seal_in_place(&key, aead::Nonce::assume_unique_for_key(nonce), aead::Aad::empty(), &mut in_out, tag_len)
+			.map_err(|_| "Unable to encrypt key".to_string())?;
+		in_out.truncate(len);
+
+		let file = KeyFile {
+			key_type,
+			public: public.clone(),
+			salt: hex(&salt),
+			iterations: ITERATIONS,
+			nonce: hex(&nonce),
+			ciphertext: hex(&in_out),
+		};
+		let json = serde_json::to_vec_pretty(&file).map_err(|e| format!("{}", e))?;
+		let path = self.key_path(key_type, &public);
+		write_private(&path, &json).map_err(|e| format!("Unable to write key {:?}: {}", path, e))?;
+		Ok(public)
+	}
+
+	/// Generate a new key, store it encrypted with `password` and return its SS58 address.
+	pub fn generate(&self, key_type: KeyType, password: &str) -> Result<String, String> {
+		let mut seed = [0u8; 32];
+		SystemRandom::new().fill(&mut seed).map_err(|_| "Unable to generate randomness".to_string())?;
+		self.insert(key_type, &format!("0x{}", HexDisplay::from(&seed)), password)
+	}
+
+	/// Type and SS58 address of every key, sorted by address.
+	pub fn list(&self) -> Result<Vec<(KeyType, String)>, String> {
+		let entries = fs::read_dir(&self.path).map_err(|e| format!("Unable to read keystore {:?}: {}", self.path, e))?;
+		let mut keys = Vec::new();
+		for entry in entries {
+			let path = entry.map_err(|e| format!("{}", e))?.path();
+			if path.extension().map_or(false, |extension| extension == "json") {
+				let file = read_key_file(&path)?;
+				keys.push((file.key_type, file.public));
+			}
+		}
+		keys.sort_by(|a, b| a.1.cmp(&b.1));
+		Ok(keys)
+	}
+
+	/// Decrypt the ed25519 key of SS58 address `public`.
+	pub fn ed25519(&self, public: &str, password: &str) -> Result<ed25519::Pair, String> {
+		let suri = self.decrypt(KeyType::Ed25519, public, password)?;
+		ed25519::Pair::from_string(&suri, None).map_err(|e| format!("Invalid stored key: {:?}", e))
+	}
+
+	/// Decrypt the sr25519 key of SS58 address `public` as a VRF keypair. Only keys stored as a
+	/// seed, as `generate` does, can be used: the secret of derived keys is not exposed.
+	pub fn vrf(&self, public: &str, password: &str) -> Result<schnorrkel::Keypair, String> {
+		let suri = self.decrypt(KeyType::Sr25519, public, password)?;
+		let seed = match suri.starts_with("0x") {
+			true => unhex(&suri[2..])?,
+			false => return Err("VRF keys must be stored as a hex seed".into()),
+		};
+		schnorrkel::MiniSecretKey::from_bytes(&seed)
+			.map(|secret| secret.expand_to_keypair())
+			.map_err(|_| "VRF keys must be stored as a hex seed".into())
+	}
+
+	fn decrypt(&self, key_type: KeyType, public: &str, password: &str) -> Result<String, String> {
+		let file = read_key_file(&self.key_path(key_type, public))?;
+		let salt = unhex(&file.salt)?;
+		let mut nonce = [0u8; 12];
+		let nonce_bytes = unhex(&file.nonce)?;
+		if nonce_bytes.len() != nonce.len() {
+			return Err("Invalid nonce in key file".into());
+		}
+		nonce.copy_from_slice(&nonce_bytes);
+
+		let key = aead::OpeningKey::new(&aead::CHACHA20_POLY1305, &derive_key(password, &salt, file.iterations))
+			.map_err(|_| "Unable to create decryption key".to_string())?;
+		let mut in_out = unhex(&file.ciphertext)?;
+		let suri = aead::open_in_place(&key, aead::Nonce::assume_unique_for_key(nonce), aead::Aad::empty(), 0, &mut in_out)
+			.map_err(|_| format!("Wrong password for key {}", public))?;
+		String::from_utf8(suri.to_vec()).map_err(|_| "Invalid stored key".to_string())
+	}
+
+	fn key_path(&self, key_type: KeyType, public: &str) -> PathBuf {
+		self.path.join(format!("{}-{}.json", key_type, public))
+	}
+}
+
+/// Read the keystore password from `password_file`, `NODE_TEMPLATE_PASSWORD` or stdin, in that
+/// order of preference.
+pub fn read_password(password_file: Option<&Path>) -> Result<String, String> {
+	if let Some(path) = password_file {
+		let password = fs::read_to_string(path).map_err(|e| format!("Unable to read password file {:?}: {}", path, e))?;
+		return Ok(password.trim_end_matches(|c| c == '\n' || c == '\r').to_string());
+	}
+	if let Ok(password) = std::env::var(PASSWORD_ENV) {
+		return Ok(password);
+	}
+	read_secret("Keystore password")
+}
+
+/// Prompt for a secret on stderr and read it from stdin, without echoing it if stdin is a
+/// terminal.
+pub fn read_secret(prompt: &str) -> Result<String, String> {
+	rpassword::prompt_password_stderr(&format!("{}: ", prompt))
+		.map(|secret| secret.trim_end_matches(|c| c == '\n' || c == '\r').to_string())
+		.map_err(|e| format!("Unable to read stdin: {}", e))
+}
+
+/// Create or replace the file at `path` with `contents`, readable by its owner only.
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+	let mut options = fs::OpenOptions::new();
+	options.write(true).create(true).truncate(true);
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::OpenOptionsExt;
+		options.mode(0o600);
+	}
+	let mut file = options.open(path)?;
+	// The mode only applies to new files.
+	restrict(path, 0o600)?;
+	file.write_all(contents)?;
+	file.sync_all()
+}
+
+/// Set the permissions of `path` to `mode`.
+#[cfg(unix)]
+fn restrict(path: &Path, mode: u32) -> io::Result<()> {
+	use std::os::unix::fs::PermissionsExt;
+	fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn restrict(_path: &Path, _mode: u32) -> io::Result<()> {
+	Ok(())
+}
+
+fn public_of(key_type: KeyType, suri: &str) -> Result<String, String> {
+	let invalid = |e| format!("Invalid secret URI: {:?}", e);
+	Ok(match key_type {
+		KeyType::Ed25519 => ed25519::Pair::from_string(suri, None).map_err(invalid)?.public().to_ss58check(),
+		KeyType::Sr25519 => sr25519::Pair::from_string(suri, None).map_err(invalid)?.public().to_ss58check(),
+	})
+}
+
+fn derive_key(password: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+	let mut key = [0u8; 32];
+	let iterations = NonZeroU32::new(iterations.max(1)).expect("at least one iteration; qed");
+	pbkdf2::derive(&digest::SHA256, iterations, salt, password.as_bytes(), &mut key);
+	key
+}
+
+fn read_key_file(path: &Path) -> Result<KeyFile, String> {
+	let bytes = fs::read(path).map_err(|e| format!("Unable to read key {:?}: {}", path, e))?;
+	serde_json::from_slice(&bytes).map_err(|e| format!("Invalid key file {:?}: {}", path, e))
+}
+
+fn hex(bytes: &[u8]) -> String {
+	format!("{}", HexDisplay::from(&bytes))
+}
+
+fn unhex(s: &str) -> Result<Vec<u8>, String> {
+	if s.len() % 2 != 0 {
+		return Err(format!("Invalid hex `{}`", s));
+	}
+	(0..s.len()).step_by(2)
+		.map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|_| format!("Invalid hex `{}`", s)))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A keystore in a fresh temporary directory, removed when dropped.
+	struct TempKeystore {
+		dir: PathBuf,
+		keystore: Keystore,
+	}
+
+	impl TempKeystore {
+		fn new(name: &str) -> Self {
+			let dir = std::env::temp_dir().join(format!("node-template-keystore-{}-{}", name, std::process::id()));
+			let _ = fs::remove_dir_all(&dir);
+			let keystore = Keystore::open(&dir).unwrap();
+			TempKeystore { dir, keystore }
+		}
+	}
+
+	impl Drop for TempKeystore {
+		fn drop(&mut self) {
+			let _ = fs::remove_dir_all(&self.dir);
+		}
+	}
+
+	#[test]
+	fn decrypts_with_the_password_only() {
+		let temp = TempKeystore::new("password");
+		let public = temp.keystore.insert(KeyType::Ed25519, "//Alice", "secret").unwrap();
+		let alice = ed25519::Pair::from_string("//Alice", None).unwrap();
+
+		assert_eq!(public, alice.public().to_ss58check());
+		assert_eq!(temp.keystore.ed25519(&public, "secret").unwrap().public(), alice.public());
+		assert_eq!(temp.keystore.list().unwrap(), vec![(KeyType::Ed25519, public.clone())]);
+		assert!(temp.keystore.ed25519(&public, "wrong").is_err());
+	}
+
+	#[test]
+	fn rejects_tampered_ciphertext() {
+		let temp = TempKeystore::new("tampered");
+		let public = temp.keystore.insert(KeyType::Sr25519, "//Bob", "secret").unwrap();
+		let path = temp.keystore.key_path(KeyType::Sr25519, &public);
+		let mut file = read_key_file(&path).unwrap();
+		let mut ciphertext = unhex(&file.ciphertext).unwrap();
+		ciphertext[0] ^= 1;
+		file.ciphertext = hex(&ciphertext);
+		fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+
+		assert!(temp.keystore.decrypt(KeyType::Sr25519, &public, "secret").is_err());
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn only_the_owner_can_read_keys() {
+		use std::os::unix::fs::PermissionsExt;
+
+		let temp = TempKeystore::new("permissions");
+		let public = temp.keystore.generate(KeyType::Ed25519, "secret").unwrap();
+		let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+		assert_eq!(mode(&temp.keystore.path), 0o700);
+		assert_eq!(mode(&temp.keystore.key_path(KeyType::Ed25519, &public)), 0o600);
+	}
+}
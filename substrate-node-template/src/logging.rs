@@ -0,0 +1,340 @@
+//! JSON and per-target file output for the node logs.
+//!
+//! `substrate_cli` always installs its own logger, printing human readable lines to stderr, and
+//! a process only has one logger: `parse_and_execute` installs it with `env_logger`'s `init`,
+//! which panics if another logger was installed before. When `--log-format json` or `--log-dir`
+//! is given, the lines written to stderr are captured instead, split back into level, target
+//! and message, and written out again as JSON, into a per-target file rotated past a size
+//! limit, or both. Lines continuing a multi-line message keep the level and target of their
+//! record.
+//!
+//! Substrate only prints the level and target of a record once debug logging is enabled for
+//! some target, so the maximum log level is raised to `Debug`. The logger still filters records
+//! by `-l`/`--log` and `RUST_LOG`, raising the maximum level only lets them reach it.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use log::LevelFilter;
+use serde_json::json;
+use crate::cli::NodeParams;
+
+/// Best block number attached to every JSON record, kept up to date by the service.
+static BLOCK_NUMBER: AtomicU64 = AtomicU64::new(0);
+
+/// Format of the records written by the logger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+	/// Human readable lines, as printed by `substrate_cli`.
+	Text,
+	/// One JSON object per line.
+	Json,
+}
+
+impl std::str::FromStr for LogFormat {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"text" => Ok(LogFormat::Text),
+			"json" => Ok(LogFormat::Json),
+			_ => Err(format!("Unknown log format `{}`, expected `text` or `json`", s)),
+		}
+	}
+}
+
+/// Logging options of the `run` command.
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+	/// Format of the records.
+	pub format: LogFormat,
+	/// Directory receiving one file per log target.
+	pub dir: Option<PathBuf>,
+	/// Size in bytes after which a log file is rotated.
+	pub max_size: u64,
+	/// Number of rotated files kept per target.
+	pub keep: usize,
+}
+
+impl LogConfig {
+	/// The logging options given on the command line.
+	pub fn from_params(params: &NodeParams) -> Self {
+		LogConfig {
+			format: params.log_format,
+			dir: params.log_dir.clone(),
+			max_size: params.log_max_size * 1024 * 1024,
+			keep: params.log_keep,
+		}
+	}
+}
+
+/// Capture the records `substrate_cli` writes to stderr and write them out as `config` asks,
+/// unless it asks for plain text on stderr only.
+pub fn init(config: LogConfig) -> Result<(), String> {
+	if config.format == LogFormat::Text && config.dir.is_none() {
+		return Ok(());
+	}
+	if let Some(dir) = &config.dir {
+		fs::create_dir_all(dir).map_err(|e| format!("Unable to create log directory {:?}: {}", dir, e))?;
+	}
+
+	let (captured, stderr) = capture_stderr()?;
+	if log::max_level() < LevelFilter::Debug {
+		log::set_max_level(LevelFilter::Debug);
+	}
+
+	let mut writer = LogWriter { config, stderr, files: HashMap::new(), last: None };
+	std::thread::Builder::new()
+		.name("log-writer".into())
+		.spawn(move || {
+			for line in BufReader::new(captured).lines() {
+				match line {
+					Ok(line) => writer.write(&line),
+					Err(_) => break,
+				}
+			}
+		})
+		.map(|_| ())
+		.map_err(|e| format!("Unable to start the log writer: {}", e))
+}
+
+/// Record the best block number reported in subsequent log records.
+pub fn set_block_number(number: u64) {
+	BLOCK_NUMBER.store(number, Ordering::Relaxed);
+}
+
+/// Point stderr at a pipe, returning its reading end and a handle on the original stderr.
+#[cfg(unix)]
+fn capture_stderr() -> Result<(File, File), String> {
+	use std::os::unix::io::FromRawFd;
+
+	let mut fds = [0; 2];
+	// Safe as `fds` holds the two descriptors `pipe` writes, and the descriptors `dup` returns
+	// are owned by the returned files only.
+	unsafe {
+		if libc::pipe(fds.as_mut_ptr()) != 0 {
+			return Err(format!("Unable to capture stderr: {}", io::Error::last_os_error()));
+		}
+		let stderr = libc::dup(libc::STDERR_FILENO);
+		if stderr < 0 || libc::dup2(fds[1], libc::STDERR_FILENO) < 0 {
+			return Err(format!("Unable to capture stderr: {}", io::Error::last_os_error()));
+		}
+		libc::close(fds[1]);
+		Ok((File::from_raw_fd(fds[0]), File::from_raw_fd(stderr)))
+	}
+}
+
+#[cfg(not(unix))]
+fn capture_stderr() -> Result<(File, File), String> {
+	Err("`--log-format json` and `--log-dir` are only supported on unix".into())
+}
+
+/// A record printed by `substrate_cli`, with debug logging enabled.
+struct Line<'a> {
+	thread: &'a str,
+	level: &'a str,
+	target: &'a str,
+	message: &'a str,
+}
+
+impl<'a> Line<'a> {
+	/// Split `line`, formatted as `<date> <time> <thread> <level> <target>  <message>` with an
+	/// empty thread name for unnamed threads. Lines of multi-line messages do not parse.
+	fn parse(line: &'a str) -> Option<Self> {
+		let rest = line.splitn(3, ' ').nth(2)?;
+		let mut parts = rest.splitn(3, ' ');
+		let thread = parts.next()?;
+		let level = parts.next()?;
+		let mut rest = parts.next()?.splitn(2, "  ");
+		let target = rest.next()?;
+		let message = rest.next()?;
+		match level {
+			"ERROR" | "WARN" | "INFO" | "DEBUG" | "TRACE" => Some(Line { thread, level, target, message }),
+			_ => None,
+		}
+	}
+}
+
+/// Writes captured records to the original stderr and the per-target files.
+struct LogWriter {
+	config: LogConfig,
+	stderr: File,
+	files: HashMap<String, LogFile>,
+	/// Thread, level and target of the last record, which continuation lines belong to.
+	last: Option<(String, String, String)>,
+}
+
+struct LogFile {
+	path: PathBuf,
+	file: File,
+	size: u64,
+}
+
+impl LogWriter {
+	fn write(&mut self, line: &str) {
+		let line = strip_colors(line);
+		// Lines that do not parse continue the message of the previous record. Before the
+		// first record, they were not logged at all, like early panic messages.
+		let record = match Line::parse(&line) {
+			Some(record) => {
+				self.last = Some((record.thread.to_string(), record.level.to_string(), record.target.to_string()));
+				record
+			},
+			None => match &self.last {
+				Some((thread, level, target)) => Line { thread, level, target, message: &line },
+				None => Line { thread: "", level: "INFO", target: "node", message: &line },
+			},
+		};
+		let formatted = format_line(self.config.format, &record, chrono::Local::now(), BLOCK_NUMBER.load(Ordering::Relaxed));
+		let target = record.target.to_string();
+
+		let _ = writeln!(self.stderr, "{}", formatted);
+		if let Err(e) = self.write_to_file(&target, &formatted) {
+			let _ = writeln!(self.stderr, "Unable to write log file for {}: {}", target, e);
+		}
+	}
+
+	fn write_to_file(&mut self, target: &str, line: &str) -> io::Result<()> {
+		let dir = match &self.config.dir {
+			Some(dir) => dir,
+			None => return Ok(()),
+		};
+		// `sync::import_queue` and `sync` share a file.
+		let target = target.split("::").next().unwrap_or(target).to_string();
+
+		if !self.files.contains_key(&target) {
+			let path = dir.join(format!("{}.log", target));
+			let file = OpenOptions::new().create(true).append(true).open(&path)?;
+			let size = file.metadata()?.len();
+			self.files.insert(target.clone(), LogFile { path, file, size });
+		}
+		let log_file = self.files.get_mut(&target).expect("inserted above if missing; qed");
+
+		writeln!(log_file.file, "{}", line)?;
+		log_file.size += line.len() as u64 + 1;
+		if log_file.size >= self.config.max_size {
+			log_file.rotate(self.config.keep)?;
+		}
+		Ok(())
+	}
+}
+
+/// `record` as a line of `format`, logged at `timestamp` while `block` was the best block.
+fn format_line(format: LogFormat, record: &Line, timestamp: chrono::DateTime<chrono::Local>, block: u64) -> String {
+	match format {
+		LogFormat::Text => format!(
+			"{} {} {} {}",
+			timestamp.format("%Y-%m-%d %H:%M:%S"),
+			record.level,
+			record.target,
+			record.message,
+		),
+		LogFormat::Json => json!({
+			"timestamp": timestamp.to_rfc3339(),
+			"level": record.level,
+			"target": record.target,
+			"message": record.message,
+			"block": block,
+			"thread": if record.thread.is_empty() { "<unnamed>" } else { record.thread },
+		}).to_string(),
+	}
+}
+
+/// `line` without the ANSI color codes `substrate_cli` prints when stderr was a terminal.
+fn strip_colors(line: &str) -> String {
+	let mut out = String::with_capacity(line.len());
+	let mut escaped = false;
+	for c in line.chars() {
+		match (escaped, c) {
+			(false, '\x1b') => escaped = true,
+			(false, c) => out.push(c),
+			// The escape sequence ends with a letter.
+			(true, c) => escaped = !c.is_ascii_alphabetic(),
+		}
+	}
+	out
+}
+
+impl LogFile {
+	/// Shift `target.log.N` to `target.log.N+1`, dropping the oldest, and start a new file.
+	fn rotate(&mut self, keep: usize) -> io::Result<()> {
+		let rotated = |n: usize| {
+			let mut path = self.path.clone().into_os_string();
+			path.push(format!(".{}", n));
+			PathBuf::from(path)
+		};
+
+		if keep == 0 {
+			fs::remove_file(&self.path)?;
+		} else {
+			for n in (1..keep).rev() {
+				if rotated(n).exists() {
+					fs::rename(rotated(n), rotated(n + 1))?;
+				}
+			}
+			fs::rename(&self.path, rotated(1))?;
+		}
+
+		self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+		self.size = 0;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use chrono::TimeZone;
+
+	#[test]
+	fn parses_and_formats_records() {
+		let line = strip_colors("2019-03-01 12:00:00.042 \x1b[1;34mimport-queue\x1b[0m INFO sync::import_queue  Imported #12 (0x1234)");
+		let record = Line::parse(&line).expect("a record with debug logging enabled");
+		assert_eq!(
+			(record.thread, record.level, record.target, record.message),
+			("import-queue", "INFO", "sync::import_queue", "Imported #12 (0x1234)"),
+		);
+		assert!(Line::parse("  at src/main.rs:12").is_none());
+
+		let timestamp = chrono::Local.ymd(2019, 3, 1).and_hms(12, 0, 0);
+		assert_eq!(
+			format_line(LogFormat::Text, &record, timestamp, 12),
+			"2019-03-01 12:00:00 INFO sync::import_queue Imported #12 (0x1234)",
+		);
+		let json: serde_json::Value = serde_json::from_str(&format_line(LogFormat::Json, &record, timestamp, 12)).unwrap();
+		assert_eq!(json["level"], "INFO");
+		assert_eq!(json["target"], "sync::import_queue");
+		assert_eq!(json["message"], "Imported #12 (0x1234)");
+		assert_eq!(json["block"], 12);
+		assert_eq!(json["thread"], "import-queue");
+		assert_eq!(json["timestamp"], timestamp.to_rfc3339());
+	}
+
+	#[test]
+	fn rotates_and_keeps_the_newest_files() {
+		let dir = std::env::temp_dir().join(format!("node-template-logging-{}", std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		let path = dir.join("sync.log");
+		let mut file = LogFile {
+			path: path.clone(),
+			file: OpenOptions::new().create(true).append(true).open(&path).unwrap(),
+			size: 0,
+		};
+		let rotated = |n: usize| dir.join(format!("sync.log.{}", n));
+
+		for n in 1..=3 {
+			writeln!(file.file, "{}", n).unwrap();
+			file.rotate(2).unwrap();
+		}
+		writeln!(file.file, "4").unwrap();
+
+		assert_eq!(fs::read_to_string(&path).unwrap(), "4\n");
+		assert_eq!(fs::read_to_string(rotated(1)).unwrap(), "3\n");
+		assert_eq!(fs::read_to_string(rotated(2)).unwrap(), "2\n");
+		assert!(!rotated(3).exists());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -6,6 +6,22 @@
 mod chain_spec;
 mod service;
 mod cli;
+mod metrics;
+mod logging;
+mod state_snapshot;
+mod replay;
+mod try_upgrade;
+mod benchmark;
+mod pool;
+mod rpc;
+mod explorer;
+mod testnet;
+mod keystore;
+mod equivocation;
+mod block_time;
+mod randomness;
+mod permissions;
+mod authorities;
 
 pub use substrate_cli::{VersionInfo, IntoExit, error};
 
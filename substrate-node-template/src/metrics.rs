@@ -0,0 +1,340 @@
+//! Prometheus metrics for the node service.
+//!
+//! Metrics are always collected, but only served over HTTP when the node is started with
+//! `--prometheus-port`. The endpoint answers every request with the text exposition format.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use futures::{Future, IntoFuture, Stream};
+use hyper::{Body, Response, Server, header, service::service_fn_ok};
+use log::{info, warn};
+use consensus::CompatibleDigestItem;
+use consensus_authorities::AuthoritiesApi;
+use consensus_common::{BlockOrigin, Environment, Proposer, AuthorityIdFor};
+use inherents::InherentData;
+use primitives::{ed25519, Pair};
+use runtime_primitives::generic::BlockId;
+use runtime_primitives::traits::{As, Block as BlockT, Digest as DigestT, Header as HeaderT};
+use substrate_client::runtime_api::ProvideRuntimeApi;
+use substrate_service::{Components, Service, TaskExecutor};
+use node_template_runtime::opaque::Header;
+use crate::explorer::FullClient;
+use crate::keystore::AuthorityKey;
+
+/// Metrics gathered from the client, network and authoring handles of the service.
+#[derive(Default)]
+pub struct Metrics {
+	best_block: AtomicU64,
+	finalized_block: AtomicU64,
+	peers: AtomicU64,
+	ready_transactions: AtomicU64,
+	future_transactions: AtomicU64,
+	sync_gap: AtomicU64,
+	import_queue_length: AtomicU64,
+	slots_authored: AtomicU64,
+	slots_missed: AtomicU64,
+	proposals_failed: AtomicU64,
+	authoring_duration_ms_sum: AtomicU64,
+	authoring_duration_ms_last: AtomicU64,
+}
+
+impl Metrics {
+	/// Record the outcome of a single block proposal.
+	///
+	/// A proposal that fails or takes longer than the time the slot allows counts as failed. Slots
+	/// the node never got to propose in are counted by `start_counting_missed_slots`.
+	pub fn record_authoring(&self, duration: Duration, max_duration: Duration, success: bool) {
+		let millis = duration.as_secs() * 1_000 + u64::from(duration.subsec_millis());
+		self.authoring_duration_ms_last.store(millis, Ordering::Relaxed);
+		self.authoring_duration_ms_sum.fetch_add(millis, Ordering::Relaxed);
+		if success && duration <= max_duration {
+			self.slots_authored.fetch_add(1, Ordering::Relaxed);
+		} else {
+			self.proposals_failed.fetch_add(1, Ordering::Relaxed);
+		}
+	}
+
+	/// Record `count` slots of the local authority key that ended without a block.
+	pub fn record_missed_slots(&self, count: u64) {
+		self.slots_missed.fetch_add(count, Ordering::Relaxed);
+	}
+
+	/// Render all metrics in the Prometheus text exposition format.
+	pub fn render(&self) -> String {
+		let mut out = String::new();
+		let mut metric = |name: &str, kind: &str, help: &str, value: &AtomicU64| {
+			out.push_str(&format!("# HELP {} {}\n", name, help));
+			out.push_str(&format!("# TYPE {} {}\n", name, kind));
+			out.push_str(&format!("{} {}\n", name, value.load(Ordering::Relaxed)));
+		};
+
+		metric("node_template_block_height", "gauge", "Best block number.", &self.best_block);
+		metric("node_template_finalized_height", "gauge", "Last finalized block number.", &self.finalized_block);
+		metric("node_template_peers", "gauge", "Number of connected peers.", &self.peers);
+		metric("node_template_txpool_ready", "gauge", "Ready transactions in the pool.", &self.ready_transactions);
+		metric("node_template_txpool_future", "gauge", "Future transactions in the pool.", &self.future_transactions);
+		metric(
+			"node_template_sync_gap_blocks",
+			"gauge",
+			"Blocks between the best block seen on the network and our best block.",
+			&self.sync_gap,
+		);
+		metric(
+			"node_template_import_queue_length",
+			"gauge",
+			"Blocks queued for import or being imported.",
+			&self.import_queue_length,
+		);
+		metric("node_template_aura_slots_authored_total", "counter", "Slots we authored a block for.", &self.slots_authored);
+		metric(
+			"node_template_aura_slots_missed_total",
+			"counter",
+			"Slots of our authority key that ended without a block on the best chain.",
+			&self.slots_missed,
+		);
+		metric(
+			"node_template_aura_proposals_failed_total",
+			"counter",
+			"Block proposals that failed or overran their slot.",
+			&self.proposals_failed,
+		);
+		metric(
+			"node_template_block_authoring_duration_ms_sum",
+			"counter",
+			"Total time spent proposing blocks, in milliseconds.",
+			&self.authoring_duration_ms_sum,
+		);
+		metric(
+			"node_template_block_authoring_duration_ms",
+			"gauge",
+			"Time spent proposing the last block, in milliseconds.",
+			&self.authoring_duration_ms_last,
+		);
+		out
+	}
+}
+
+/// Keep `metrics` up to date with the state of `service` until `exit` fires.
+pub fn start_collecting<C, E>(service: &Service<C>, metrics: Arc<Metrics>, exit: E, executor: TaskExecutor) where
+	C: Components,
+	E: IntoFuture<Item=(), Error=()>,
+	E::Future: Send + 'static,
+{
+	let client = service.client();
+	let txpool = service.transaction_pool();
+	let import_queue = service.config.custom.import_queue.clone();
+
+	// The network emits a status roughly once a second, which is the same cadence the informant uses.
+	let collect = service.network().status().for_each(move |status| {
+		if let Ok(info) = client.info() {
+			let best: u64 = info.chain.best_number.as_();
+			let best_seen: u64 = status.best_seen_block.map(|n| n.as_()).unwrap_or(best);
+			metrics.best_block.store(best, Ordering::Relaxed);
+			crate::logging::set_block_number(best);
+			metrics.finalized_block.store(info.chain.finalized_number.as_(), Ordering::Relaxed);
+			metrics.sync_gap.store(best_seen.saturating_sub(best), Ordering::Relaxed);
+		}
+		metrics.peers.store(status.num_peers as u64, Ordering::Relaxed);
+		if let Some(queue) = import_queue.lock().expect("the lock is never held across a panic; qed").as_ref() {
+			metrics.import_queue_length.store(queue.importing() as u64, Ordering::Relaxed);
+		}
+
+		let pool_status = txpool.status();
+		metrics.ready_transactions.store(pool_status.ready as u64, Ordering::Relaxed);
+		metrics.future_transactions.store(pool_status.future as u64, Ordering::Relaxed);
+		Ok(())
+	});
+
+	executor.spawn(exit.into_future().select(collect).then(|_| Ok(())));
+}
+
+/// Count the slots of `key` that ended without a block into `metrics`, until `exit` fires.
+///
+/// Every new best block is compared with its parent: the slots between theirs belong to
+/// authorities that did not author a block, and those `key` owned in the authority set of the
+/// parent are missed. Blocks imported during the initial sync are skipped, as they may predate
+/// the node being an authority.
+pub fn start_counting_missed_slots<E>(
+	client: Arc<FullClient>,
+	key: Arc<AuthorityKey>,
+	metrics: Arc<Metrics>,
+	exit: E,
+	executor: &TaskExecutor,
+) where
+	E: IntoFuture<Item=(), Error=()>,
+	E::Future: Send + 'static,
+{
+	let count = client.import_notification_stream()
+		.filter(|notification| notification.is_new_best && notification.origin != BlockOrigin::NetworkInitialSync)
+		.for_each(move |notification| {
+			let parent = match client.header(&BlockId::Hash(*notification.header.parent_hash())) {
+				Ok(Some(parent)) => parent,
+				_ => return Ok(()),
+			};
+			// The genesis block has no slot.
+			let (from, to) = match (slot_of(&parent), slot_of(&notification.header)) {
+				(Some(from), Some(to)) => (from, to),
+				_ => return Ok(()),
+			};
+			let authorities = match client.runtime_api().authorities(&BlockId::Hash(parent.hash())) {
+				Ok(authorities) => authorities,
+				Err(e) => {
+					warn!("Unable to read the authorities: {:?}", e);
+					return Ok(());
+				},
+			};
+			let public = key.public();
+			let missed = authorities.iter()
+				.enumerate()
+				.filter(|(_, authority)| **authority == public)
+				.map(|(index, _)| owned_between(from, to, authorities.len() as u64, index as u64))
+				.sum();
+			if missed > 0 {
+				metrics.record_missed_slots(missed);
+			}
+			Ok(())
+		});
+
+	executor.spawn(exit.into_future().select(count).then(|_| Ok(())));
+}
+
+/// The Aura slot `header` was sealed for.
+fn slot_of(header: &Header) -> Option<u64> {
+	header.digest().logs().iter()
+		.filter_map(|item| CompatibleDigestItem::<ed25519::Pair>::as_aura_seal(item))
+		.map(|(slot, _)| slot)
+		.next()
+}
+
+/// Number of slots strictly between `from` and `to` owned by the authority at `index` of a set
+/// of `authorities`.
+fn owned_between(from: u64, to: u64, authorities: u64, index: u64) -> u64 {
+	// Slots up to and including `slot` owned by the authority.
+	let owned_up_to = |slot: u64| match slot < index {
+		true => 0,
+		false => (slot - index) / authorities + 1,
+	};
+	match to > from + 1 {
+		true => owned_up_to(to - 1) - owned_up_to(from),
+		false => 0,
+	}
+}
+
+/// Serve `metrics` over HTTP on `port` until `exit` fires.
+pub fn start_server<E>(port: u16, metrics: Arc<Metrics>, exit: E, executor: TaskExecutor) -> Result<(), String> where
+	E: IntoFuture<Item=(), Error=()>,
+	E::Future: Send + 'static,
+{
+	let addr = SocketAddr::from(([127, 0, 0, 1], port));
+	let server = Server::try_bind(&addr)
+		.map_err(|e| format!("Unable to bind Prometheus endpoint to {}: {}", addr, e))?
+		.serve(move || {
+			let metrics = metrics.clone();
+			service_fn_ok(move |_| {
+				Response::builder()
+					.header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+					.body(Body::from(metrics.render()))
+					.expect("static header and body are valid; qed")
+			})
+		})
+		.map_err(|e| warn!("Prometheus endpoint error: {:?}", e));
+
+	info!("Prometheus metrics served on http://{}/metrics", addr);
+	executor.spawn(exit.into_future().select(server).then(|_| Ok(())));
+	Ok(())
+}
+
+/// Block authoring environment that reports proposal timings to `Metrics`.
+pub struct MeteredEnvironment<E> {
+	inner: E,
+	metrics: Arc<Metrics>,
+}
+
+impl<E> MeteredEnvironment<E> {
+	/// Wrap `inner`, recording every proposal it creates into `metrics`.
+	pub fn new(inner: E, metrics: Arc<Metrics>) -> Self {
+		MeteredEnvironment { inner, metrics }
+	}
+}
+
+impl<B: BlockT, E: Environment<B>> Environment<B> for MeteredEnvironment<E> {
+	type Proposer = MeteredProposer<E::Proposer>;
+	type Error = E::Error;
+
+	fn init(
+		&self,
+		parent_header: &B::Header,
+		authorities: &[AuthorityIdFor<B>],
+	) -> Result<Self::Proposer, Self::Error> {
+		self.inner.init(parent_header, authorities).map(|inner| MeteredProposer {
+			inner,
+			metrics: self.metrics.clone(),
+		})
+	}
+}
+
+/// Proposer created by `MeteredEnvironment`.
+pub struct MeteredProposer<P> {
+	inner: P,
+	metrics: Arc<Metrics>,
+}
+
+impl<B, P> Proposer<B> for MeteredProposer<P> where
+	B: BlockT,
+	P: Proposer<B>,
+	<P::Create as IntoFuture>::Future: Send + 'static,
+{
+	type Error = P::Error;
+	type Create = Box<dyn Future<Item=B, Error=P::Error> + Send>;
+
+	fn propose(&self, inherent_data: InherentData, max_duration: Duration) -> Self::Create {
+		let started = Instant::now();
+		let metrics = self.metrics.clone();
+		Box::new(self.inner.propose(inherent_data, max_duration).into_future().then(move |result| {
+			metrics.record_authoring(started.elapsed(), max_duration, result.is_ok());
+			result
+		}))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn render_exposes_every_metric() {
+		let metrics = Metrics::default();
+		metrics.best_block.store(42, Ordering::Relaxed);
+		metrics.import_queue_length.store(3, Ordering::Relaxed);
+		metrics.record_authoring(Duration::from_millis(200), Duration::from_secs(1), true);
+		metrics.record_authoring(Duration::from_secs(2), Duration::from_secs(1), true);
+		metrics.record_missed_slots(2);
+
+		let rendered = metrics.render();
+		assert!(rendered.contains(
+			"# HELP node_template_block_height Best block number.\n\
+			 # TYPE node_template_block_height gauge\n\
+			 node_template_block_height 42\n"
+		));
+		assert!(rendered.contains("\nnode_template_import_queue_length 3\n"));
+		assert!(rendered.contains("\nnode_template_aura_slots_authored_total 1\n"));
+		assert!(rendered.contains("\nnode_template_aura_proposals_failed_total 1\n"));
+		assert!(rendered.contains("\nnode_template_aura_slots_missed_total 2\n"));
+		assert!(rendered.contains("\nnode_template_block_authoring_duration_ms_sum 2200\n"));
+		assert!(rendered.contains("\nnode_template_block_authoring_duration_ms 2000\n"));
+		assert_eq!(rendered.lines().filter(|line| line.starts_with("# TYPE")).count(), 12);
+	}
+
+	#[test]
+	fn owned_slots_between_two_blocks() {
+		// Authority 1 of 3 owns slots 1, 4, 7, ...
+		assert_eq!(owned_between(3, 5, 3, 1), 1);
+		assert_eq!(owned_between(4, 7, 3, 1), 0);
+		assert_eq!(owned_between(0, 11, 3, 1), 4);
+		assert_eq!(owned_between(5, 6, 3, 1), 0);
+		assert_eq!(owned_between(6, 6, 1, 0), 0);
+		assert_eq!(owned_between(0, 10, 1, 0), 9);
+	}
+}
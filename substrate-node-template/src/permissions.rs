@@ -0,0 +1,123 @@
+//! Peer allow-listing from the node IDs kept by the `Permissions` runtime module.
+//!
+//! With `--peer-allow-list`, the node only connects to reserved peers, and only the nodes of
+//! `--reserved-nodes` and `--bootnodes` whose ID is allowed at the best block are reserved. The
+//! list is read again whenever the best block changes, so nodes removed on chain are dropped
+//! and nodes added are connected to. Allowed nodes need a known address to be reached, the
+//! chain only decides which of them are allowed.
+//!
+//! A new node only knows the allow-list of the blocks it has imported, which at first is empty
+//! or outdated. Its boot nodes are therefore reserved during the initial sync whether allowed
+//! or not, and only checked against the allow-list once it imports a block that is not part of
+//! the initial sync.
+
+use std::collections::HashSet;
+use std::mem;
+use std::sync::Arc;
+use futures::{Future, IntoFuture, Stream};
+use log::{info, warn};
+use consensus_common::BlockOrigin;
+use network::{ManageNetwork, PeerId};
+use network::config::{NetworkConfiguration, NonReservedPeerMode};
+use runtime_primitives::generic::BlockId;
+use substrate_client::runtime_api::ProvideRuntimeApi;
+use substrate_service::TaskExecutor;
+use node_template_runtime::{api_ext::PermissionsApi, opaque::Block};
+use crate::explorer::FullClient;
+
+/// A node that can be reserved, with its address.
+pub struct Candidate {
+	peer_id: PeerId,
+	address: String,
+	/// Given with `--bootnodes`, so reserved until the node is synced.
+	boot: bool,
+}
+
+/// Only accept reserved peers, taking the reserved nodes out of `config` so none is reserved
+/// before its ID is checked. Returns the nodes to reserve once allowed.
+pub fn configure(config: &mut NetworkConfiguration) -> Vec<Candidate> {
+	config.non_reserved_mode = NonReservedPeerMode::Deny;
+	let reserved = mem::replace(&mut config.reserved_nodes, Vec::new());
+	reserved.into_iter()
+		.map(|address| (address, false))
+		.chain(config.boot_nodes.iter().map(|address| (address.clone(), true)))
+		.filter_map(|(address, boot)| match peer_id_of(&address) {
+			Some(peer_id) => Some(Candidate { peer_id, address, boot }),
+			None => {
+				warn!("Ignoring node `{}` without a node ID", address);
+				None
+			},
+		})
+		.collect()
+}
+
+/// The ID of the node at `address`, ending with `/p2p/<ID>`.
+fn peer_id_of(address: &str) -> Option<PeerId> {
+	match address.rfind("/p2p/") {
+		Some(i) => address[i + "/p2p/".len()..].parse().ok(),
+		None => None,
+	}
+}
+
+/// Keep the reserved peers of `network` to the allowed `candidates` until `exit` fires.
+pub fn follow<N, E>(
+	client: Arc<FullClient>,
+	network: Arc<N>,
+	candidates: Vec<Candidate>,
+	exit: E,
+	executor: &TaskExecutor,
+) where
+	N: ManageNetwork + Send + Sync + 'static,
+	E: IntoFuture<Item=(), Error=()>,
+	E::Future: Send + 'static,
+{
+	let mut reserved = HashSet::new();
+	let update_client = client.clone();
+	let mut update = move |at: BlockId<Block>, synced: bool| {
+		let allowed = match update_client.runtime_api().allowed_nodes(&at) {
+			Ok(allowed) => allowed.iter()
+				.filter_map(|id| std::str::from_utf8(id).ok()?.parse::<PeerId>().ok())
+				.collect::<HashSet<_>>(),
+			Err(e) => {
+				warn!("Unable to read the allowed nodes: {:?}", e);
+				return;
+			},
+		};
+		for candidate in &candidates {
+			let allowed = allowed.contains(&candidate.peer_id) || (candidate.boot && !synced);
+			match (allowed, reserved.contains(&candidate.peer_id)) {
+				(true, false) => match network.add_reserved_peer(candidate.address.clone()) {
+					Ok(()) => {
+						info!("Node {} is allowed", candidate.peer_id.to_base58());
+						reserved.insert(candidate.peer_id.clone());
+					},
+					Err(e) => warn!("Unable to reserve {}: {}", candidate.address, e),
+				},
+				(false, true) => {
+					info!("Node {} is no longer allowed", candidate.peer_id.to_base58());
+					network.remove_reserved_peer(candidate.peer_id.clone());
+					reserved.remove(&candidate.peer_id);
+				},
+				_ => {},
+			}
+		}
+	};
+
+	match client.info() {
+		Ok(info) => update(BlockId::Hash(info.chain.best_hash), false),
+		Err(e) => warn!("Unable to read the best block: {:?}", e),
+	}
+	let mut synced = false;
+	let updates = client.import_notification_stream()
+		.filter(|notification| notification.is_new_best)
+		.for_each(move |notification| {
+			if notification.origin != BlockOrigin::NetworkInitialSync && !synced {
+				info!("Synced, only allowed nodes are reserved from now on");
+				synced = true;
+			}
+			update(BlockId::Hash(notification.hash), synced);
+			Ok(())
+		});
+
+	executor.spawn(exit.into_future().select(updates).then(|_| Ok(())));
+}
@@ -0,0 +1,262 @@
+//! Transaction pool policy of the node.
+//!
+//! The total number and size of pooled transactions are capped by the `--pool-limit` and
+//! `--pool-kbytes` options of the pool itself, which evicts the lowest priority transactions
+//! once it is full. On top of that, `LimitedChainApi` rejects the transactions of senders that
+//! already have `PoolPolicy::sender_limit` transactions in the pool, and bans transactions that
+//! can never become valid for a configurable time so they are not validated over and over
+//! again. Transactions that may become valid later, such as ones whose sender cannot pay yet,
+//! is filtered or has no account index yet, are not banned.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use log::debug;
+use parity_codec::Encode;
+use parking_lot::{Mutex, RwLock};
+use primitives::H256;
+use runtime_primitives::generic::BlockId;
+use runtime_primitives::traits::ProvideRuntimeApi;
+use runtime_primitives::ApplyError;
+use runtime_primitives::transaction_validity::TransactionValidity;
+use transaction_pool::{self, txpool::{self, ChainApi, Pool, ExtrinsicFor, NumberFor, BlockHash}};
+use node_template_runtime::{opaque::Block, api_ext::TransactionInfo};
+
+/// `TransactionValidity::Invalid` code of transactions beyond the sender limit.
+const SENDER_LIMIT_REACHED: i8 = -16;
+/// `TransactionValidity::Invalid` code of banned transactions.
+const BANNED: i8 = -17;
+
+/// Counts the transactions in the pool providing a tag that starts with the given encoded
+/// sender, other than the one with the given hash.
+type SenderCount = Box<dyn Fn(&[u8], &H256) -> usize + Send + Sync>;
+
+/// Pool policy of the node, set from the command line.
+pub struct PoolPolicy {
+	/// Maximum number of transactions a sender may have in the pool.
+	pub sender_limit: usize,
+	/// Time transactions that can never become valid are rejected for without validating them.
+	pub ban_time: Duration,
+	/// Counts the transactions of a sender once the pool is built.
+	sender_count: RwLock<Option<SenderCount>>,
+}
+
+impl PoolPolicy {
+	/// A policy with the given limits.
+	pub fn new(sender_limit: usize, ban_time: Duration) -> Self {
+		PoolPolicy { sender_limit, ban_time, sender_count: RwLock::new(None) }
+	}
+
+	/// Count the transactions of senders in `pool`, which must be the pool the policy applies to.
+	pub fn watch<A>(&self, pool: &Arc<Pool<A>>) where
+		A: ChainApi<Hash = H256> + 'static,
+	{
+		let pool: Weak<Pool<A>> = Arc::downgrade(pool);
+		*self.sender_count.write() = Some(Box::new(move |sender, hash| {
+			let pool = match pool.upgrade() {
+				Some(pool) => pool,
+				None => return 0,
+			};
+			let is_from_sender = |tx_hash: &H256, provides: &[Vec<u8>]|
+				tx_hash != hash && provides.iter().any(|tag| tag.starts_with(sender));
+			let ready = pool.ready().filter(|tx| is_from_sender(&tx.hash, &tx.provides)).count();
+			let future = pool.futures().into_iter().filter(|tx| is_from_sender(&tx.hash, &tx.provides)).count();
+			ready + future
+		}));
+	}
+
+	/// Number of transactions `sender` has in the pool, besides `hash`.
+	fn sender_count(&self, sender: &[u8], hash: &H256) -> usize {
+		self.sender_count.read().as_ref().map_or(0, |count| count(sender, hash))
+	}
+}
+
+impl Default for PoolPolicy {
+	fn default() -> Self {
+		PoolPolicy::new(16, Duration::from_secs(30 * 60))
+	}
+}
+
+thread_local! {
+	// The pool is built by `FullComponents::new` from the transaction pool options only, so the
+	// policy is handed to it on the thread building the service.
+	static HANDED_OVER: RefCell<Option<Arc<PoolPolicy>>> = RefCell::new(None);
+}
+
+/// Make `policy` the policy of the next `LimitedChainApi` built on this thread.
+pub fn hand_over(policy: Arc<PoolPolicy>) {
+	HANDED_OVER.with(|handed_over| *handed_over.borrow_mut() = Some(policy));
+}
+
+/// A `txpool::ChainApi` enforcing the node's pool policy on top of the runtime's validation.
+pub struct LimitedChainApi<C> {
+	inner: transaction_pool::ChainApi<C, Block>,
+	client: Arc<C>,
+	policy: Arc<PoolPolicy>,
+	banned: Mutex<HashMap<H256, Instant>>,
+}
+
+impl<C> LimitedChainApi<C> {
+	/// Create a new instance validating transactions against `client`, with the policy handed
+	/// over on this thread or the default one.
+	pub fn new(client: Arc<C>) -> Self {
+		let policy = HANDED_OVER.with(|handed_over| handed_over.borrow_mut().take()).unwrap_or_default();
+		LimitedChainApi {
+			inner: transaction_pool::ChainApi::new(client.clone()),
+			client,
+			policy,
+			banned: Mutex::new(HashMap::new()),
+		}
+	}
+
+	/// Whether `hash` is banned, forgetting bans that have expired.
+	fn is_banned(&self, hash: &H256) -> bool {
+		let now = Instant::now();
+		let mut banned = self.banned.lock();
+		banned.retain(|_, until| *until > now);
+		banned.contains_key(hash)
+	}
+
+	fn ban(&self, hash: H256) {
+		self.banned.lock().insert(hash, Instant::now() + self.policy.ban_time);
+	}
+}
+
+/// Whether a transaction rejected with `code` stays invalid whatever the state: its signature
+/// is wrong, its nonce was used or it is too large for any block.
+fn is_permanent(code: i8) -> bool {
+	[ApplyError::BadSignature, ApplyError::Stale, ApplyError::FullBlock]
+		.iter()
+		.any(|e| *e as i8 == code)
+}
+
+impl<C> txpool::ChainApi for LimitedChainApi<C> where
+	C: ProvideRuntimeApi + Send + Sync + 'static,
+	C::Api: TransactionInfo<Block>,
+	transaction_pool::ChainApi<C, Block>: txpool::ChainApi<
+		Block = Block,
+		Hash = H256,
+		Error = transaction_pool::error::Error,
+	>,
+{
+	type Block = Block;
+	type Hash = H256;
+	type Error = transaction_pool::error::Error;
+
+	fn validate_transaction(
+		&self,
+		at: &BlockId<Self::Block>,
+		uxt: ExtrinsicFor<Self>,
+	) -> Result<TransactionValidity, Self::Error> {
+		let (hash, _) = self.inner.hash_and_length(&uxt);
+		if self.is_banned(&hash) {
+			debug!(target: "txpool", "Rejecting banned transaction {:?}", hash);
+			return Ok(TransactionValidity::Invalid(BANNED));
+		}
+
+		let sender = self.client.runtime_api().sender(at, uxt.clone())
+			.map_err(|e| format!("Failed to look up the sender of {:?}: {:?}", hash, e))?;
+		if let Some((who, _, _)) = sender {
+			// Signed transactions provide the tag `(sender, nonce)`.
+			if self.policy.sender_count(&who.encode(), &hash) >= self.policy.sender_limit {
+				debug!(target: "txpool", "Rejecting {:?}, too many transactions from {:?}", hash, who);
+				return Ok(TransactionValidity::Invalid(SENDER_LIMIT_REACHED));
+			}
+		}
+
+		let validity = self.inner.validate_transaction(at, uxt)?;
+		if let TransactionValidity::Invalid(code) = validity {
+			if is_permanent(code) {
+				self.ban(hash);
+			}
+		}
+		Ok(validity)
+	}
+
+	fn block_id_to_number(&self, at: &BlockId<Self::Block>) -> Result<Option<NumberFor<Self>>, Self::Error> {
+		self.inner.block_id_to_number(at)
+	}
+
+	fn block_id_to_hash(&self, at: &BlockId<Self::Block>) -> Result<Option<BlockHash<Self>>, Self::Error> {
+		self.inner.block_id_to_hash(at)
+	}
+
+	fn hash_and_length(&self, uxt: &ExtrinsicFor<Self>) -> (Self::Hash, usize) {
+		self.inner.hash_and_length(uxt)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use node_template_runtime::opaque::UncheckedExtrinsic;
+	use runtime_primitives::traits::{BlakeTwo256, Hash};
+
+	/// Accepts every transaction, with its first byte as priority and the second as sender.
+	struct TestApi;
+
+	impl txpool::ChainApi for TestApi {
+		type Block = Block;
+		type Hash = H256;
+		type Error = transaction_pool::error::Error;
+
+		fn validate_transaction(&self, _: &BlockId<Block>, uxt: ExtrinsicFor<Self>) -> Result<TransactionValidity, Self::Error> {
+			Ok(TransactionValidity::Valid {
+				priority: u64::from(uxt.0[0]),
+				requires: vec![],
+				provides: vec![(uxt.0[1], uxt.0[0]).encode()],
+				longevity: 64,
+			})
+		}
+
+		fn block_id_to_number(&self, at: &BlockId<Block>) -> Result<Option<NumberFor<Self>>, Self::Error> {
+			Ok(match at {
+				BlockId::Number(number) => Some(*number),
+				BlockId::Hash(_) => None,
+			})
+		}
+
+		fn block_id_to_hash(&self, at: &BlockId<Block>) -> Result<Option<BlockHash<Self>>, Self::Error> {
+			Ok(match at {
+				BlockId::Hash(hash) => Some(*hash),
+				BlockId::Number(_) => None,
+			})
+		}
+
+		fn hash_and_length(&self, uxt: &ExtrinsicFor<Self>) -> (Self::Hash, usize) {
+			let encoded = uxt.encode();
+			(BlakeTwo256::hash(&encoded), encoded.len())
+		}
+	}
+
+	fn limit(count: usize) -> txpool::Limit {
+		txpool::Limit { count, total_bytes: 1024 * 1024 }
+	}
+
+	#[test]
+	fn full_pool_evicts_lowest_priority_transactions() {
+		let pool = Pool::new(txpool::Options { ready: limit(2), future: limit(2) }, TestApi);
+		let at = BlockId::Number(0);
+		for priority in &[5u8, 1, 9] {
+			let _ = pool.submit_one(&at, UncheckedExtrinsic(vec![*priority, 0]));
+		}
+		assert_eq!(pool.ready().map(|tx| tx.priority).collect::<Vec<_>>(), vec![9, 5]);
+	}
+
+	#[test]
+	fn counts_pooled_transactions_per_sender() {
+		let pool = Arc::new(Pool::new(txpool::Options { ready: limit(16), future: limit(16) }, TestApi));
+		let policy = PoolPolicy::new(2, Duration::from_secs(1));
+		policy.watch(&pool);
+		let at = BlockId::Number(0);
+		let hashes = [vec![1u8, 7], vec![2, 7], vec![3, 8]].iter()
+			.map(|data| pool.submit_one(&at, UncheckedExtrinsic(data.clone())).unwrap())
+			.collect::<Vec<_>>();
+
+		assert_eq!(policy.sender_count(&7u8.encode(), &H256::zero()), 2);
+		assert_eq!(policy.sender_count(&7u8.encode(), &hashes[0]), 1);
+		assert_eq!(policy.sender_count(&8u8.encode(), &H256::zero()), 1);
+		assert_eq!(policy.sender_count(&9u8.encode(), &H256::zero()), 0);
+	}
+}
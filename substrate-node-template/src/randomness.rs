@@ -0,0 +1,231 @@
+//! VRF outputs of block authors, mixed into randomness by the `Randomness` runtime module.
+//!
+//! An authority started with `--vrf-key` registers the public key with an unsigned
+//! `Randomness::register_vrf_key`, signed by its authority key, and from then on includes the
+//! output of its VRF over the slot and the seed of the parent block in the blocks it authors,
+//! as the `set_vrf_output` inherent, which deposits it in the header as a `VrfOutput` log.
+//! Nodes, light clients included, check that log against the VRF key of the slot author before
+//! importing a block, and reject blocks of authors with a registered key that carry no output,
+//! so authors cannot pick their output. An authority whose VRF key is registered must
+//! therefore keep running with `--vrf-key`, or the blocks it authors are rejected.
+
+use std::sync::Arc;
+use futures::{Future, IntoFuture, Stream};
+use log::{debug, info, warn};
+use merlin::Transcript;
+use parity_codec::{Decode, Encode};
+use schnorrkel::{Keypair, PublicKey, vrf::{VRFOutput, VRFProof}};
+use aura::AuraInherentData;
+use consensus::CompatibleDigestItem;
+use consensus_authorities::AuthoritiesApi;
+use consensus_common::{BlockImport, Error as ConsensusError, ErrorKind as ConsensusErrorKind, ImportBlock, ImportResult};
+use inherents::{InherentData, InherentDataProviders, InherentIdentifier, ProvideInherentData};
+use primitives::{ed25519, sr25519, Pair};
+use primitives::crypto::Ss58Codec;
+use runtime_primitives::RuntimeString;
+use runtime_primitives::generic::BlockId;
+use runtime_primitives::traits::Header as HeaderT;
+use substrate_client::runtime_api::ProvideRuntimeApi;
+use substrate_service::TaskExecutor;
+use transaction_pool::txpool::{ChainApi, Pool};
+use node_template_runtime::{
+	AuthorityId, Call, Hash, Header as RuntimeHeader, RandomnessCall, UncheckedExtrinsic as RuntimeExtrinsic,
+	VrfInherentType, VrfKey, VRF_INHERENT_IDENTIFIER, api_ext::RandomnessApi, opaque::{Block, UncheckedExtrinsic},
+};
+use crate::explorer::FullClient;
+use crate::keystore::AuthorityKey;
+
+/// The public key of `keypair`, as registered in the runtime.
+pub fn vrf_key(keypair: &Keypair) -> VrfKey {
+	sr25519::Public(keypair.public.to_bytes())
+}
+
+/// The transcript the author of `slot` evaluates its VRF over.
+fn transcript(slot: u64, seed: &Hash) -> Transcript {
+	let mut transcript = Transcript::new(b"node-template randomness");
+	transcript.append_message(b"slot", &slot.encode());
+	transcript.append_message(b"seed", seed.as_ref());
+	transcript
+}
+
+/// The VRF output and proof of `keypair` for `slot`.
+fn evaluate(keypair: &Keypair, slot: u64, seed: &Hash) -> VrfInherentType {
+	let (inout, proof, _) = keypair.vrf_sign(transcript(slot, seed));
+	(inout.to_output().to_bytes(), proof.to_bytes().to_vec())
+}
+
+/// Check that `output` is the VRF output of `key` for `slot`.
+fn verify(key: &VrfKey, slot: u64, seed: &Hash, output: &[u8; 32], proof: &[u8]) -> Result<(), String> {
+	let public = PublicKey::from_bytes(key.as_ref()).map_err(|e| format!("Invalid VRF key: {:?}", e))?;
+	let output = VRFOutput::from_bytes(&output[..]).map_err(|e| format!("Invalid VRF output: {:?}", e))?;
+	let proof = VRFProof::from_bytes(proof).map_err(|e| format!("Invalid VRF proof: {:?}", e))?;
+	public.vrf_verify(transcript(slot, seed), &output, &proof)
+		.map(|_| ())
+		.map_err(|e| format!("Invalid VRF proof: {:?}", e))
+}
+
+/// Provides the VRF output of the author to the `Randomness` module, once its VRF key is
+/// registered for the current authority key.
+pub struct VrfInherentDataProvider {
+	client: Arc<FullClient>,
+	authority_key: Arc<AuthorityKey>,
+	vrf_key: Arc<Keypair>,
+}
+
+impl ProvideInherentData for VrfInherentDataProvider {
+	fn inherent_identifier(&self) -> &'static InherentIdentifier {
+		&VRF_INHERENT_IDENTIFIER
+	}
+
+	fn provide_inherent_data(&self, inherent_data: &mut InherentData) -> Result<(), RuntimeString> {
+		// Blocks are authored on top of the best block, which the output is verified against.
+		let at = BlockId::Hash(self.client.info().map_err(|e| format!("{:?}", e))?.chain.best_hash);
+		let api = self.client.runtime_api();
+		let registered = api.vrf_key(&at, self.authority_key.public()).map_err(|e| format!("{:?}", e))?;
+		if registered != Some(vrf_key(&self.vrf_key)) {
+			return Ok(());
+		}
+		let slot = inherent_data.aura_inherent_data()?;
+		let seed = api.vrf_seed(&at).map_err(|e| format!("{:?}", e))?;
+		inherent_data.put_data(VRF_INHERENT_IDENTIFIER, &evaluate(&self.vrf_key, slot, &seed))
+	}
+
+	fn error_to_string(&self, error: &[u8]) -> Option<String> {
+		RuntimeString::decode(&mut &error[..]).map(Into::into)
+	}
+}
+
+/// Provide the VRF output of `vrf_key` in the blocks authored with `authority_key`. Must be
+/// registered after the slot provider, as the output is evaluated over the slot.
+pub fn register(
+	providers: &InherentDataProviders,
+	client: Arc<FullClient>,
+	authority_key: Arc<AuthorityKey>,
+	vrf_key: Arc<Keypair>,
+) -> Result<(), String> {
+	if providers.has_provider(&VRF_INHERENT_IDENTIFIER) {
+		return Ok(());
+	}
+	providers.register_provider(VrfInherentDataProvider { client, authority_key, vrf_key })
+		.map_err(|e| format!("{:?}", e))
+}
+
+/// Block import checking the VRF output of imported blocks before passing them on to `inner`.
+///
+/// Full nodes read VRF keys and seeds from their own state. Light clients fetch them from full
+/// nodes, so they rely on those nodes having rejected blocks with invalid outputs themselves.
+pub struct VrfVerifier<I, C> {
+	inner: Arc<I>,
+	client: Arc<C>,
+}
+
+impl<I, C> VrfVerifier<I, C> where
+	C: ProvideRuntimeApi,
+	C::Api: AuthoritiesApi<Block> + RandomnessApi<Block>,
+{
+	/// Wrap `inner`, reading VRF keys and seeds from the state of `client`.
+	pub fn new(inner: Arc<I>, client: Arc<C>) -> Self {
+		VrfVerifier { inner, client }
+	}
+
+	/// Check the VRF output in the header of `block` against the key of the slot author.
+	/// Blocks of authors with a registered key must carry an output, otherwise authors could
+	/// leave out outputs they do not like.
+	fn check(&self, block: &ImportBlock<Block>) -> Result<(), String> {
+		let output = RuntimeHeader::decode(&mut &block.header.encode()[..])
+			.and_then(|header| node_template_runtime::vrf_output(&header));
+		let slot = block.post_digests.iter()
+			.filter_map(|item| CompatibleDigestItem::<ed25519::Pair>::as_aura_seal(item))
+			.map(|(slot, _)| slot)
+			.next()
+			.ok_or_else(|| "Block without a seal".to_string())?;
+
+		let at = BlockId::Hash(block.header.parent_hash);
+		let api = self.client.runtime_api();
+		let authorities: Vec<AuthorityId> = api.authorities(&at).map_err(|e| format!("{:?}", e))?;
+		if authorities.is_empty() {
+			return Err("No authorities".into());
+		}
+		let author = authorities[(slot % authorities.len() as u64) as usize].clone();
+		let key = api.vrf_key(&at, author.clone()).map_err(|e| format!("{:?}", e))?;
+		let (key, (seed, output, proof)) = match (key, output) {
+			(Some(key), Some(output)) => (key, output),
+			(None, None) => return Ok(()),
+			(Some(_), None) => return Err(format!("Block of {} without the VRF output of its registered key", author)),
+			(None, Some(_)) => return Err(format!("VRF output of {}, which has no VRF key", author)),
+		};
+		if seed != api.vrf_seed(&at).map_err(|e| format!("{:?}", e))? {
+			return Err(format!("VRF output of {} over another seed than its parent's", author));
+		}
+		verify(&key, slot, &seed, &output, &proof)
+	}
+}
+
+impl<I, C> BlockImport<Block> for VrfVerifier<I, C> where
+	I: BlockImport<Block, Error = ConsensusError>,
+	C: ProvideRuntimeApi,
+	C::Api: AuthoritiesApi<Block> + RandomnessApi<Block>,
+{
+	type Error = ConsensusError;
+
+	fn check_block(&self, hash: Hash, parent_hash: Hash) -> Result<ImportResult, Self::Error> {
+		self.inner.check_block(hash, parent_hash)
+	}
+
+	fn import_block(
+		&self,
+		block: ImportBlock<Block>,
+		new_authorities: Option<Vec<AuthorityId>>,
+	) -> Result<ImportResult, Self::Error> {
+		if let Err(e) = self.check(&block) {
+			warn!("Rejecting block #{} ({}): {}", block.header.number, block.header.hash(), e);
+			return Err(ConsensusErrorKind::ClientImport(e).into());
+		}
+		self.inner.import_block(block, new_authorities)
+	}
+}
+
+/// Submit a registration of `vrf_key` to `pool` whenever a block is imported while it is not
+/// the VRF key of the current authority key, until `exit` fires. Rotating the authority key
+/// registers the VRF key again for the new one.
+pub fn start_registering<A, E>(
+	client: Arc<FullClient>,
+	pool: Arc<Pool<A>>,
+	authority_key: Arc<AuthorityKey>,
+	vrf_key: VrfKey,
+	exit: E,
+	executor: &TaskExecutor,
+) where
+	A: ChainApi<Block = Block> + 'static,
+	E: IntoFuture<Item=(), Error=()>,
+	E::Future: Send + 'static,
+{
+	let register = client.import_notification_stream().for_each(move |notification| {
+		let at = BlockId::Hash(notification.hash);
+		let authority = authority_key.public();
+		let registered = match client.runtime_api().vrf_key(&at, authority.clone()) {
+			Ok(registered) => registered,
+			Err(e) => {
+				warn!("Unable to read the VRF key of {}: {:?}", authority, e);
+				return Ok(());
+			},
+		};
+		if registered.as_ref() == Some(&vrf_key) {
+			return Ok(());
+		}
+
+		let signature = authority_key.sign(&(b"vrf_key", &vrf_key, registered).encode());
+		let call = Call::Randomness(RandomnessCall::register_vrf_key(authority, vrf_key.clone(), signature));
+		let xt = RuntimeExtrinsic::new_unsigned(call).encode();
+		let xt = UncheckedExtrinsic::decode(&mut &xt[..])
+			.expect("runtime extrinsics encode as opaque extrinsics; qed");
+		match pool.submit_one(&at, xt) {
+			Ok(_) => info!("Registering VRF key {}", vrf_key.to_ss58check()),
+			// Most likely still pending from a previous block.
+			Err(e) => debug!("VRF key registration not submitted: {:?}", e),
+		}
+		Ok(())
+	});
+
+	executor.spawn(exit.into_future().select(register).then(|_| Ok(())));
+}
@@ -0,0 +1,287 @@
+//! Re-execution of historical blocks.
+//!
+//! Blocks are loaded from the local database and executed with `Core_execute_block` on top of
+//! the state of their parent, either with the native runtime of this binary or with a supplied
+//! Wasm blob. The blob only replaces `:code` as seen by the executor, so the storage root the
+//! block is checked against is the one of the original state. Re-executing old blocks needs
+//! their parent state, so the database must have been created with `--pruning archive`.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+use log::{info, warn};
+use parity_codec::{Decode, Encode};
+use primitives::{Blake2Hasher, H256, storage::well_known_keys};
+use runtime_primitives::ApplyResult;
+use runtime_primitives::generic::BlockId;
+use runtime_primitives::traits::{Block as BlockT, Header as HeaderT};
+use state_machine::{Backend, ExecutionStrategy, InMemoryChangesTrieStorage, NeverOffchainExt, OverlayedChanges, TrieBackend};
+use structopt::StructOpt;
+use substrate_cli::{SharedParams, VersionInfo, create_config_with_db_path, error};
+use substrate_executor::NativeExecutor;
+use node_template_runtime::opaque::{Block, Header};
+use crate::service;
+
+/// The `replay-blocks` command.
+#[derive(Debug, StructOpt, Clone)]
+pub struct ReplayBlocksCmd {
+	/// First block to re-execute.
+	#[structopt(long = "from", value_name = "BLOCK")]
+	pub from: u64,
+
+	/// Last block to re-execute.
+	#[structopt(long = "to", value_name = "BLOCK")]
+	pub to: u64,
+
+	/// Execute with this Wasm runtime instead of the native runtime of this binary.
+	#[structopt(long = "wasm", value_name = "PATH", parse(from_os_str))]
+	pub wasm: Option<PathBuf>,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub shared_params: SharedParams,
+}
+
+/// Outcome of re-executing a single block.
+struct Replayed {
+	/// The error `Core_execute_block` failed with, if it did.
+	error: Option<String>,
+	millis: u128,
+}
+
+impl ReplayBlocksCmd {
+	/// Re-execute the requested blocks, reporting failures, storage root mismatches and timings.
+	pub fn run(self, version: &VersionInfo) -> error::Result<()> {
+		if self.from == 0 || self.from > self.to {
+			return Err("`--from` must be at least 1 and not greater than `--to`".into());
+		}
+
+		let config = create_config_with_db_path::<service::Factory, _>(
+			crate::cli::load_spec,
+			&self.shared_params,
+			version,
+		)?;
+		let client = substrate_service::new_client::<service::Factory>(&config)
+			.map_err(|e| format!("{:?}", e))?;
+		let executor = NativeExecutor::<service::Executor>::new(config.default_heap_pages);
+
+		let wasm = match self.wasm {
+			Some(ref path) => Some(fs::read(path).map_err(|e| format!("Unable to read {:?}: {}", path, e))?),
+			None => None,
+		};
+
+		let mut failures = 0;
+		for number in self.from..=self.to {
+			let block = client.block(&BlockId::Number(number))?
+				.ok_or_else(|| format!("Block #{} is not in the database", number))?
+				.block;
+			let state = client.state_at(&BlockId::Hash(*block.header().parent_hash()))?;
+
+			let replayed = match wasm {
+				Some(ref code) => replay(&CodeOverride { inner: &state, code }, &executor, true, &block),
+				None => replay(&state, &executor, false, &block),
+			};
+			match replayed.error {
+				None => info!("#{}: ok in {} ms", number, replayed.millis),
+				Some(error) => {
+					failures += 1;
+					warn!("#{}: failed in {} ms: {}", number, replayed.millis, error);
+					let rebuilt = match wasm {
+						Some(ref code) => rebuild(&CodeOverride { inner: &state, code }, &executor, true, &block),
+						None => rebuild(&state, &executor, false, &block),
+					};
+					match rebuilt {
+						Ok(ref header) if header.state_root() != block.header().state_root() => warn!(
+							"#{}: storage root mismatch, expected {} but computed {}",
+							number,
+							block.header().state_root(),
+							header.state_root(),
+						),
+						Ok(_) => {},
+						Err(e) => warn!("#{}: unable to rebuild the block: {}", number, e),
+					}
+				},
+			}
+		}
+
+		if failures > 0 {
+			return Err(format!("{} of {} blocks failed to execute", failures, self.to - self.from + 1).into());
+		}
+		Ok(())
+	}
+}
+
+/// Runtime calls made on top of a state without ever committing to it.
+///
+/// Changes of every call are kept in the same overlay, so consecutive calls build on each other
+/// like they do in the block builder.
+pub struct Execution<'a, S> {
+	state: &'a S,
+	executor: &'a NativeExecutor<service::Executor>,
+	overlay: OverlayedChanges,
+	strategy: ExecutionStrategy,
+}
+
+impl<'a, S: state_machine::Backend<Blake2Hasher>> Execution<'a, S> {
+	/// Execute with the runtime found in `state`, natively when the versions match.
+	pub fn new(state: &'a S, executor: &'a NativeExecutor<service::Executor>) -> Self {
+		Execution {
+			state,
+			executor,
+			overlay: OverlayedChanges::default(),
+			strategy: ExecutionStrategy::NativeWhenPossible,
+		}
+	}
+
+	/// Always execute with the Wasm runtime found in `state`.
+	pub fn always_wasm(mut self) -> Self {
+		self.strategy = ExecutionStrategy::AlwaysWasm;
+		self
+	}
+
+	/// Set `:code` to the Wasm runtime `code`, as `Consensus::set_code` does, and execute with it.
+	/// The change is part of the overlay, so it is part of the storage root of built blocks.
+	pub fn set_code(mut self, code: Vec<u8>) -> Self {
+		self.overlay.set_storage(well_known_keys::CODE.to_vec(), Some(code));
+		self.strategy = ExecutionStrategy::AlwaysWasm;
+		self
+	}
+
+	/// Call the runtime API `method` with SCALE encoded `data`.
+	pub fn call(&mut self, method: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+		state_machine::new(
+			self.state,
+			None::<&InMemoryChangesTrieStorage<Blake2Hasher>>,
+			NeverOffchainExt::new(),
+			&mut self.overlay,
+			self.executor,
+			method,
+			data,
+		)
+			.execute(self.strategy)
+			.map(|(result, _, _)| result)
+			.map_err(|e| format!("{} failed: {:?}", method, e))
+	}
+
+	/// Start building a block with `header`.
+	pub fn initialize_block(&mut self, header: &Header) -> Result<(), String> {
+		self.call("Core_initialize_block", &header.encode()).map(drop)
+	}
+
+	/// Apply `extrinsic` to the block being built, returning whether it was applied successfully.
+	pub fn apply_extrinsic<E: Encode>(&mut self, extrinsic: &E) -> Result<bool, String> {
+		let result = self.call("BlockBuilder_apply_extrinsic", &extrinsic.encode())?;
+		Ok(match ApplyResult::decode(&mut &result[..]) {
+			Some(Ok(_)) => true,
+			_ => false,
+		})
+	}
+
+	/// Finish the block being built, returning its header.
+	pub fn finalize_block(&mut self) -> Result<Header, String> {
+		let result = self.call("BlockBuilder_finalize_block", &[])?;
+		Header::decode(&mut &result[..]).ok_or_else(|| "Unable to decode header returned by finalize_block".into())
+	}
+
+	/// Build a block with `header` out of `extrinsics`, returning the resulting header and
+	/// the number of extrinsics that failed to apply.
+	pub fn execute_block<E: Encode>(&mut self, header: &Header, extrinsics: &[E]) -> Result<(Header, usize), String> {
+		self.initialize_block(header)?;
+		let mut failed = 0;
+		for extrinsic in extrinsics {
+			if !self.apply_extrinsic(extrinsic)? {
+				failed += 1;
+			}
+		}
+		Ok((self.finalize_block()?, failed))
+	}
+}
+
+/// Execute `block` on top of `state` with `Executive::execute_block`, which checks its
+/// extrinsics and storage roots.
+fn replay<S>(
+	state: &S,
+	executor: &NativeExecutor<service::Executor>,
+	wasm: bool,
+	block: &Block,
+) -> Replayed where
+	S: state_machine::Backend<Blake2Hasher>,
+{
+	let mut execution = Execution::new(state, executor);
+	if wasm {
+		execution = execution.always_wasm();
+	}
+
+	let started = Instant::now();
+	let error = execution.call("Core_execute_block", &block.encode()).err();
+	let millis = started.elapsed().as_millis();
+	Replayed { error, millis }
+}
+
+/// Build `block` again on top of `state` the way the block builder does, without checking it,
+/// returning the resulting header.
+fn rebuild<S>(
+	state: &S,
+	executor: &NativeExecutor<service::Executor>,
+	wasm: bool,
+	block: &Block,
+) -> Result<Header, String> where
+	S: state_machine::Backend<Blake2Hasher>,
+{
+	let mut execution = Execution::new(state, executor);
+	if wasm {
+		execution = execution.always_wasm();
+	}
+	execution.execute_block(block.header(), block.extrinsics()).map(|(header, _)| header)
+}
+
+/// `inner` with `:code` replaced by `code`, and every other key and the storage root unchanged.
+struct CodeOverride<'a, S> {
+	inner: &'a S,
+	code: &'a [u8],
+}
+
+impl<'a, S: Backend<Blake2Hasher>> Backend<Blake2Hasher> for CodeOverride<'a, S> {
+	type Error = S::Error;
+	type Transaction = S::Transaction;
+	type TrieBackendStorage = S::TrieBackendStorage;
+
+	fn storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		match key == well_known_keys::CODE {
+			true => Ok(Some(self.code.to_vec())),
+			false => self.inner.storage(key),
+		}
+	}
+
+	fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		self.inner.child_storage(storage_key, key)
+	}
+
+	fn for_keys_with_prefix<F: FnMut(&[u8])>(&self, prefix: &[u8], f: F) {
+		self.inner.for_keys_with_prefix(prefix, f)
+	}
+
+	fn for_keys_in_child_storage<F: FnMut(&[u8])>(&self, storage_key: &[u8], f: F) {
+		self.inner.for_keys_in_child_storage(storage_key, f)
+	}
+
+	fn storage_root<I>(&self, delta: I) -> (H256, Self::Transaction) where
+		I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>,
+	{
+		self.inner.storage_root(delta)
+	}
+
+	fn child_storage_root<I>(&self, storage_key: &[u8], delta: I) -> (Vec<u8>, bool, Self::Transaction) where
+		I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>,
+	{
+		self.inner.child_storage_root(storage_key, delta)
+	}
+
+	fn pairs(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
+		self.inner.pairs()
+	}
+
+	fn try_into_trie_backend(self) -> Option<TrieBackend<Self::TrieBackendStorage, Blake2Hasher>> {
+		None
+	}
+}
@@ -0,0 +1,79 @@
+//! `account_*` methods reading account state at the best block.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use jsonrpc_core::{Error, Params};
+use parity_codec::Encode;
+use primitives::crypto::Ss58Codec;
+use primitives::hexdisplay::HexDisplay;
+use serde_json::json;
+use substrate_client::runtime_api::ProvideRuntimeApi;
+use substrate_service::{Components, ComponentClient};
+use transaction_pool::txpool::{ChainApi, Pool};
+use node_template_runtime::{AccountId, Nonce, api_ext::AccountApi, opaque::Block};
+use crate::service;
+use super::{Handler, best_block, internal};
+
+/// Register the `account_*` methods on `io`:
+///
+/// - `account_nextIndex(address)` returns the nonce to sign the next transaction of an account
+///   with, skipping the nonces of its transactions that are ready in the pool.
+/// - `account_info(address)` returns the nonce, free and reserved balance and locks of an account.
+pub fn register<C, A>(io: &mut Handler, client: Arc<ComponentClient<C>>, pool: Arc<Pool<A>>) where
+	C: Components<Factory = service::Factory>,
+	ComponentClient<C>: ProvideRuntimeApi,
+	<ComponentClient<C> as ProvideRuntimeApi>::Api: AccountApi<Block>,
+	A: ChainApi + 'static,
+{
+	let next_index_client = client.clone();
+	io.add_method("account_nextIndex", move |params: Params| {
+		let (address,): (String,) = params.parse()?;
+		let who = parse_account(&address)?;
+		let at = best_block::<C>(&next_index_client)?;
+		let nonce = next_index_client.runtime_api().account_nonce(&at, who.clone()).map_err(internal)?;
+		Ok(json!(next_index(&pool, &who, nonce)))
+	});
+
+	io.add_method("account_info", move |params: Params| {
+		let (address,): (String,) = params.parse()?;
+		let who = parse_account(&address)?;
+		let at = best_block::<C>(&client)?;
+		let info = client.runtime_api().account_info(&at, who).map_err(internal)?;
+
+		let locks = info.locks.iter()
+			.map(|lock| json!({
+				"id": format!("0x{}", HexDisplay::from(&lock.id)),
+				"amount": lock.amount.to_string(),
+				"until": lock.until,
+				"reasons": format!("{:?}", lock.reasons),
+			}))
+			.collect::<Vec<_>>();
+		Ok(json!({
+			"nonce": info.nonce,
+			// Balances are serialized as strings as they do not fit in a JSON number.
+			"free": info.free.to_string(),
+			"reserved": info.reserved.to_string(),
+			"locks": locks,
+		}))
+	});
+}
+
+/// The first nonce after `nonce` not provided by a ready transaction of `who`.
+///
+/// Signed transactions provide the tag `(sender, nonce)`, so consecutive nonces are followed
+/// through the ready queue. Transactions in the future queue are ignored, as they wait for a
+/// nonce that is still missing.
+fn next_index<A: ChainApi>(pool: &Pool<A>, who: &AccountId, mut nonce: Nonce) -> Nonce {
+	let provided = pool.ready()
+		.flat_map(|tx| tx.provides.clone())
+		.collect::<HashSet<_>>();
+	while provided.contains(&(who, nonce).encode()) {
+		nonce += 1;
+	}
+	nonce
+}
+
+fn parse_account(address: &str) -> Result<AccountId, Error> {
+	AccountId::from_ss58check(address)
+		.map_err(|e| Error::invalid_params(format!("Invalid address `{}`: {:?}", address, e)))
+}
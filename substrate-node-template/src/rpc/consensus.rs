@@ -0,0 +1,28 @@
+//! `consensus_*` methods reading the authority set.
+
+use std::sync::Arc;
+use consensus_authorities::AuthoritiesApi;
+use jsonrpc_core::Params;
+use primitives::crypto::Ss58Codec;
+use serde_json::json;
+use substrate_client::runtime_api::ProvideRuntimeApi;
+use substrate_service::{Components, ComponentClient};
+use node_template_runtime::opaque::Block;
+use crate::service;
+use super::{Handler, best_block, internal};
+
+/// Register the `consensus_*` methods on `io`:
+///
+/// - `consensus_authorities()` returns the SS58 addresses of the authorities allowed to author
+///   the block after the best one.
+pub fn register<C>(io: &mut Handler, client: Arc<ComponentClient<C>>) where
+	C: Components<Factory = service::Factory>,
+	ComponentClient<C>: ProvideRuntimeApi,
+	<ComponentClient<C> as ProvideRuntimeApi>::Api: AuthoritiesApi<Block>,
+{
+	io.add_method("consensus_authorities", move |_: Params| {
+		let at = best_block::<C>(&client)?;
+		let authorities = client.runtime_api().authorities(&at).map_err(internal)?;
+		Ok(json!(authorities.iter().map(|authority| authority.to_ss58check()).collect::<Vec<_>>()))
+	});
+}
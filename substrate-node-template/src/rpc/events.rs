@@ -0,0 +1,97 @@
+//! `events_*` methods querying and following the events of the chain index.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use futures::{Future, Stream};
+use futures::sync::oneshot;
+use jsonrpc_core::{Error, Params, Value};
+use jsonrpc_pubsub::{Subscriber, SubscriptionId};
+use log::warn;
+use parking_lot::Mutex;
+use serde_json::json;
+use substrate_service::TaskExecutor;
+use crate::explorer::{EventFilter, Index};
+use super::{Handler, Metadata};
+
+/// Register the `events_*` methods on `io`:
+///
+/// - `events_query(filter, from, to)` returns the events of blocks `from..=to` selected by `filter`.
+/// - `events_subscribe(filter)` notifies the events selected by `filter` of every newly indexed block.
+pub fn register(io: &mut Handler, index: Arc<Index>, executor: TaskExecutor) {
+	let query_index = index.clone();
+	io.add_method("events_query", move |params: Params| {
+		let (filter, from, to): (EventFilter, u64, u64) = params.parse()?;
+		filter.validate().map_err(Error::invalid_params)?;
+		let events = query_index.query(&filter, from, to).map_err(Error::invalid_params)?;
+		Ok(Value::Array(events.into_iter()
+			.map(|(number, hash, event)| json!({ "block": number, "hash": hash, "event": event }))
+			.collect()))
+	});
+
+	let subscriptions = Arc::new(Mutex::new(HashMap::<u64, oneshot::Sender<()>>::new()));
+	let unsubscriptions = subscriptions.clone();
+	let next_id = AtomicU64::new(0);
+
+	io.add_subscription(
+		"events_subscription",
+		("events_subscribe", move |params: Params, _: Metadata, subscriber: Subscriber| {
+			let filter = match params.parse::<(EventFilter,)>() {
+				Ok((filter,)) => filter,
+				Err(e) => {
+					let _ = subscriber.reject(e);
+					return;
+				},
+			};
+			if let Err(e) = filter.validate() {
+				let _ = subscriber.reject(Error::invalid_params(e));
+				return;
+			}
+
+			let id = next_id.fetch_add(1, Ordering::Relaxed);
+			let sink = match subscriber.assign_id(SubscriptionId::Number(id)) {
+				Ok(sink) => sink,
+				Err(()) => return,
+			};
+			let (cancel_send, cancel) = oneshot::channel();
+			subscriptions.lock().insert(id, cancel_send);
+
+			let blocks = index.subscribe();
+			let index = index.clone();
+			let follow = blocks
+				.filter_map(move |(number, hash)| {
+					let events = match index.events(&hash) {
+						Ok(events) => events.unwrap_or_default(),
+						Err(e) => {
+							warn!("Unable to read the events of block {:?}: {}", hash, e);
+							return None;
+						},
+					};
+					let events = events.into_iter().filter(|event| filter.matches(event)).collect::<Vec<_>>();
+					if events.is_empty() {
+						return None;
+					}
+					Some(json!({ "block": number, "hash": hash, "events": events }))
+				})
+				.for_each(move |notification| {
+					sink.notify(Params::Array(vec![notification]))
+						.map(|_| ())
+						.map_err(|e| warn!("Unable to notify events subscriber: {:?}", e))
+				});
+
+			let subscriptions = subscriptions.clone();
+			executor.spawn(follow.select(cancel.then(|_| Ok(()))).then(move |_| {
+				subscriptions.lock().remove(&id);
+				Ok(())
+			}));
+		}),
+		("events_unsubscribe", move |id: SubscriptionId| {
+			let removed = match id {
+				// Dropping the cancel handle ends the subscription.
+				SubscriptionId::Number(id) => unsubscriptions.lock().remove(&id).is_some(),
+				SubscriptionId::String(_) => false,
+			};
+			Ok(Value::Bool(removed))
+		}),
+	);
+}
@@ -0,0 +1,86 @@
+//! `keystore_*` methods managing the encrypted keystore and the authority key.
+//!
+//! The methods taking passwords or changing keys are unsafe: they are only served with
+//! `--unsafe-ext-rpc`, and passwords travel in their parameters.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use consensus_authorities::AuthoritiesApi;
+use jsonrpc_core::{Error, Params};
+use log::info;
+use primitives::Pair;
+use primitives::crypto::Ss58Codec;
+use serde_json::{json, Value};
+use substrate_client::runtime_api::ProvideRuntimeApi;
+use substrate_service::{Components, ComponentClient};
+use node_template_runtime::opaque::Block;
+use crate::keystore::{AuthorityKey, KeyType, Keystore};
+use crate::service;
+use super::{best_block, internal};
+
+/// Register the `keystore_*` methods on `io`:
+///
+/// - `keystore_authorityKey()` returns the address of the key blocks are sealed with, or `null`
+///   if the node is not an authority.
+///
+/// With `unsafe_methods` only:
+///
+/// - `keystore_list()` returns the type and address of every key of the encrypted keystore.
+/// - `keystore_generate(type, password)` generates a key encrypted with `password` and returns
+///   its address.
+/// - `keystore_rotateAuthorityKey(address, password)` decrypts an ed25519 key of the keystore
+///   and seals blocks with it from the next slot on, returning the address of the previous key.
+///   The key must already be in the authority set at the best block.
+pub fn register<C>(
+	io: &mut super::Handler,
+	client: Arc<ComponentClient<C>>,
+	keystore_path: PathBuf,
+	authority_key: Option<Arc<AuthorityKey>>,
+	unsafe_methods: bool,
+) where
+	C: Components<Factory = service::Factory>,
+	ComponentClient<C>: ProvideRuntimeApi,
+	<ComponentClient<C> as ProvideRuntimeApi>::Api: AuthoritiesApi<Block>,
+{
+	let current = authority_key.clone();
+	io.add_method("keystore_authorityKey", move |_: Params| {
+		Ok(current.as_ref().map_or(Value::Null, |key| Value::String(key.public().to_ss58check())))
+	});
+
+	if !unsafe_methods {
+		return;
+	}
+
+	let open = move || Keystore::open(&keystore_path).map_err(Error::invalid_params);
+	let list_open = open.clone();
+	io.add_method("keystore_list", move |_: Params| {
+		let keys = list_open()?.list().map_err(Error::invalid_params)?;
+		Ok(Value::Array(keys.into_iter()
+			.map(|(key_type, public)| json!({ "type": key_type, "public": public }))
+			.collect()))
+	});
+
+	let generate_open = open.clone();
+	io.add_method("keystore_generate", move |params: Params| {
+		let (key_type, password): (String, String) = params.parse()?;
+		let key_type = key_type.parse::<KeyType>().map_err(Error::invalid_params)?;
+		let public = generate_open()?.generate(key_type, &password).map_err(Error::invalid_params)?;
+		Ok(Value::String(public))
+	});
+
+	io.add_method("keystore_rotateAuthorityKey", move |params: Params| {
+		let (public, password): (String, String) = params.parse()?;
+		let authority_key = authority_key.as_ref().ok_or_else(|| Error::invalid_params(
+			"The authority key can only be rotated on validators started with `--key` or `--authority-key`"
+		))?;
+		let pair = open()?.ed25519(&public, &password).map_err(Error::invalid_params)?;
+		let at = best_block::<C>(&client)?;
+		let authorities = client.runtime_api().authorities(&at).map_err(internal)?;
+		if !authorities.contains(&pair.public()) {
+			return Err(Error::invalid_params(format!("{} is not in the authority set", public)));
+		}
+		let previous = authority_key.rotate(pair).public().to_ss58check();
+		info!("Rotated the authority key from {} to {}", previous, public);
+		Ok(Value::String(previous))
+	});
+}
@@ -0,0 +1,150 @@
+//! Node specific JSON-RPC methods.
+//!
+//! The RPC servers of the service only serve the standard Substrate methods, so the methods
+//! of this node are served by separate servers, over HTTP with `--ext-rpc-port` and over
+//! WebSocket with `--ext-ws-port`. Subscriptions are only available over WebSocket.
+//!
+//! Both servers only answer requests addressed to the local interface and reject requests from
+//! web pages, which would otherwise reach them from the browser of the node operator. Methods
+//! managing keys are only served with `--unsafe-ext-rpc`.
+
+mod account;
+mod consensus;
+mod events;
+mod keystore;
+mod payment;
+mod pool;
+mod randomness;
+mod template;
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use jsonrpc_core::{Error, MetaIoHandler, Value};
+use jsonrpc_pubsub::{PubSubHandler, PubSubMetadata, Session};
+use consensus_authorities::AuthoritiesApi;
+use log::info;
+use runtime_primitives::generic::BlockId;
+use substrate_client::runtime_api::ProvideRuntimeApi;
+use substrate_service::{Components, ComponentClient, TaskExecutor};
+use node_template_runtime::{api_ext::{AccountApi, RandomnessApi, TemplateApi, TransactionPayment}, opaque::Block};
+use crate::{explorer, service};
+
+/// Per-connection metadata, holding the session of WebSocket connections.
+#[derive(Clone, Default)]
+pub struct Metadata {
+	session: Option<Arc<Session>>,
+}
+
+impl jsonrpc_core::Metadata for Metadata {}
+
+impl PubSubMetadata for Metadata {
+	fn session(&self) -> Option<Arc<Session>> {
+		self.session.clone()
+	}
+}
+
+/// The handler of all node specific methods.
+pub type Handler = PubSubHandler<Metadata>;
+
+/// Build the handler of all node specific methods. The `events_*` methods are only
+/// registered when the chain is indexed, and the unsafe `keystore_*` methods with
+/// `unsafe_methods`.
+pub fn handler<C>(
+	service: &substrate_service::Service<C>,
+	index: Option<Arc<explorer::Index>>,
+	unsafe_methods: bool,
+	executor: TaskExecutor,
+) -> Handler where
+	C: Components<Factory = service::Factory>,
+	C::TransactionPoolApi: 'static,
+	ComponentClient<C>: ProvideRuntimeApi,
+	<ComponentClient<C> as ProvideRuntimeApi>::Api: AccountApi<Block> + TransactionPayment<Block>
+		+ TemplateApi<Block> + AuthoritiesApi<Block> + RandomnessApi<Block>,
+{
+	let mut io = PubSubHandler::new(MetaIoHandler::default());
+	pool::register(&mut io, service.transaction_pool());
+	account::register::<C, _>(&mut io, service.client(), service.transaction_pool());
+	payment::register::<C>(&mut io, service.client());
+	template::register::<C>(&mut io, service.client());
+	randomness::register::<C>(&mut io, service.client());
+	consensus::register::<C>(&mut io, service.client());
+	keystore::register::<C>(
+		&mut io,
+		service.client(),
+		PathBuf::from(&service.config.keystore_path),
+		service.config.custom.authority_key.clone(),
+		unsafe_methods,
+	);
+	if let Some(index) = index {
+		events::register(&mut io, index, executor);
+	}
+	io
+}
+
+/// The best block of `client`, which runtime API calls are made at.
+fn best_block<C>(client: &ComponentClient<C>) -> Result<BlockId<Block>, Error> where
+	C: Components<Factory = service::Factory>,
+{
+	let info = client.info().map_err(internal)?;
+	Ok(BlockId::Hash(info.chain.best_hash))
+}
+
+/// An internal error carrying the debug representation of `e`.
+fn internal<E: std::fmt::Debug>(e: E) -> Error {
+	let mut error = Error::internal_error();
+	error.data = Some(Value::String(format!("{:?}", e)));
+	error
+}
+
+/// The `Host` headers of requests addressed to `port` of the local interface.
+fn local_hosts<H: From<String>>(port: u16) -> Vec<H> {
+	vec![format!("localhost:{}", port).into(), format!("127.0.0.1:{}", port).into()]
+}
+
+/// Running servers, which stop when dropped.
+pub struct Servers {
+	_http: Option<jsonrpc_http_server::Server>,
+	_ws: Option<jsonrpc_ws_server::Server>,
+}
+
+/// Serve the handlers built by `handler` on the given ports of the local interface.
+pub fn start_servers<F>(http_port: Option<u16>, ws_port: Option<u16>, handler: F) -> Result<Servers, String> where
+	F: Fn() -> Handler,
+{
+	let http = match http_port {
+		Some(port) => {
+			let addr = SocketAddr::from(([127, 0, 0, 1], port));
+			let server = jsonrpc_http_server::ServerBuilder::new(handler())
+				.threads(1)
+				.cors(jsonrpc_http_server::DomainsValidation::AllowOnly(Vec::new()))
+				.allowed_hosts(jsonrpc_http_server::DomainsValidation::AllowOnly(local_hosts(port)))
+				.start_http(&addr)
+				.map_err(|e| format!("Unable to bind extension RPC server to {}: {}", addr, e))?;
+			info!("Extension RPC served on http://{}", addr);
+			Some(server)
+		},
+		None => None,
+	};
+
+	let ws = match ws_port {
+		Some(port) => {
+			let addr = SocketAddr::from(([127, 0, 0, 1], port));
+			let server = jsonrpc_ws_server::ServerBuilder::with_meta_extractor(
+				handler(),
+				|context: &jsonrpc_ws_server::RequestContext| Metadata {
+					session: Some(Arc::new(Session::new(context.sender()))),
+				},
+			)
+				.allowed_origins(jsonrpc_ws_server::DomainsValidation::AllowOnly(Vec::new()))
+				.allowed_hosts(jsonrpc_ws_server::DomainsValidation::AllowOnly(local_hosts(port)))
+				.start(&addr)
+				.map_err(|e| format!("Unable to bind extension RPC server to {}: {}", addr, e))?;
+			info!("Extension RPC served on ws://{}", addr);
+			Some(server)
+		},
+		None => None,
+	};
+
+	Ok(Servers { _http: http, _ws: ws })
+}
@@ -0,0 +1,39 @@
+//! `payment_*` methods estimating the fees of transactions.
+
+use std::sync::Arc;
+use jsonrpc_core::{Error, Params};
+use parity_codec::Decode;
+use primitives::Bytes;
+use serde_json::json;
+use substrate_client::runtime_api::ProvideRuntimeApi;
+use substrate_service::{Components, ComponentClient};
+use node_template_runtime::{api_ext::TransactionPayment, opaque::{Block, UncheckedExtrinsic}};
+use crate::service;
+use super::{Handler, best_block, internal};
+
+/// Register the `payment_*` methods on `io`:
+///
+/// - `payment_queryInfo(extrinsic)` returns the weight, class, fee and tip of a hex encoded
+///   transaction at the best block, as they would be charged if it were applied.
+pub fn register<C>(io: &mut Handler, client: Arc<ComponentClient<C>>) where
+	C: Components<Factory = service::Factory>,
+	ComponentClient<C>: ProvideRuntimeApi,
+	<ComponentClient<C> as ProvideRuntimeApi>::Api: TransactionPayment<Block>,
+{
+	io.add_method("payment_queryInfo", move |params: Params| {
+		let (encoded,): (Bytes,) = params.parse()?;
+		let tx = UncheckedExtrinsic::decode(&mut &encoded.0[..])
+			.ok_or_else(|| Error::invalid_params("Unable to decode the extrinsic"))?;
+		let at = best_block::<C>(&client)?;
+		let fee = client.runtime_api().query_info(&at, tx).map_err(internal)?;
+
+		Ok(json!({
+			"weight": fee.weight,
+			"class": format!("{:?}", fee.class),
+			// Balances are serialized as strings as they do not fit in a JSON number.
+			"fee": fee.fee.to_string(),
+			"tip": fee.tip.to_string(),
+			"total": fee.fee.saturating_add(fee.tip).to_string(),
+		}))
+	});
+}
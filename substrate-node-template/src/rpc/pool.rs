@@ -0,0 +1,49 @@
+//! `pool_*` methods inspecting the transaction pool.
+
+use std::sync::Arc;
+use jsonrpc_core::{Params, Value};
+use parity_codec::Encode;
+use primitives::hexdisplay::HexDisplay;
+use serde_json::json;
+use transaction_pool::txpool::{ChainApi, Pool, TransactionFor};
+use super::Handler;
+
+/// Register the `pool_*` methods on `io`.
+pub fn register<A>(io: &mut Handler, pool: Arc<Pool<A>>) where
+	A: ChainApi + 'static,
+{
+	io.add_method("pool_content", move |_: Params| Ok(content(&pool)));
+}
+
+/// The transactions of the pool with their priority and validity tags. Ready transactions are
+/// listed in the order they would be included in a block, future ones wait for the tags in
+/// `requires` that no transaction of the pool provides yet.
+fn content<A: ChainApi>(pool: &Pool<A>) -> Value {
+	let ready = pool.ready()
+		.map(|tx| transaction(&tx))
+		.collect::<Vec<_>>();
+	let future = pool.futures().into_iter()
+		.map(|tx| transaction(&tx))
+		.collect::<Vec<_>>();
+
+	json!({
+		"ready": ready,
+		"future": future,
+	})
+}
+
+fn transaction<A: ChainApi>(tx: &TransactionFor<A>) -> Value {
+	json!({
+		"hash": tx.hash,
+		"extrinsic": hex(&tx.data.encode()),
+		"bytes": tx.bytes,
+		"priority": tx.priority,
+		"validTill": tx.valid_till,
+		"requires": tx.requires.iter().map(|tag| hex(tag)).collect::<Vec<_>>(),
+		"provides": tx.provides.iter().map(|tag| hex(tag)).collect::<Vec<_>>(),
+	})
+}
+
+fn hex(bytes: &[u8]) -> String {
+	format!("0x{}", HexDisplay::from(&bytes))
+}
@@ -0,0 +1,29 @@
+//! `randomness_*` methods reading the randomness of the `Randomness` runtime module.
+
+use std::sync::Arc;
+use jsonrpc_core::Params;
+use primitives::Bytes;
+use serde_json::json;
+use substrate_client::runtime_api::ProvideRuntimeApi;
+use substrate_service::{Components, ComponentClient};
+use node_template_runtime::{api_ext::RandomnessApi, opaque::Block};
+use crate::service;
+use super::{Handler, best_block, internal};
+
+/// Register the `randomness_*` methods on `io`:
+///
+/// - `randomness_random(subject)` returns the randomness for the hex encoded `subject` at the
+///   best block, and the block from which on it is safe to use: it is only unpredictable for
+///   what was committed to before that block.
+pub fn register<C>(io: &mut Handler, client: Arc<ComponentClient<C>>) where
+	C: Components<Factory = service::Factory>,
+	ComponentClient<C>: ProvideRuntimeApi,
+	<ComponentClient<C> as ProvideRuntimeApi>::Api: RandomnessApi<Block>,
+{
+	io.add_method("randomness_random", move |params: Params| {
+		let (subject,): (Bytes,) = params.parse()?;
+		let at = best_block::<C>(&client)?;
+		let (random, block) = client.runtime_api().random(&at, subject.0).map_err(internal)?;
+		Ok(json!({ "random": random, "safeFrom": block }))
+	});
+}
@@ -0,0 +1,26 @@
+//! `template_*` methods reading the state of the template module.
+
+use std::sync::Arc;
+use jsonrpc_core::Params;
+use serde_json::{json, Value};
+use substrate_client::runtime_api::ProvideRuntimeApi;
+use substrate_service::{Components, ComponentClient};
+use node_template_runtime::{api_ext::TemplateApi, opaque::Block};
+use crate::service;
+use super::{Handler, best_block, internal};
+
+/// Register the `template_*` methods on `io`:
+///
+/// - `template_something()` returns the value stored by `do_something` and the block it was
+///   stored at, or `null` if nothing was stored yet.
+pub fn register<C>(io: &mut Handler, client: Arc<ComponentClient<C>>) where
+	C: Components<Factory = service::Factory>,
+	ComponentClient<C>: ProvideRuntimeApi,
+	<ComponentClient<C> as ProvideRuntimeApi>::Api: TemplateApi<Block>,
+{
+	io.add_method("template_something", move |_: Params| {
+		let at = best_block::<C>(&client)?;
+		let something = client.runtime_api().something(&at).map_err(internal)?;
+		Ok(something.map_or(Value::Null, |(value, block)| json!({ "value": value, "block": block })))
+	});
+}
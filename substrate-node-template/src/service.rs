@@ -2,10 +2,12 @@
 
 #![warn(unused_extern_crates)]
 
-use std::sync::Arc;
-use log::info;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use futures::Future;
+use log::{info, warn};
 use transaction_pool::{self, txpool::{Pool as TransactionPool}};
-use node_template_runtime::{self, GenesisConfig, opaque::Block, RuntimeApi};
+use node_template_runtime::{self, GenesisConfig, Hash, opaque::Block, RuntimeApi};
 use substrate_service::{
 	FactoryFullConfiguration, LightComponents, FullComponents, FullBackend,
 	FullClient, LightClient, LightBackend, FullExecutor, LightExecutor,
@@ -13,12 +15,24 @@ use substrate_service::{
 };
 use basic_authorship::ProposerFactory;
 use consensus::{import_queue, start_aura, AuraImportQueue, SlotDuration, NothingExtra};
+use consensus_common::BlockOrigin;
+use consensus_common::import_queue::{ImportQueue, ImportQueueStatus, IncomingBlock, Link};
 use substrate_client as client;
 use primitives::{ed25519::Pair, Pair as PairT};
+use runtime_primitives::Justification;
+use runtime_primitives::traits::NumberFor;
 use inherents::InherentDataProviders;
 use network::construct_simple_protocol;
 use substrate_executor::native_executor_instance;
 use substrate_service::construct_service_factory;
+use crate::authorities::{self, AuthorityTracker};
+use crate::block_time;
+use crate::equivocation::{self, EquivocationDetector, Reports};
+use crate::keystore::AuthorityKey;
+use crate::metrics::{self, Metrics, MeteredEnvironment};
+use crate::permissions;
+use crate::pool::{self, LimitedChainApi, PoolPolicy};
+use crate::randomness::{self, VrfVerifier};
 
 pub use substrate_executor::NativeExecutor;
 // Our native executor instance.
@@ -32,6 +46,83 @@ native_executor_instance!(
 #[derive(Default)]
 pub struct NodeConfig {
 	inherent_data_providers: InherentDataProviders,
+	/// Metrics shared between the service and the Prometheus endpoint.
+	pub metrics: Arc<Metrics>,
+	/// Key to seal blocks with, which can be rotated through the RPC. Set from `--key` or
+	/// `--authority-key`, otherwise blocks are sealed with the key of the Substrate keystore.
+	pub authority_key: Option<Arc<AuthorityKey>>,
+	/// Equivocations found while importing blocks, reported to the runtime by full nodes.
+	pub equivocations: Arc<Reports>,
+	/// Slot duration Aura computes slots with, following changes made by the runtime.
+	pub slot_duration: Arc<AtomicU64>,
+	/// VRF key authored blocks contribute randomness with, set from `--vrf-key`.
+	pub vrf_key: Option<Arc<schnorrkel::Keypair>>,
+	/// Only connect to the nodes allowed by the runtime, set from `--peer-allow-list`.
+	pub peer_allow_list: bool,
+	/// The import queue of the service, set once it is built, which shutdown waits on.
+	pub import_queue: Arc<Mutex<Option<SharedImportQueue>>>,
+	/// Per-sender limit and ban time of the transaction pool of full nodes.
+	pub pool_policy: Arc<PoolPolicy>,
+}
+
+/// Aura import queue that can be closed to incoming blocks, so that shutdown can wait for the
+/// blocks already queued to be imported. Clones share the same queue.
+#[derive(Clone)]
+pub struct SharedImportQueue {
+	inner: Arc<AuraImportQueue<Block>>,
+	closed: Arc<AtomicBool>,
+}
+
+impl SharedImportQueue {
+	/// Wrap `inner`, making it available to shutdown through `slot`.
+	fn new(inner: AuraImportQueue<Block>, slot: &Mutex<Option<SharedImportQueue>>) -> Self {
+		let queue = SharedImportQueue { inner: Arc::new(inner), closed: Arc::new(AtomicBool::new(false)) };
+		*slot.lock().expect("the lock is never held across a panic; qed") = Some(queue.clone());
+		queue
+	}
+
+	/// Drop blocks coming from the network from now on.
+	pub fn close(&self) {
+		self.closed.store(true, Ordering::SeqCst);
+	}
+
+	/// Number of blocks queued or being imported.
+	pub fn importing(&self) -> usize {
+		self.inner.status().importing_count
+	}
+}
+
+impl ImportQueue<Block> for SharedImportQueue {
+	fn start<L: 'static + Link<Block>>(&self, link: L) -> Result<(), std::io::Error> {
+		self.inner.start(link)
+	}
+
+	fn stop(&self) {
+		self.inner.stop()
+	}
+
+	fn status(&self) -> ImportQueueStatus<Block> {
+		self.inner.status()
+	}
+
+	fn is_importing(&self, hash: &Hash) -> bool {
+		self.inner.is_importing(hash)
+	}
+
+	fn clear(&self) {
+		self.inner.clear()
+	}
+
+	fn import_blocks(&self, origin: BlockOrigin, blocks: Vec<IncomingBlock<Block>>) {
+		if self.closed.load(Ordering::SeqCst) {
+			return;
+		}
+		self.inner.import_blocks(origin, blocks)
+	}
+
+	fn import_justification(&self, hash: Hash, number: NumberFor<Block>, justification: Justification) -> bool {
+		self.inner.import_justification(hash, number, justification)
+	}
 }
 
 construct_simple_protocol! {
@@ -45,70 +136,154 @@ construct_service_factory! {
 		RuntimeApi = RuntimeApi,
 		NetworkProtocol = NodeProtocol { |config| Ok(NodeProtocol::new()) },
 		RuntimeDispatch = Executor,
-		FullTransactionPoolApi = transaction_pool::ChainApi<client::Client<FullBackend<Self>, FullExecutor<Self>, Block, RuntimeApi>, Block>
-			{ |config, client| Ok(TransactionPool::new(config, transaction_pool::ChainApi::new(client))) },
+		FullTransactionPoolApi = LimitedChainApi<client::Client<FullBackend<Self>, FullExecutor<Self>, Block, RuntimeApi>>
+			{ |config, client| Ok(TransactionPool::new(config, LimitedChainApi::new(client))) },
 		LightTransactionPoolApi = transaction_pool::ChainApi<client::Client<LightBackend<Self>, LightExecutor<Self>, Block, RuntimeApi>, Block>
 			{ |config, client| Ok(TransactionPool::new(config, transaction_pool::ChainApi::new(client))) },
 		Genesis = GenesisConfig,
 		Configuration = NodeConfig,
 		FullService = FullComponents<Self>
-			{ |config: FactoryFullConfiguration<Self>, executor: TaskExecutor|
-				FullComponents::<Factory>::new(config, executor)
-			},
+			{ |mut config: FactoryFullConfiguration<Self>, executor: TaskExecutor| {
+				let candidates = match config.custom.peer_allow_list {
+					true => Some(permissions::configure(&mut config.network)),
+					false => None,
+				};
+				let pool_policy = config.custom.pool_policy.clone();
+				pool::hand_over(pool_policy.clone());
+				let service = FullComponents::<Factory>::new(config, executor.clone())?;
+				pool_policy.watch(&service.transaction_pool());
+				if let Some(candidates) = candidates {
+					permissions::follow(service.client(), service.network(), candidates, service.on_exit(), &executor);
+				}
+				Ok(service)
+			}},
 		AuthoritySetup = {
 			|service: Self::FullService, executor: TaskExecutor, key: Option<Arc<Pair>>| {
+				equivocation::start_reporting(
+					service.client(),
+					service.transaction_pool(),
+					service.config.custom.equivocations.clone(),
+					service.on_exit(),
+					&executor,
+				);
+				let slot_duration = service.config.custom.slot_duration.clone();
 				if let Some(key) = key {
+					let key = match service.config.custom.authority_key.clone() {
+						Some(authority_key) => authority_key,
+						None => Arc::new(AuthorityKey::shared(key)),
+					};
 					info!("Using authority key {}", key.public());
-					let proposer = Arc::new(ProposerFactory {
-						client: service.client(),
-						transaction_pool: service.transaction_pool(),
-						inherents_pool: service.inherents_pool(),
-					});
-					let client = service.client();
-					executor.spawn(start_aura(
-						SlotDuration::get_or_compute(&*client)?,
+					metrics::start_counting_missed_slots(
+						service.client(),
 						key.clone(),
-						client.clone(),
-						client,
-						proposer,
-						service.network(),
+						service.config.custom.metrics.clone(),
 						service.on_exit(),
-						service.config.custom.inherent_data_providers.clone(),
-						service.config.force_authoring,
-					)?);
+						&executor,
+					);
+					if let Some(vrf_key) = service.config.custom.vrf_key.clone() {
+						randomness::start_registering(
+							service.client(),
+							service.transaction_pool(),
+							key.clone(),
+							randomness::vrf_key(&vrf_key),
+							service.on_exit(),
+							&executor,
+						);
+						randomness::register(&service.config.custom.inherent_data_providers, service.client(), key.clone(), vrf_key)?;
+					}
+					let proposer = Arc::new(MeteredEnvironment::new(
+						ProposerFactory {
+							client: service.client(),
+							transaction_pool: service.transaction_pool(),
+							inherents_pool: service.inherents_pool(),
+						},
+						service.config.custom.metrics.clone(),
+					));
+					let client = service.client();
+					let network = service.network();
+					let service_exit = service.on_exit();
+					let inherent_data_providers = service.config.custom.inherent_data_providers.clone();
+					let force_authoring = service.config.force_authoring;
+					let authoring_executor = executor.clone();
+					// Starts a slot worker with the current slot duration, stopped by the returned signal.
+					let start = move || -> Result<exit_future::Signal, String> {
+						let (signal, exit) = exit_future::signal();
+						authoring_executor.spawn(start_aura(
+							SlotDuration::get_or_compute(&*client).map_err(|e| format!("{:?}", e))?,
+							key.clone(),
+							client.clone(),
+							client.clone(),
+							proposer.clone(),
+							network.clone(),
+							service_exit.clone().select(exit).then(|_| Ok(())),
+							inherent_data_providers.clone(),
+							force_authoring,
+						).map_err(|e| format!("{:?}", e))?);
+						Ok(signal)
+					};
+
+					let mut worker = Some(start()?);
+					block_time::follow(service.client(), slot_duration, service.on_exit(), &executor, move |_| {
+						if let Some(signal) = worker.take() {
+							signal.fire();
+						}
+						match start() {
+							Ok(signal) => worker = Some(signal),
+							Err(e) => warn!("Unable to restart block authoring: {}", e),
+						}
+					});
+				} else {
+					block_time::follow(service.client(), slot_duration, service.on_exit(), &executor, |_| {});
 				}
 
 				Ok(service)
 			}
 		},
 		LightService = LightComponents<Self>
-			{ |config, executor| <LightComponents<Factory>>::new(config, executor) },
-		FullImportQueue = AuraImportQueue<
-			Self::Block,
-		>
+			{ |config: FactoryFullConfiguration<Self>, executor: TaskExecutor| {
+				let slot_duration = config.custom.slot_duration.clone();
+				let service = <LightComponents<Factory>>::new(config, executor.clone())?;
+				block_time::follow(service.client(), slot_duration, service.on_exit(), &executor, |_| {});
+				Ok(service)
+			}},
+		FullImportQueue = SharedImportQueue
 			{ |config: &mut FactoryFullConfiguration<Self> , client: Arc<FullClient<Self>>| {
+					let block_import = EquivocationDetector::new(
+						Arc::new(VrfVerifier::new(client.clone(), client.clone())),
+						config.custom.equivocations.clone(),
+					);
+					let slot_duration = SlotDuration::get_or_compute(&*client)?;
+					block_time::register(&config.custom.inherent_data_providers, &config.custom.slot_duration, slot_duration.get())?;
 					import_queue::<_, _, _, Pair>(
-						SlotDuration::get_or_compute(&*client)?,
-						client.clone(),
+						slot_duration,
+						Arc::new(block_import),
 						None,
 						client,
 						NothingExtra,
 						config.custom.inherent_data_providers.clone(),
-					).map_err(Into::into)
+					).map(|queue| SharedImportQueue::new(queue, &config.custom.import_queue)).map_err(Into::into)
 				}
 			},
-		LightImportQueue = AuraImportQueue<
-			Self::Block,
-		>
+		LightImportQueue = SharedImportQueue
 			{ |config: &mut FactoryFullConfiguration<Self>, client: Arc<LightClient<Self>>| {
+					let genesis_hash = client.info()?.chain.genesis_hash;
+					let genesis_authorities = authorities::genesis_authorities(&config.chain_spec.build_storage()?.0)?;
+					let block_import = AuthorityTracker::new(
+							Arc::new(VrfVerifier::new(client.clone(), client.clone())),
+							client.clone(),
+							genesis_hash,
+							genesis_authorities,
+						)?;
+					let slot_duration = SlotDuration::get_or_compute(&*client)?;
+					block_time::register(&config.custom.inherent_data_providers, &config.custom.slot_duration, slot_duration.get())?;
 					import_queue::<_, _, _, Pair>(
-						SlotDuration::get_or_compute(&*client)?,
-						client.clone(),
+						slot_duration,
+						Arc::new(block_import),
 						None,
 						client,
 						NothingExtra,
 						config.custom.inherent_data_providers.clone(),
-					).map_err(Into::into)
+					).map(|queue| SharedImportQueue::new(queue, &config.custom.import_queue)).map_err(Into::into)
 				}
 			},
 	}
@@ -0,0 +1,147 @@
+//! Export and import of chain state snapshots.
+//!
+//! A snapshot holds every storage key/value pair of the full client backend at one block.
+//! It can be turned back into a raw chain spec whose genesis storage is that state, which
+//! is handy to fork a running dev chain for debugging.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use log::info;
+use primitives::storage::{StorageKey, StorageData};
+use runtime_primitives::generic::BlockId;
+use runtime_primitives::traits::{As, Header as HeaderT};
+use serde_derive::{Serialize, Deserialize};
+use structopt::StructOpt;
+use substrate_cli::{SharedParams, VersionInfo, create_config_with_db_path, error};
+use node_template_runtime::{Hash, opaque::Block};
+use crate::{chain_spec, service};
+
+/// All storage of a chain at a single block.
+#[derive(Serialize, Deserialize)]
+pub struct StateSnapshot {
+	/// Name of the chain the snapshot was taken from.
+	pub chain: String,
+	/// Number of the block the snapshot was taken at.
+	pub block: u64,
+	/// Hash of the block the snapshot was taken at.
+	pub hash: Hash,
+	/// Storage key/value pairs.
+	pub storage: HashMap<StorageKey, StorageData>,
+}
+
+impl StateSnapshot {
+	/// Read a snapshot written by `export-state`.
+	pub fn read(path: &Path) -> Result<Self, String> {
+		let mut bytes = Vec::new();
+		File::open(path)
+			.and_then(|mut file| file.read_to_end(&mut bytes))
+			.map_err(|e| format!("Unable to read snapshot {:?}: {}", path, e))?;
+		serde_json::from_slice(&bytes).map_err(|e| format!("Invalid snapshot {:?}: {}", path, e))
+	}
+}
+
+/// The `export-state` command.
+#[derive(Debug, StructOpt, Clone)]
+pub struct ExportStateCmd {
+	/// Block number or `0x`-prefixed hash to export the state of. Defaults to the best block.
+	#[structopt(long = "at", value_name = "BLOCK")]
+	pub at: Option<String>,
+
+	/// File to write the snapshot to. Defaults to stdout.
+	#[structopt(long = "output", short = "o", value_name = "PATH", parse(from_os_str))]
+	pub output: Option<PathBuf>,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub shared_params: SharedParams,
+}
+
+impl ExportStateCmd {
+	/// Dump the state at the requested block.
+	pub fn run(self, version: &VersionInfo) -> error::Result<()> {
+		let config = create_config_with_db_path::<service::Factory, _>(
+			crate::cli::load_spec,
+			&self.shared_params,
+			version,
+		)?;
+		let client = substrate_service::new_client::<service::Factory>(&config)
+			.map_err(|e| format!("{:?}", e))?;
+
+		let at = match self.at {
+			Some(ref at) => parse_block_id(at)?,
+			None => BlockId::Hash(client.info()?.chain.best_hash),
+		};
+		let header = client.header(&at)?.ok_or_else(|| format!("Unknown block {:?}", at))?;
+		info!("Exporting state at #{} ({})", header.number(), header.hash());
+
+		let mut storage = HashMap::new();
+		for key in client.storage_keys(&at, &StorageKey(Vec::new()))? {
+			if let Some(value) = client.storage(&at, &key)? {
+				storage.insert(key, value);
+			}
+		}
+		info!("Exported {} storage entries", storage.len());
+
+		let snapshot = StateSnapshot {
+			chain: config.chain_spec.name().to_string(),
+			block: header.number().as_(),
+			hash: header.hash(),
+			storage,
+		};
+		let output: Box<dyn Write> = match self.output {
+			Some(path) => Box::new(File::create(path)?),
+			None => Box::new(io::stdout()),
+		};
+		serde_json::to_writer_pretty(output, &snapshot).map_err(|e| format!("{}", e))?;
+		Ok(())
+	}
+}
+
+/// The `import-state` command.
+#[derive(Debug, StructOpt, Clone)]
+pub struct ImportStateCmd {
+	/// Snapshot written by `export-state`.
+	#[structopt(value_name = "SNAPSHOT", parse(from_os_str))]
+	pub snapshot: PathBuf,
+
+	/// Replace the consensus authorities and the sudo key with Alice's development keys.
+	#[structopt(long = "dev-keys")]
+	pub dev_keys: bool,
+
+	/// File to write the raw chain spec to. Defaults to stdout.
+	#[structopt(long = "output", short = "o", value_name = "PATH", parse(from_os_str))]
+	pub output: Option<PathBuf>,
+
+	/// Sets a custom logging filter.
+	#[structopt(short = "l", long = "log", value_name = "LOG_PATTERN")]
+	pub log: Option<String>,
+}
+
+impl ImportStateCmd {
+	/// Write a raw chain spec booting a new chain from the snapshot.
+	pub fn run(self) -> error::Result<()> {
+		let snapshot = StateSnapshot::read(&self.snapshot)?;
+		let spec = chain_spec::from_snapshot(&snapshot, self.dev_keys)?;
+		let json = spec.to_json(true)?;
+
+		match self.output {
+			Some(path) => File::create(path)?.write_all(json.as_bytes())?,
+			None => println!("{}", json),
+		}
+		Ok(())
+	}
+}
+
+pub(crate) fn parse_block_id(s: &str) -> Result<BlockId<Block>, String> {
+	if s.starts_with("0x") {
+		s[2..].parse::<Hash>()
+			.map(BlockId::Hash)
+			.map_err(|e| format!("Invalid block hash `{}`: {:?}", s, e))
+	} else {
+		s.parse::<u64>()
+			.map(BlockId::Number)
+			.map_err(|e| format!("Invalid block number `{}`: {}", s, e))
+	}
+}
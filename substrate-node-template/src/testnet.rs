@@ -0,0 +1,269 @@
+//! A local testnet of several validator nodes, launched by the `testnet` command.
+//!
+//! Validators are named after the development accounts (Alice, Bob, ...) and derive their keys
+//! from those names, so `--key //<name>` is their authority key. Node keys are derived from the
+//! index of the validator, which makes the peer ids and thus the boot nodes of the chain spec
+//! stable across runs.
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use log::info;
+use network::identity::{Keypair, secp256k1};
+use primitives::blake2_256;
+use primitives::crypto::Ss58Codec;
+use primitives::hexdisplay::HexDisplay;
+use serde_json::json;
+use structopt::StructOpt;
+use substrate_cli::error;
+use node_template_signer::Client;
+use crate::chain_spec;
+
+const NAMES: &[&str] = &["Alice", "Bob", "Charlie", "Dave", "Eve", "Ferdie"];
+
+/// The `testnet` command.
+#[derive(Debug, StructOpt, Clone)]
+pub struct TestnetCmd {
+	/// Number of validator nodes to run.
+	#[structopt(long = "validators", value_name = "COUNT", default_value = "2")]
+	pub validators: usize,
+
+	/// Directory to write the chain spec, keys, logs and databases of the nodes into.
+	#[structopt(long = "out", value_name = "PATH", parse(from_os_str))]
+	pub out: PathBuf,
+
+	/// P2P port of the first node. Node `i` uses this port plus `i`, and so on for RPC ports.
+	#[structopt(long = "port", value_name = "PORT", default_value = "30333")]
+	pub port: u16,
+
+	/// HTTP RPC port of the first node.
+	#[structopt(long = "rpc-port", value_name = "PORT", default_value = "9933")]
+	pub rpc_port: u16,
+
+	/// WebSocket RPC port of the first node.
+	#[structopt(long = "ws-port", value_name = "PORT", default_value = "9944")]
+	pub ws_port: u16,
+
+	/// Prometheus port of the first node.
+	#[structopt(long = "prometheus-port", value_name = "PORT", default_value = "9615")]
+	pub prometheus_port: u16,
+
+	/// Seconds to wait for every node to author blocks and import those of the others.
+	#[structopt(long = "timeout", value_name = "SECS", default_value = "180")]
+	pub timeout: u64,
+
+	/// Only write the chain spec and keys, without starting the nodes.
+	#[structopt(long = "no-start")]
+	pub no_start: bool,
+
+	/// Sets a custom logging filter.
+	#[structopt(short = "l", long = "log", value_name = "LOG_PATTERN")]
+	pub log: Option<String>,
+}
+
+/// A validator of the testnet.
+struct Validator {
+	name: String,
+	base_path: PathBuf,
+	node_key: [u8; 32],
+	port: u16,
+	rpc_port: u16,
+	ws_port: u16,
+	prometheus_port: u16,
+}
+
+/// A running node, killed when dropped.
+struct Node {
+	name: String,
+	process: Child,
+}
+
+impl Drop for Node {
+	fn drop(&mut self) {
+		let _ = self.process.kill();
+		let _ = self.process.wait();
+	}
+}
+
+impl TestnetCmd {
+	/// Write the chain spec, then run the nodes until one of them stops.
+	pub fn run(self) -> error::Result<()> {
+		if self.validators == 0 {
+			return Err("The testnet needs at least one validator".into());
+		}
+		fs::create_dir_all(&self.out)?;
+
+		let validators = (0..self.validators).map(|i| self.validator(i)).collect::<error::Result<Vec<_>>>()?;
+		let mut boot_nodes = Vec::new();
+		for validator in &validators {
+			let secret = secp256k1::SecretKey::from_bytes(validator.node_key)
+				.map_err(|e| format!("Invalid node key: {:?}", e))?;
+			let peer_id = Keypair::Secp256k1(secret.into()).public().into_peer_id();
+			boot_nodes.push(format!("/ip4/127.0.0.1/tcp/{}/p2p/{}", validator.port, peer_id.to_base58()));
+		}
+
+		let names = validators.iter().map(|validator| validator.name.clone()).collect::<Vec<_>>();
+		let spec = chain_spec::testnet(&names, boot_nodes.clone())?;
+		let spec_path = self.out.join("chain-spec.json");
+		fs::write(&spec_path, spec.to_json(true)?)?;
+
+		let keys = validators.iter().zip(&boot_nodes)
+			.map(|(validator, boot_node)| json!({
+				"name": validator.name,
+				"seed": format!("//{}", validator.name),
+				"authority": chain_spec::authority_key(&validator.name).to_ss58check(),
+				"account": chain_spec::account_key(&validator.name).to_ss58check(),
+				"nodeKey": format!("0x{}", HexDisplay::from(&validator.node_key)),
+				"bootNode": boot_node,
+				"rpcPort": validator.rpc_port,
+				"wsPort": validator.ws_port,
+			}))
+			.collect::<Vec<_>>();
+		fs::write(self.out.join("keys.json"), serde_json::to_string_pretty(&keys).map_err(|e| format!("{}", e))?)?;
+		info!("Wrote the chain spec and keys of {} validators to {:?}", validators.len(), self.out);
+
+		if self.no_start {
+			return Ok(());
+		}
+
+		let binary = std::env::current_exe()?;
+		let mut nodes = Vec::new();
+		for validator in &validators {
+			let log = File::create(self.out.join(format!("{}.log", validator.name.to_lowercase())))?;
+			let process = Command::new(&binary)
+				.arg("--chain").arg(&spec_path)
+				.arg("--base-path").arg(&validator.base_path)
+				.arg("--name").arg(&validator.name)
+				.arg("--key").arg(format!("//{}", validator.name))
+				.arg("--node-key").arg(format!("{}", HexDisplay::from(&validator.node_key)))
+				.arg("--port").arg(validator.port.to_string())
+				.arg("--rpc-port").arg(validator.rpc_port.to_string())
+				.arg("--ws-port").arg(validator.ws_port.to_string())
+				.arg("--prometheus-port").arg(validator.prometheus_port.to_string())
+				.arg("--validator")
+				.stdout(Stdio::null())
+				.stderr(log)
+				.spawn()?;
+			info!("Started {} (pid {}), logging to {}.log", validator.name, process.id(), validator.name.to_lowercase());
+			nodes.push(Node { name: validator.name.clone(), process });
+		}
+
+		self.wait_until_ready(&validators, &mut nodes)?;
+		info!("All {} validators are authoring and importing blocks, press Ctrl-C to stop", validators.len());
+
+		loop {
+			for node in &mut nodes {
+				if let Some(status) = node.process.try_wait()? {
+					return Err(format!("{} stopped: {}", node.name, status).into());
+				}
+			}
+			thread::sleep(Duration::from_secs(1));
+		}
+	}
+
+	fn validator(&self, i: usize) -> error::Result<Validator> {
+		let name = NAMES.get(i).map(|name| name.to_string()).unwrap_or_else(|| format!("Validator{}", i + 1));
+		Ok(Validator {
+			base_path: self.out.join(name.to_lowercase()),
+			node_key: blake2_256(format!("node-template testnet node {}", i).as_bytes()),
+			port: node_port(self.port, i, "--port")?,
+			rpc_port: node_port(self.rpc_port, i, "--rpc-port")?,
+			ws_port: node_port(self.ws_port, i, "--ws-port")?,
+			prometheus_port: node_port(self.prometheus_port, i, "--prometheus-port")?,
+			name,
+		})
+	}
+
+	/// Wait until every node has authored a block, and all of them agree on the chain past
+	/// every validator's first slot.
+	fn wait_until_ready(&self, validators: &[Validator], nodes: &mut [Node]) -> error::Result<()> {
+		let deadline = Instant::now() + Duration::from_secs(self.timeout);
+		let target = 2 * validators.len() as u64 + 1;
+		let mut clients = Vec::new();
+		for validator in validators {
+			clients.push(loop {
+				if let Ok(client) = Client::connect(&format!("ws://127.0.0.1:{}", validator.ws_port)) {
+					break client;
+				}
+				self.check(nodes, deadline)?;
+				thread::sleep(Duration::from_millis(500));
+			});
+		}
+
+		loop {
+			let numbers = clients.iter()
+				.map(|client| client.best_header().map(|header| header.number).unwrap_or(0))
+				.collect::<Vec<_>>();
+			let authored = validators.iter()
+				.map(|validator| slots_authored(validator.prometheus_port).unwrap_or(0))
+				.collect::<Vec<_>>();
+			info!("Best blocks: {}", validators.iter().zip(numbers.iter().zip(&authored))
+				.map(|(validator, (number, authored))| format!("{} #{} ({} authored)", validator.name, number, authored))
+				.collect::<Vec<_>>()
+				.join(", "));
+
+			if numbers.iter().all(|number| *number >= target) && authored.iter().all(|authored| *authored > 0) {
+				let hashes = clients.iter()
+					.map(|client| client.block_hash(target).map_err(|e| format!("{}", e)))
+					.collect::<Result<Vec<_>, _>>()?;
+				if hashes.iter().all(|hash| *hash == hashes[0]) {
+					return Ok(());
+				}
+			}
+			self.check(nodes, deadline)?;
+			thread::sleep(Duration::from_secs(2));
+		}
+	}
+
+	/// Fail if a node stopped or the deadline passed.
+	fn check(&self, nodes: &mut [Node], deadline: Instant) -> error::Result<()> {
+		for node in nodes {
+			if let Some(status) = node.process.try_wait()? {
+				return Err(format!("{} stopped: {}", node.name, status).into());
+			}
+		}
+		if Instant::now() > deadline {
+			return Err(format!("The testnet did not start within {} seconds", self.timeout).into());
+		}
+		Ok(())
+	}
+}
+
+/// The port of validator `i`, that of the first one being `first`.
+fn node_port(first: u16, i: usize, option: &str) -> error::Result<u16> {
+	let offset = match i <= u16::max_value() as usize {
+		true => Some(i as u16),
+		false => None,
+	};
+	offset.and_then(|offset| first.checked_add(offset))
+		.ok_or_else(|| format!("{} {} leaves no port for validator {}", option, first, i + 1).into())
+}
+
+/// The number of slots a node authored a block for, read from its Prometheus endpoint.
+fn slots_authored(port: u16) -> Option<u64> {
+	let mut stream = TcpStream::connect(("127.0.0.1", port)).ok()?;
+	stream.write_all(b"GET /metrics HTTP/1.0\r\n\r\n").ok()?;
+	let mut response = String::new();
+	stream.read_to_string(&mut response).ok()?;
+	response.lines()
+		.find(|line| line.starts_with("node_template_aura_slots_authored_total "))
+		.and_then(|line| line.split_whitespace().nth(1))
+		.and_then(|value| value.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn rejects_ports_past_the_last_one() {
+		assert_eq!(node_port(30333, 2, "--port").unwrap(), 30335);
+		assert_eq!(node_port(65534, 1, "--port").unwrap(), 65535);
+		assert!(node_port(65534, 2, "--port").is_err());
+		assert!(node_port(0, 70_000, "--port").is_err());
+	}
+}
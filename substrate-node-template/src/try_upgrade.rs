@@ -0,0 +1,239 @@
+//! Dry-run of a runtime upgrade against the current chain state.
+//!
+//! The new Wasm runtime is set as `:code` on a copy of the state of the best block, which is
+//! never written back, as `Consensus::set_code` would, and blocks are built on top of it. The
+//! first one runs the `on_initialize` hooks of every module, and with them any pending storage
+//! migrations, against the state the upgrade would actually meet.
+//!
+//! The blocks then apply the transactions the chain would see next: the pending transactions
+//! given with `--extrinsics`, and the signed transactions of the most recent blocks again. Those
+//! already used their nonces, so the ones of development accounts are signed again with their
+//! current nonce, and the others are skipped.
+
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use log::{info, warn};
+use parity_codec::{Decode, Encode};
+use consensus::SlotDuration;
+use inherents::InherentData;
+use primitives::{Blake2Hasher, Bytes, sr25519, Pair};
+use runtime_primitives::generic::{BlockId, Era};
+use runtime_primitives::traits::{As, Block as BlockT, Header as HeaderT};
+use structopt::StructOpt;
+use substrate_cli::{SharedParams, VersionInfo, create_config_with_db_path, error};
+use substrate_client::runtime_api::RuntimeVersion;
+use substrate_executor::NativeExecutor;
+use node_template_runtime::{self, Address, Hash, Nonce, UncheckedExtrinsic, opaque::{Block, Header}};
+use crate::replay::Execution;
+use crate::service;
+
+/// Development accounts whose transactions are signed again.
+const DEV_ACCOUNTS: &[&str] = &["Alice", "Bob", "Charlie", "Dave", "Eve", "Ferdie"];
+
+/// The `try-upgrade` command.
+#[derive(Debug, StructOpt, Clone)]
+pub struct TryUpgradeCmd {
+	/// Compact Wasm runtime to try, as it would be passed to `Consensus::set_code`.
+	#[structopt(long = "wasm", value_name = "PATH", parse(from_os_str))]
+	pub wasm: PathBuf,
+
+	/// Number of blocks to build with the new runtime on top of the best block, replaying the
+	/// signed extrinsics of as many recent blocks.
+	#[structopt(long = "blocks", value_name = "COUNT", default_value = "10")]
+	pub blocks: u64,
+
+	/// JSON array of hex encoded extrinsics to apply in the first block, as returned by the
+	/// `author_pendingExtrinsics` RPC method of a running node.
+	#[structopt(long = "extrinsics", value_name = "PATH", parse(from_os_str))]
+	pub extrinsics: Option<PathBuf>,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub shared_params: SharedParams,
+}
+
+impl TryUpgradeCmd {
+	/// Check the new runtime's version and build blocks with it on top of the best block,
+	/// failing on the first panic or if every applied extrinsic is rejected.
+	pub fn run(self, version: &VersionInfo) -> error::Result<()> {
+		let config = create_config_with_db_path::<service::Factory, _>(
+			crate::cli::load_spec,
+			&self.shared_params,
+			version,
+		)?;
+		let client = substrate_service::new_client::<service::Factory>(&config)
+			.map_err(|e| format!("{:?}", e))?;
+		let executor = NativeExecutor::<service::Executor>::new(config.default_heap_pages);
+
+		let info = client.info()?.chain;
+		let best_number: u64 = info.best_number.as_();
+		let at = BlockId::Hash(info.best_hash);
+		let current = client.runtime_version_at(&at)?;
+		let code = fs::read(&self.wasm).map_err(|e| format!("Unable to read {:?}: {}", self.wasm, e))?;
+		let mut pending = match self.extrinsics {
+			Some(ref path) => read_extrinsics(path)?,
+			None => Vec::new(),
+		};
+
+		let state = client.state_at(&at)?;
+		let mut execution = Execution::new(&state, &executor).set_code(code);
+
+		let new = RuntimeVersion::decode(&mut &execution.call("Core_version", &[])?[..])
+			.ok_or("Unable to decode the version of the new runtime")?;
+		check_version(&current, &new)?;
+		info!(
+			"Upgrading {} from spec version {} to {}",
+			new.spec_name,
+			current.spec_version,
+			new.spec_version,
+		);
+
+		let slot_duration = SlotDuration::get_or_compute(&*client)?.get();
+		let mut timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+			.map_err(|e| format!("{}", e))?
+			.as_secs();
+		let mut parent = client.header(&at)?.ok_or("Header of the best block is missing")?;
+		let dev_accounts: Vec<sr25519::Pair> = DEV_ACCOUNTS.iter()
+			.map(|name| sr25519::Pair::from_string(&format!("//{}", name), None).expect("static values are valid; qed"))
+			.collect();
+		let (mut applied_total, mut rejected_total, mut skipped_total) = (0, 0, 0);
+
+		for i in 1..=self.blocks {
+			let number = best_number + i;
+			// Blocks before the first one, on chains shorter than `--blocks`, have nothing to replay.
+			let source = (best_number + i).checked_sub(self.blocks).filter(|n| *n > 0);
+			let replayed = match source {
+				Some(source) => signed_extrinsics(client.block(&BlockId::Number(As::sa(source)))?.map(|b| b.block))?,
+				None => Vec::new(),
+			};
+
+			let header = Header::new(number, Default::default(), Default::default(), parent.hash(), Default::default());
+			execution.initialize_block(&header)
+				.map_err(|e| format!("Block #{} panicked during initialization: {}", number, e))?;
+
+			let mut inherent_data = InherentData::new();
+			inherent_data.put_data(timestamp::INHERENT_IDENTIFIER, &timestamp)
+				.map_err(|e| format!("{:?}", e))?;
+			let inherents = execution.call("BlockBuilder_inherent_extrinsics", &inherent_data.encode())?;
+			let inherents = Vec::<<Block as BlockT>::Extrinsic>::decode(&mut &inherents[..])
+				.ok_or("Unable to decode inherent extrinsics")?;
+			for inherent in &inherents {
+				if !execution.apply_extrinsic(inherent)? {
+					return Err(format!("Inherent extrinsic of block #{} failed to apply", number).into());
+				}
+			}
+
+			let (mut applied, mut rejected, mut skipped) = (0, 0, 0);
+			// Pending extrinsics all go into the first block.
+			let to_apply = mem::replace(&mut pending, Vec::new()).into_iter()
+				.map(|extrinsic| (extrinsic, false))
+				.chain(replayed.into_iter().map(|extrinsic| (extrinsic, true)));
+			for (extrinsic, replayed) in to_apply {
+				let extrinsic = match replayed {
+					true => match resign(&mut execution, &dev_accounts, info.genesis_hash, extrinsic) {
+						Some(extrinsic) => extrinsic,
+						None => {
+							skipped += 1;
+							continue;
+						},
+					},
+					false => extrinsic,
+				};
+				applied += 1;
+				if !execution.apply_extrinsic(&extrinsic)
+					.map_err(|e| format!("Extrinsic in block #{} panicked: {}", number, e))?
+				{
+					rejected += 1;
+				}
+			}
+
+			parent = execution.finalize_block()
+				.map_err(|e| format!("Block #{} panicked during finalization: {}", number, e))?;
+			if rejected > 0 {
+				warn!("#{}: {} of {} extrinsics were rejected", number, rejected, applied);
+			}
+			info!("#{}: built with the new runtime, state root {}", number, parent.state_root());
+			applied_total += applied;
+			rejected_total += rejected;
+			skipped_total += skipped;
+			timestamp += slot_duration;
+		}
+
+		if skipped_total > 0 {
+			warn!("{} recent extrinsics of other accounts than the development ones were skipped", skipped_total);
+		}
+		if applied_total > 0 && rejected_total == applied_total {
+			return Err(format!("All {} applied extrinsics were rejected by the new runtime", applied_total).into());
+		}
+		if applied_total == 0 {
+			warn!("No extrinsics to apply, only inherents were applied");
+		}
+
+		info!("Runtime upgrade to spec version {} looks safe", new.spec_version);
+		Ok(())
+	}
+}
+
+/// The new runtime must be an upgrade of this chain's runtime.
+fn check_version(current: &RuntimeVersion, new: &RuntimeVersion) -> Result<(), String> {
+	if new.spec_name != node_template_runtime::VERSION.spec_name || new.spec_name != current.spec_name {
+		return Err(format!(
+			"spec_name of the new runtime is `{}`, expected `{}`",
+			new.spec_name,
+			current.spec_name,
+		));
+	}
+	if new.spec_version <= current.spec_version {
+		return Err(format!(
+			"spec_version of the new runtime is {}, it must be greater than the current {}",
+			new.spec_version,
+			current.spec_version,
+		));
+	}
+	Ok(())
+}
+
+/// The signed extrinsics of `block`. Inherents are skipped since every block brings its own.
+fn signed_extrinsics(block: Option<Block>) -> Result<Vec<UncheckedExtrinsic>, String> {
+	let mut extrinsics = Vec::new();
+	for extrinsic in block.map(|b| b.extrinsics).unwrap_or_default() {
+		let extrinsic = UncheckedExtrinsic::decode(&mut &extrinsic.encode()[..])
+			.ok_or("Unable to decode extrinsic of a recent block")?;
+		if extrinsic.signature.is_some() {
+			extrinsics.push(extrinsic);
+		}
+	}
+	Ok(extrinsics)
+}
+
+/// `extrinsic` signed again by the development account that signed it, with the account's
+/// nonce in the block being built, or `None` if another account signed it.
+fn resign<S: state_machine::Backend<Blake2Hasher>>(
+	execution: &mut Execution<S>,
+	dev_accounts: &[sr25519::Pair],
+	genesis_hash: Hash,
+	extrinsic: UncheckedExtrinsic,
+) -> Option<UncheckedExtrinsic> {
+	let who = match extrinsic.signature {
+		Some((Address::Id(ref who), _, _, _)) => who.clone(),
+		_ => return None,
+	};
+	let pair = dev_accounts.iter().find(|pair| pair.public() == who)?;
+	let nonce = execution.call("AccountApi_account_nonce", &who.encode()).ok()?;
+	let nonce = Nonce::decode(&mut &nonce[..])?;
+	Some(node_template_signer::sign(extrinsic.function, pair, nonce, Era::Immortal, genesis_hash))
+}
+
+/// The extrinsics of the JSON array of hex strings at `path`.
+fn read_extrinsics(path: &Path) -> Result<Vec<UncheckedExtrinsic>, String> {
+	let json = fs::read(path).map_err(|e| format!("Unable to read {:?}: {}", path, e))?;
+	let extrinsics: Vec<Bytes> = serde_json::from_slice(&json)
+		.map_err(|e| format!("{:?} is not a JSON array of hex strings: {}", path, e))?;
+	extrinsics.iter()
+		.enumerate()
+		.map(|(i, bytes)| UncheckedExtrinsic::decode(&mut &bytes.0[..])
+			.ok_or_else(|| format!("Unable to decode extrinsic {} of {:?}", i, path)))
+		.collect()
+}
@@ -0,0 +1,113 @@
+//! Runs a full node and a light client of the development chain, and checks the light client
+//! follows the full node and serves the same state.
+//!
+//! The test starts the `node-template` binary, which cargo builds before integration tests,
+//! and waits for blocks to be authored.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use node_template_client::{Client, balances, template};
+use node_template_signer::{Era, sign};
+use primitives::{Pair, sr25519};
+use serde_json::{json, Value};
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A node process, killed along with its database when dropped.
+struct Node {
+	process: Child,
+	base_path: PathBuf,
+}
+
+impl Node {
+	fn start(name: &str, args: &[&str]) -> Node {
+		let base_path = env::temp_dir().join(format!("node-template-{}-{}", name, std::process::id()));
+		let _ = fs::remove_dir_all(&base_path);
+		let process = Command::new(env!("CARGO_BIN_EXE_node-template"))
+			.args(&["--dev", "--base-path"])
+			.arg(&base_path)
+			.args(args)
+			.stdout(Stdio::null())
+			.stderr(Stdio::null())
+			.spawn()
+			.expect("Unable to start node-template");
+		Node { process, base_path }
+	}
+}
+
+impl Drop for Node {
+	fn drop(&mut self) {
+		let _ = self.process.kill();
+		let _ = self.process.wait();
+		let _ = fs::remove_dir_all(&self.base_path);
+	}
+}
+
+/// Retry `f` until it returns `Some`, panicking after `TIMEOUT`.
+fn wait_for<T>(what: &str, mut f: impl FnMut() -> Option<T>) -> T {
+	let start = Instant::now();
+	loop {
+		if let Some(value) = f() {
+			return value;
+		}
+		if start.elapsed() > TIMEOUT {
+			panic!("Timed out waiting for {}", what);
+		}
+		thread::sleep(Duration::from_millis(500));
+	}
+}
+
+fn best_number(client: &Client) -> Option<u64> {
+	client.rpc().best_header().ok().map(|header| header.number)
+}
+
+#[test]
+fn light_client_follows_full_node() {
+	let _full = Node::start("full", &[
+		"--port", "31333", "--rpc-port", "19933", "--ws-port", "19944", "--ext-ws-port", "19946",
+	]);
+	let full = wait_for("the full node", || Client::connect("ws://127.0.0.1:19944").ok());
+	let network = full.rpc().request("system_networkState", json!([])).unwrap();
+	let peer_id = network["peerId"].as_str().expect("the network state has a peer id").to_string();
+
+	let bootnode = format!("/ip4/127.0.0.1/tcp/31333/p2p/{}", peer_id);
+	let _light = Node::start("light", &[
+		"--light", "--bootnodes", &bootnode,
+		"--port", "31334", "--rpc-port", "19934", "--ws-port", "19945", "--ext-ws-port", "19947",
+	]);
+	let light = wait_for("the light client", || Client::connect("ws://127.0.0.1:19945").ok());
+
+	// Headers are synced, with their seals checked against the authority set.
+	wait_for("the light client to import blocks", || best_number(&light).filter(|number| *number >= 2));
+	let authorities = |url: &str| {
+		let ext = node_template_signer::Client::connect(url).unwrap();
+		ext.request("consensus_authorities", json!([])).unwrap()
+	};
+	assert_eq!(authorities("ws://127.0.0.1:19946"), authorities("ws://127.0.0.1:19947"));
+
+	// Storage is read from the full node with a proof, at a block known to both.
+	let alice = sr25519::Pair::from_string("//Alice", None).unwrap();
+	let hash = light.rpc().best_header().unwrap().parent_hash;
+	assert_eq!(
+		balances::storage::free_balance(&light, &alice.public(), Some(hash)).unwrap(),
+		balances::storage::free_balance(&full, &alice.public(), Some(hash)).unwrap(),
+	);
+
+	// A transaction submitted to the full node shows up in the runtime API served by the light
+	// client through remote calls.
+	let nonce = full.rpc().account_nonce(&alice.public()).unwrap();
+	let genesis = full.rpc().genesis_hash().unwrap();
+	let tx = sign(template::calls::do_something(42), &alice, nonce, Era::Immortal, genesis);
+	full.rpc().submit(&tx).unwrap();
+
+	let ext = node_template_signer::Client::connect("ws://127.0.0.1:19947").unwrap();
+	let something = wait_for("the light client to see the stored value", || {
+		ext.request("template_something", json!([])).ok().filter(|value| !value.is_null())
+	});
+	assert_eq!(something["value"], Value::from(42));
+	assert_eq!(template::storage::something(&light, None).unwrap(), Some(42));
+}